@@ -9,11 +9,14 @@ fn main() {
     let dest = Path::new(&out_dir);
 
     let mut file = BufWriter::new(File::create(&dest.join("bindings.rs")).unwrap());
+    // 3.3 for glVertexAttribDivisor and the timer queries, contexts
+    // are still 3.2 so they fall back to the extensions' versions.
+    // glClipControl is 4.5 so only the extension is loaded
     Registry::new(Api::Gl,
-                  (3, 2),
+                  (3, 3),
                   Profile::Core,
                   Fallbacks::All,
-                  [])
+                  ["GL_KHR_debug", "GL_ARB_debug_output", "GL_ARB_instanced_arrays", "GL_ARB_timer_query", "GL_ARB_clip_control"])
         .write_bindings(GlobalGenerator, &mut file)
         .unwrap();
 }
@@ -0,0 +1,86 @@
+//! Context creation shared by the tool binaries.
+
+// Each binary only uses part of this
+#![allow(dead_code)]
+
+use std::process;
+
+/// Whichever context was created, kept so it isn't destroyed early.
+pub enum Context {
+    #[cfg(feature = "headless")]
+    Headless(invisible::headless::Context),
+    #[cfg(feature = "sdl")]
+    Window {
+        _sdl: sdl2::Sdl,
+        window: sdl2::video::Window,
+        _gl_context: sdl2::video::GLContext,
+    },
+}
+
+impl Context {
+    /// Creates a window like the game does, or with `headless` an
+    /// offscreen context (requires the `headless` feature). Exits if
+    /// the context can't be created.
+    pub fn create(headless: bool, title: &str, width: u32, height: u32, visible: bool) -> Context {
+        if headless {
+            #[cfg(feature = "headless")]
+            {
+                use invisible::gl;
+                use std::env;
+
+                let debug = env::var_os("STEVEN_GL_DEBUG").is_some();
+                let ctx = if debug {
+                    invisible::headless::Context::new_debug(width, height)
+                } else {
+                    invisible::headless::Context::new(width, height)
+                };
+                let ctx = ctx.unwrap_or_else(|err| {
+                    println!("Failed to create headless context: {}", err);
+                    process::exit(1);
+                });
+                if env::var_os("STEVEN_GL_CHECK").is_some() {
+                    gl::set_debug_checks(true);
+                }
+                if debug {
+                    gl::set_debug_callback(gl::print_debug_message);
+                }
+                return Context::Headless(ctx);
+            }
+            #[cfg(not(feature = "headless"))]
+            {
+                println!("Built without the headless feature");
+                process::exit(1);
+            }
+        }
+        #[cfg(feature = "sdl")]
+        {
+            let sdl = sdl2::init().unwrap();
+            let video = sdl.video().unwrap();
+            let (mut window, gl_context) = invisible::window::create(&video, title, width, height);
+            if !visible {
+                window.hide();
+            }
+            Context::Window {
+                _sdl: sdl,
+                window,
+                _gl_context: gl_context,
+            }
+        }
+        #[cfg(not(feature = "sdl"))]
+        {
+            let _ = (title, visible);
+            println!("Built without the sdl feature, use --headless");
+            process::exit(1);
+        }
+    }
+
+    /// Presents the frame, does nothing for headless contexts.
+    pub fn swap(&self) {
+        match *self {
+            #[cfg(feature = "headless")]
+            Context::Headless(_) => {},
+            #[cfg(feature = "sdl")]
+            Context::Window { ref window, .. } => window.gl_swap_window(),
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! Prints a report about the OpenGL driver and whether the renderer's
+//! shaders work with it, for attaching to bug reports.
+//!
+//! Creates a windowed context like the game does, or with `--headless`
+//! an offscreen one (requires the `headless` feature).
+
+// Nothing runs without a way to create a context
+#![cfg_attr(not(any(feature = "sdl", feature = "headless")), allow(unused, unreachable_code))]
+
+use invisible::gl;
+use invisible::render::{self, glsl, shaders};
+use std::env;
+use std::process;
+
+mod common;
+
+const LIMITS: &[(&str, gl::IntegerParameter)] = &[
+    ("GL_MAX_SAMPLES", gl::MAX_SAMPLES),
+    ("GL_MAX_COLOR_TEXTURE_SAMPLES", gl::MAX_COLOR_TEXTURE_SAMPLES),
+    ("GL_MAX_DEPTH_TEXTURE_SAMPLES", gl::MAX_DEPTH_TEXTURE_SAMPLES),
+    ("GL_MAX_TEXTURE_SIZE", gl::MAX_TEXTURE_SIZE),
+    ("GL_MAX_ARRAY_TEXTURE_LAYERS", gl::MAX_ARRAY_TEXTURE_LAYERS),
+    ("GL_MAX_DRAW_BUFFERS", gl::MAX_DRAW_BUFFERS),
+    ("GL_MAX_COLOR_ATTACHMENTS", gl::MAX_COLOR_ATTACHMENTS),
+    ("GL_MAX_VERTEX_ATTRIBS", gl::MAX_VERTEX_ATTRIBS),
+    ("GL_MAX_VERTEX_UNIFORM_COMPONENTS", gl::MAX_VERTEX_UNIFORM_COMPONENTS),
+    ("GL_MAX_FRAGMENT_UNIFORM_COMPONENTS", gl::MAX_FRAGMENT_UNIFORM_COMPONENTS),
+    ("GL_MAX_COMBINED_TEXTURE_IMAGE_UNITS", gl::MAX_COMBINED_TEXTURE_IMAGE_UNITS),
+];
+
+fn main() {
+    let headless = env::args().skip(1).any(|v| v == "--headless");
+    // Kept alive until the report is done
+    let _context = common::Context::create(headless, "glinfo", 64, 64, false);
+
+    let mut failed = false;
+
+    println!("== Context");
+    println!("Vendor: {}", gl::get_string(gl::VENDOR));
+    println!("Renderer: {}", gl::get_string(gl::RENDERER));
+    println!("Version: {}", gl::get_string(gl::VERSION));
+    println!("GLSL version: {}", gl::get_string(gl::SHADING_LANGUAGE_VERSION));
+    let flags = gl::get_integer(gl::CONTEXT_FLAGS);
+    println!("Context flags: 0x{:X}{}", flags,
+        if flags & gl::CONTEXT_FLAG_DEBUG != 0 { " (debug)" } else { "" });
+    let quirks: Vec<String> = gl::quirks::active().iter().map(|v| v.to_string()).collect();
+    println!("Quirks: {}", if quirks.is_empty() { "none".to_owned() } else { quirks.join(", ") });
+    let missing: Vec<String> = gl::caps::ALL.iter().filter(|v| !gl::caps::has(**v)).map(|v| v.to_string()).collect();
+    println!("Missing capabilities: {}", if missing.is_empty() { "none".to_owned() } else { missing.join(", ") });
+
+    println!();
+    println!("== Limits");
+    for &(name, param) in LIMITS {
+        println!("{}: {}", name, gl::get_integer(param));
+    }
+
+    println!();
+    let mut extensions = gl::extensions();
+    extensions.sort();
+    println!("== Extensions ({})", extensions.len());
+    for ext in &extensions {
+        println!("{}", ext);
+    }
+
+    println!();
+    println!("== Shaders");
+    let mut reg = glsl::Registry::new();
+    shaders::add_shaders(&mut reg);
+    for name in reg.names() {
+        let ty = if name.ends_with("_vertex") {
+            gl::VERTEX_SHADER
+        } else if name.ends_with("_frag") {
+            gl::FRAGMENT_SHADER
+        } else if name.ends_with("_geom") {
+            gl::GEOMETRY_SHADER
+        } else {
+            // Only used through #include
+            continue;
+        };
+        match shaders::compile_shader(ty, &reg.get(name)) {
+            Ok(_) => println!("{}: ok", name),
+            Err(err) => {
+                failed = true;
+                println!("{}: FAILED", name);
+                println!("{}", err);
+            },
+        }
+    }
+
+    println!();
+    println!("== Renderer");
+    match render::Renderer::new(64, 64) {
+        Ok(_) => println!("ok"),
+        Err(err) => {
+            failed = true;
+            println!("FAILED");
+            println!("{}", err);
+        },
+    }
+
+    if let Err(errors) = gl::gl_errors() {
+        failed = true;
+        println!();
+        for err in errors {
+            println!("glGetError = {}", err);
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+}
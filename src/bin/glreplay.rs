@@ -0,0 +1,76 @@
+//! Replays a gl trace recorded with `STEVEN_GL_TRACE`.
+//!
+//! Usage: `glreplay <trace> [--headless] [--screenshot <path>]`
+//!
+//! `--screenshot` saves the last frame of the trace.
+
+// Nothing runs without a way to create a context
+#![cfg_attr(not(any(feature = "sdl", feature = "headless")), allow(unused, unreachable_code))]
+
+use invisible::gl;
+use invisible::render::screenshot;
+use std::env;
+use std::fs::File;
+use std::io::BufReader;
+use std::process;
+
+mod common;
+
+fn main() {
+    let mut trace = None;
+    let mut headless = false;
+    let mut screenshot_path = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--headless" => headless = true,
+            "--screenshot" => screenshot_path = args.next(),
+            _ if trace.is_none() => trace = Some(arg),
+            _ => usage(),
+        }
+    }
+    let trace = trace.unwrap_or_else(|| usage());
+
+    let file = File::open(&trace).unwrap_or_else(|err| {
+        println!("Failed to open {}: {}", trace, err);
+        process::exit(1);
+    });
+    let mut replayer = gl::trace::Replayer::new(BufReader::new(file)).unwrap_or_else(|err| {
+        println!("Failed to read {}: {}", trace, err);
+        process::exit(1);
+    });
+    let (width, height) = replayer.size();
+    let context = common::Context::create(headless, "glreplay", width, height, true);
+
+    let mut frames = 0;
+    let mut last = None;
+    loop {
+        match replayer.next_frame() {
+            Ok(true) => {},
+            Ok(false) => break,
+            Err(err) => {
+                println!("Failed to replay frame {} (call {}): {}", frames, replayer.calls(), err);
+                process::exit(1);
+            },
+        }
+        frames += 1;
+        if screenshot_path.is_some() {
+            last = Some(screenshot::read_default_framebuffer(width, height));
+        }
+        context.swap();
+    }
+    println!("Replayed {} frames, {} calls", frames, replayer.calls());
+
+    if let Some(path) = screenshot_path {
+        match last.map(|v| v.save(&path)) {
+            Some(Ok(())) => println!("Saved {}", path),
+            Some(Err(err)) => println!("Failed to save {}: {}", path, err),
+            None => println!("Trace has no frames"),
+        }
+    }
+}
+
+fn usage() -> ! {
+    println!("Usage: glreplay <trace> [--headless] [--screenshot <path>]");
+    process::exit(2);
+}
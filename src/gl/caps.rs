@@ -0,0 +1,114 @@
+//! Optional features of the current context that the renderer has
+//! fallbacks for.
+//!
+//! Capabilities are detected at `gl::init` from the version and
+//! extensions. Unlike quirks they aren't driver bugs, the fallbacks are
+//! only needed because a feature is missing. Capabilities can be
+//! disabled (e.g. to test a fallback on a driver that has the feature)
+//! with `disable` or the `STEVEN_GL_DISABLE_CAPS` environment
+//! variable, which takes a comma separated list of capability names
+//! (or `all`).
+
+use std::env;
+use std::fmt;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// `Cap` is an optional feature of the context.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cap {
+    /// GL 3.3 or `GL_ARB_instanced_arrays`: attributes can be per
+    /// instance. Without it instances are drawn one at a time.
+    InstancedArrays,
+    /// GL 3.3 or `GL_ARB_timer_query`: gpu time can be measured.
+    TimerQuery,
+    /// GL 4.5 or `GL_ARB_clip_control`: clip space depth can be mapped
+    /// onto 0 to 1. Without it reverse-Z projections fall back to the
+    /// normal depth range.
+    ClipControl,
+}
+
+pub const ALL: [Cap; 3] = [
+    Cap::InstancedArrays,
+    Cap::TimerQuery,
+    Cap::ClipControl,
+];
+
+impl Cap {
+    fn bit(self) -> u32 {
+        1 << (self as u32)
+    }
+
+    /// The name used for the capability in `STEVEN_GL_DISABLE_CAPS`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Cap::InstancedArrays => "instanced_arrays",
+            Cap::TimerQuery => "timer_query",
+            Cap::ClipControl => "clip_control",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Cap> {
+        ALL.iter().cloned().find(|v| v.name() == name)
+    }
+}
+
+impl fmt::Display for Cap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+static DETECTED: AtomicU32 = AtomicU32::new(0);
+static DISABLED: AtomicU32 = AtomicU32::new(0);
+
+/// Detects the capabilities of the current context. Called by
+/// `gl::init`.
+pub(super) fn init() {
+    let version = super::version();
+    let detected = [
+        (Cap::InstancedArrays, (3, 3), "GL_ARB_instanced_arrays"),
+        (Cap::TimerQuery, (3, 3), "GL_ARB_timer_query"),
+        (Cap::ClipControl, (4, 5), "GL_ARB_clip_control"),
+    ].iter()
+        .filter(|(_, core, ext)| version >= *core || super::has_extension(ext))
+        .fold(0, |bits, (cap, _, _)| bits | cap.bit());
+    DETECTED.store(detected, Ordering::Relaxed);
+
+    if let Ok(names) = env::var("STEVEN_GL_DISABLE_CAPS") {
+        for name in names.split(',').map(str::trim).filter(|v| !v.is_empty()) {
+            if name == "all" {
+                ALL.iter().for_each(|c| disable(*c));
+            } else if let Some(c) = Cap::from_name(name) {
+                disable(c);
+            } else {
+                println!("Unknown gl capability {}", name);
+            }
+        }
+    }
+    for c in &ALL {
+        if !has(*c) {
+            println!("Using fallback for missing gl capability {}", c);
+        }
+    }
+}
+
+/// Returns whether the capability is available and not disabled.
+pub fn has(cap: Cap) -> bool {
+    (DETECTED.load(Ordering::Relaxed) & !DISABLED.load(Ordering::Relaxed)) & cap.bit() != 0
+}
+
+/// Uses the fallback for the capability even if it is available.
+/// Disabled capabilities stay disabled when gl is re-initialized.
+pub fn disable(cap: Cap) {
+    DISABLED.fetch_or(cap.bit(), Ordering::Relaxed);
+}
+
+/// Stops disabling the capability, it is used again if available.
+pub fn reenable(cap: Cap) {
+    DISABLED.fetch_and(!cap.bit(), Ordering::Relaxed);
+}
+
+/// Returns the capabilities currently in use.
+pub fn active() -> Vec<Cap> {
+    ALL.iter().cloned().filter(|c| has(*c)).collect()
+}
@@ -0,0 +1,188 @@
+use super::gl;
+use std::ffi;
+use std::fmt;
+use std::ptr;
+use std::slice;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// `DebugSource` is where a debug message came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugSource {
+    Api,
+    WindowSystem,
+    ShaderCompiler,
+    ThirdParty,
+    Application,
+    Other,
+}
+
+impl DebugSource {
+    fn from_raw(v: u32) -> DebugSource {
+        match v {
+            gl::DEBUG_SOURCE_API => DebugSource::Api,
+            gl::DEBUG_SOURCE_WINDOW_SYSTEM => DebugSource::WindowSystem,
+            gl::DEBUG_SOURCE_SHADER_COMPILER => DebugSource::ShaderCompiler,
+            gl::DEBUG_SOURCE_THIRD_PARTY => DebugSource::ThirdParty,
+            gl::DEBUG_SOURCE_APPLICATION => DebugSource::Application,
+            _ => DebugSource::Other,
+        }
+    }
+}
+
+/// `DebugType` is the kind of problem a debug message is about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugType {
+    Error,
+    DeprecatedBehavior,
+    UndefinedBehavior,
+    Portability,
+    Performance,
+    Marker,
+    PushGroup,
+    PopGroup,
+    Other,
+}
+
+impl DebugType {
+    fn from_raw(v: u32) -> DebugType {
+        match v {
+            gl::DEBUG_TYPE_ERROR => DebugType::Error,
+            gl::DEBUG_TYPE_DEPRECATED_BEHAVIOR => DebugType::DeprecatedBehavior,
+            gl::DEBUG_TYPE_UNDEFINED_BEHAVIOR => DebugType::UndefinedBehavior,
+            gl::DEBUG_TYPE_PORTABILITY => DebugType::Portability,
+            gl::DEBUG_TYPE_PERFORMANCE => DebugType::Performance,
+            gl::DEBUG_TYPE_MARKER => DebugType::Marker,
+            gl::DEBUG_TYPE_PUSH_GROUP => DebugType::PushGroup,
+            gl::DEBUG_TYPE_POP_GROUP => DebugType::PopGroup,
+            _ => DebugType::Other,
+        }
+    }
+}
+
+/// `DebugSeverity` is how important a debug message is, ordered from
+/// least to most severe.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DebugSeverity {
+    Notification,
+    Low,
+    Medium,
+    High,
+}
+
+impl DebugSeverity {
+    fn from_raw(v: u32) -> DebugSeverity {
+        match v {
+            gl::DEBUG_SEVERITY_HIGH => DebugSeverity::High,
+            gl::DEBUG_SEVERITY_MEDIUM => DebugSeverity::Medium,
+            gl::DEBUG_SEVERITY_LOW => DebugSeverity::Low,
+            _ => DebugSeverity::Notification,
+        }
+    }
+}
+
+/// A message reported by the driver through `KHR_debug` or
+/// `ARB_debug_output`.
+pub struct DebugMessage<'a> {
+    pub source: DebugSource,
+    pub ty: DebugType,
+    pub id: u32,
+    pub severity: DebugSeverity,
+    pub message: &'a str,
+}
+
+impl<'a> fmt::Display for DebugMessage<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{:?}] {:?} {:?} {}: {}", self.severity, self.source, self.ty, self.id, self.message)
+    }
+}
+
+/// Prints the message, useful as a default callback.
+pub fn print_debug_message(msg: &DebugMessage) {
+    println!("gl debug: {}", msg);
+}
+
+type Callback = Box<dyn Fn(&DebugMessage) + Send + Sync>;
+
+// The installed callback. Boxed twice so gl can be handed a thin
+// pointer to it.
+static CALLBACK: Mutex<Option<Box<Callback>>> = Mutex::new(None);
+static HAS_KHR_DEBUG: AtomicBool = AtomicBool::new(false);
+
+extern "system" fn debug_callback(
+    source: u32, ty: u32, id: u32, severity: u32,
+    length: i32, message: *const gl::types::GLchar,
+    user: *mut ffi::c_void,
+) {
+    let message = unsafe {
+        if length >= 0 {
+            String::from_utf8_lossy(slice::from_raw_parts(message as *const u8, length as usize))
+        } else {
+            ffi::CStr::from_ptr(message).to_string_lossy()
+        }
+    };
+    let msg = DebugMessage {
+        source: DebugSource::from_raw(source),
+        ty: DebugType::from_raw(ty),
+        id,
+        severity: DebugSeverity::from_raw(severity),
+        message: message.trim_end(),
+    };
+    let callback = unsafe { &*(user as *const Callback) };
+    callback(&msg);
+}
+
+/// Routes debug messages from the driver to the passed callback,
+/// replacing any previous one. Messages are reported synchronously
+/// so the callback runs inside the gl call that caused it.
+///
+/// Returns false if the context supports neither `KHR_debug` nor
+/// `ARB_debug_output`. Most drivers only report messages for contexts
+/// created with the debug flag.
+pub fn set_debug_callback<F>(callback: F) -> bool
+    where F: Fn(&DebugMessage) + Send + Sync + 'static {
+    let khr = HAS_KHR_DEBUG.load(Ordering::Relaxed);
+    let arb = super::has_extension("GL_ARB_debug_output");
+    if !khr && !arb {
+        return false;
+    }
+
+    let callback: Box<Callback> = Box::new(Box::new(callback));
+    let user = &*callback as *const Callback as *const ffi::c_void;
+    unsafe {
+        if khr {
+            gl::DebugMessageCallback(debug_callback, user);
+            gl::DebugMessageControl(gl::DONT_CARE, gl::DONT_CARE, gl::DONT_CARE, 0, ptr::null(), gl::TRUE);
+            gl::Enable(gl::DEBUG_OUTPUT);
+        } else {
+            gl::DebugMessageCallbackARB(debug_callback, user);
+            gl::DebugMessageControlARB(gl::DONT_CARE, gl::DONT_CARE, gl::DONT_CARE, 0, ptr::null(), gl::TRUE);
+        }
+        gl::Enable(gl::DEBUG_OUTPUT_SYNCHRONOUS);
+    }
+    // Replaced after installing the new one so gl never sees a
+    // freed callback.
+    *CALLBACK.lock().unwrap_or_else(|v| v.into_inner()) = Some(callback);
+    true
+}
+
+pub(super) fn init() {
+    let khr = super::version() >= (4, 3) || super::has_extension("GL_KHR_debug");
+    HAS_KHR_DEBUG.store(khr, Ordering::Relaxed);
+}
+
+/// Attaches a name to the object, shown in driver debug messages and
+/// tools like apitrace/RenderDoc. Does nothing without `KHR_debug`.
+///
+/// Some drivers only accept labels once the object has been bound at
+/// least once.
+#[track_caller]
+pub(super) fn object_label(identifier: u32, name: u32, label: &str) {
+    if !HAS_KHR_DEBUG.load(Ordering::Relaxed) {
+        return;
+    }
+    let _check = super::error::debug_check("glObjectLabel");
+    unsafe {
+        gl::ObjectLabel(identifier, name, label.len() as i32, label.as_ptr() as *const _);
+    }
+}
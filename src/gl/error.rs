@@ -0,0 +1,213 @@
+use super::gl;
+use std::error;
+use std::fmt;
+use std::panic::Location;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// `GlError` is an error reported by `glGetError`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GlError {
+    InvalidEnum,
+    InvalidValue,
+    InvalidOperation,
+    InvalidFramebufferOperation,
+    OutOfMemory,
+    Unknown(u32),
+}
+
+impl GlError {
+    fn from_raw(err: u32) -> Option<GlError> {
+        Some(match err {
+            gl::NO_ERROR => return None,
+            gl::INVALID_ENUM => GlError::InvalidEnum,
+            gl::INVALID_VALUE => GlError::InvalidValue,
+            gl::INVALID_OPERATION => GlError::InvalidOperation,
+            gl::INVALID_FRAMEBUFFER_OPERATION => GlError::InvalidFramebufferOperation,
+            gl::OUT_OF_MEMORY => GlError::OutOfMemory,
+            err => GlError::Unknown(err),
+        })
+    }
+
+    /// Returns the raw value of the error.
+    pub fn raw(self) -> u32 {
+        match self {
+            GlError::InvalidEnum => gl::INVALID_ENUM,
+            GlError::InvalidValue => gl::INVALID_VALUE,
+            GlError::InvalidOperation => gl::INVALID_OPERATION,
+            GlError::InvalidFramebufferOperation => gl::INVALID_FRAMEBUFFER_OPERATION,
+            GlError::OutOfMemory => gl::OUT_OF_MEMORY,
+            GlError::Unknown(err) => err,
+        }
+    }
+
+    /// Returns the name of the error as used by the gl spec.
+    pub fn name(self) -> &'static str {
+        match self {
+            GlError::InvalidEnum => "GL_INVALID_ENUM",
+            GlError::InvalidValue => "GL_INVALID_VALUE",
+            GlError::InvalidOperation => "GL_INVALID_OPERATION",
+            GlError::InvalidFramebufferOperation => "GL_INVALID_FRAMEBUFFER_OPERATION",
+            GlError::OutOfMemory => "GL_OUT_OF_MEMORY",
+            GlError::Unknown(_) => "unknown",
+        }
+    }
+}
+
+impl fmt::Display for GlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (0x{:04X})", self.name(), self.raw())
+    }
+}
+
+impl error::Error for GlError {}
+
+/// Returns the oldest error recorded by gl since the last call, if any.
+pub fn get_error() -> Option<GlError> {
+    GlError::from_raw(unsafe { gl::GetError() })
+}
+
+/// Returns every error recorded by gl since the last call,
+/// clearing them.
+pub fn gl_errors() -> Result<(), Vec<GlError>> {
+    let mut errors = vec![];
+    while let Some(err) = get_error() {
+        errors.push(err);
+        // GetError keeps returning OUT_OF_MEMORY/CONTEXT_LOST on
+        // some drivers instead of clearing the flag.
+        if errors.len() >= 32 {
+            break;
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+pub fn check_gl_error() {
+    if let Err(errors) = gl_errors() {
+        for err in errors {
+            println!("glGetError = {}", err);
+        }
+    }
+}
+
+/// `FramebufferError` is the reason a framebuffer isn't complete.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FramebufferError {
+    Undefined,
+    IncompleteAttachment,
+    IncompleteMissingAttachment,
+    IncompleteDrawBuffer,
+    IncompleteReadBuffer,
+    Unsupported,
+    IncompleteMultisample,
+    IncompleteLayerTargets,
+    Unknown(u32),
+}
+
+impl FramebufferError {
+    fn from_raw(status: u32) -> Option<FramebufferError> {
+        Some(match status {
+            gl::FRAMEBUFFER_COMPLETE => return None,
+            gl::FRAMEBUFFER_UNDEFINED => FramebufferError::Undefined,
+            gl::FRAMEBUFFER_INCOMPLETE_ATTACHMENT => FramebufferError::IncompleteAttachment,
+            gl::FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT => FramebufferError::IncompleteMissingAttachment,
+            gl::FRAMEBUFFER_INCOMPLETE_DRAW_BUFFER => FramebufferError::IncompleteDrawBuffer,
+            gl::FRAMEBUFFER_INCOMPLETE_READ_BUFFER => FramebufferError::IncompleteReadBuffer,
+            gl::FRAMEBUFFER_UNSUPPORTED => FramebufferError::Unsupported,
+            gl::FRAMEBUFFER_INCOMPLETE_MULTISAMPLE => FramebufferError::IncompleteMultisample,
+            gl::FRAMEBUFFER_INCOMPLETE_LAYER_TARGETS => FramebufferError::IncompleteLayerTargets,
+            status => FramebufferError::Unknown(status),
+        })
+    }
+
+    /// Returns the name of the status as used by the gl spec.
+    pub fn name(self) -> &'static str {
+        match self {
+            FramebufferError::Undefined => "GL_FRAMEBUFFER_UNDEFINED",
+            FramebufferError::IncompleteAttachment => "GL_FRAMEBUFFER_INCOMPLETE_ATTACHMENT",
+            FramebufferError::IncompleteMissingAttachment => "GL_FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT",
+            FramebufferError::IncompleteDrawBuffer => "GL_FRAMEBUFFER_INCOMPLETE_DRAW_BUFFER",
+            FramebufferError::IncompleteReadBuffer => "GL_FRAMEBUFFER_INCOMPLETE_READ_BUFFER",
+            FramebufferError::Unsupported => "GL_FRAMEBUFFER_UNSUPPORTED",
+            FramebufferError::IncompleteMultisample => "GL_FRAMEBUFFER_INCOMPLETE_MULTISAMPLE",
+            FramebufferError::IncompleteLayerTargets => "GL_FRAMEBUFFER_INCOMPLETE_LAYER_TARGETS",
+            FramebufferError::Unknown(_) => "unknown",
+        }
+    }
+}
+
+impl fmt::Display for FramebufferError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FramebufferError::Unknown(status) => write!(f, "unknown framebuffer status 0x{:04X}", status),
+            err => f.write_str(err.name()),
+        }
+    }
+}
+
+impl error::Error for FramebufferError {}
+
+/// Returns whether the currently bound framebuffer is complete.
+pub fn framebuffer_status() -> Result<(), FramebufferError> {
+    let status = unsafe { gl::CheckFramebufferStatus(gl::FRAMEBUFFER) };
+    match FramebufferError::from_raw(status) {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
+/// Panics if the currently bound framebuffer isn't complete.
+#[track_caller]
+pub fn check_framebuffer_status() {
+    if let Err(err) = framebuffer_status() {
+        panic!("glBindFramebuffer failed, glCheckFrameBufferStatus(GL_FRAMEBUFFER) = {}", err);
+    }
+}
+
+static DEBUG_CHECKS: AtomicBool = AtomicBool::new(false);
+
+/// Enables checking `glGetError` after every call made through this
+/// module. Errors are printed with the name of the gl call and the
+/// location of the code that called the wrapper.
+///
+/// This is slow (it stalls the gpu pipeline) so should only be used
+/// whilst tracking down a bug.
+pub fn set_debug_checks(enabled: bool) {
+    DEBUG_CHECKS.store(enabled, Ordering::Relaxed);
+}
+
+pub fn debug_checks() -> bool {
+    DEBUG_CHECKS.load(Ordering::Relaxed)
+}
+
+/// Checks for errors when dropped, if debug checks are enabled.
+/// Created at the start of each wrapper so the check happens
+/// after the gl call.
+pub(super) struct DebugCheck {
+    call: &'static str,
+    location: &'static Location<'static>,
+}
+
+#[track_caller]
+pub(super) fn debug_check(call: &'static str) -> DebugCheck {
+    DebugCheck {
+        call,
+        location: Location::caller(),
+    }
+}
+
+impl Drop for DebugCheck {
+    fn drop(&mut self) {
+        if !debug_checks() {
+            return;
+        }
+        if let Err(errors) = gl_errors() {
+            for err in errors {
+                println!("{} failed at {}: {}", self.call, self.location, err);
+            }
+        }
+    }
+}
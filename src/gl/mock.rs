@@ -0,0 +1,1317 @@
+//! A fake gl implementation for testing code that uses the `gl` module
+//! without a context.
+//!
+//! `init` loads the mock in place of a driver. Every call made through
+//! the `gl` module is then recorded (`glGet*` calls aren't) and can be
+//! checked with `calls`. The mock hands out ids, tracks bindings and uploaded
+//! buffer data, and answers program introspection by parsing the
+//! `uniform`/`in` declarations of the attached shaders. Members of
+//! uniform blocks aren't reported as uniforms. Shaders always compile
+//! unless they contain an `#error` line.
+//!
+//! Query results are available as soon as the query ends. Time queries
+//! report 1ms, timestamps advance by 1ms per counter, and occlusion
+//! queries report a sample per draw call made whilst they were active.
+//!
+//! The state is per thread so tests can run in parallel, each test
+//! should call `init` first. Calling a gl function the mock doesn't
+//! implement panics.
+
+use super::gl;
+use super::gl::types::*;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::ffi;
+use std::os::raw::c_void;
+use std::ptr;
+use std::slice;
+
+/// A gl call recorded by the mock.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Call {
+    /// The name of the gl function, e.g. `glDrawElements`.
+    pub func: &'static str,
+    /// The integer arguments (enums, ids, sizes, offsets) in order.
+    pub args: Vec<i64>,
+    /// The float arguments, including uniform values and matrices.
+    pub floats: Vec<f32>,
+    /// For `glUniform*` calls, the name of the uniform in the program
+    /// in use.
+    pub uniform: Option<String>,
+}
+
+impl Call {
+    fn new(func: &'static str, args: &[i64]) -> Call {
+        Call {
+            func,
+            args: args.to_vec(),
+            floats: vec![],
+            uniform: None,
+        }
+    }
+}
+
+/// A uniform or attribute declared by a shader.
+#[derive(Clone)]
+struct Variable {
+    name: String,
+    ty: GLenum,
+    size: i32,
+    location: i32,
+}
+
+#[derive(Default)]
+struct Program {
+    shaders: Vec<GLuint>,
+    linked: bool,
+    uniforms: Vec<Variable>,
+    attributes: Vec<Variable>,
+    /// Uniform block names and their bindings, indexed by block index
+    blocks: Vec<(String, GLuint)>,
+}
+
+#[derive(Default)]
+struct State {
+    calls: Vec<Call>,
+    next_id: GLuint,
+    viewport: [GLint; 4],
+
+    program: GLuint,
+    vertex_array: GLuint,
+    buffers: HashMap<GLenum, GLuint>,
+    indexed_buffers: HashMap<(GLenum, GLuint), GLuint>,
+    // Element buffer bindings are part of the vertex array's state
+    element_buffers: HashMap<GLuint, GLuint>,
+    active_texture: GLuint,
+    textures: HashMap<(GLuint, GLenum), GLuint>,
+    read_framebuffer: GLuint,
+    draw_framebuffer: GLuint,
+    renderbuffer: GLuint,
+    flags: HashSet<GLenum>,
+
+    live: HashSet<GLuint>,
+    labels: HashMap<GLuint, String>,
+    buffer_data: HashMap<GLuint, Vec<u8>>,
+    shaders: HashMap<GLuint, (GLenum, String)>,
+    programs: HashMap<GLuint, Program>,
+    /// The active query and the call it began at per target.
+    active_queries: HashMap<GLenum, (GLuint, usize)>,
+    query_results: HashMap<GLuint, u64>,
+    timestamp: u64,
+    fail_waits: bool,
+    /// Queries that are only names until first begun.
+    unused_queries: HashSet<GLuint>,
+    errors: Vec<GLenum>,
+    /// The location of the first attribute of each program.
+    attribute_base: GLint,
+    /// Overrides the 4 samples supported.
+    max_samples: Option<GLint>,
+}
+
+impl State {
+    fn gen(&mut self) -> GLuint {
+        self.next_id += 1;
+        self.live.insert(self.next_id);
+        self.next_id
+    }
+
+    fn delete(&mut self, id: GLuint) {
+        self.live.remove(&id);
+    }
+
+    fn bound_buffer(&self, target: GLenum) -> GLuint {
+        if target == gl::ELEMENT_ARRAY_BUFFER {
+            self.element_buffers.get(&self.vertex_array).cloned().unwrap_or(0)
+        } else {
+            self.buffers.get(&target).cloned().unwrap_or(0)
+        }
+    }
+
+    fn uniform_name(&self, location: GLint) -> Option<String> {
+        let program = self.programs.get(&self.program)?;
+        program.uniforms.iter()
+            .find(|v| location >= v.location && location < v.location + v.size)
+            .map(|v| if v.size > 1 {
+                format!("{}[{}]", v.name, location - v.location)
+            } else {
+                v.name.clone()
+            })
+    }
+}
+
+thread_local! {
+    static STATE: RefCell<State> = RefCell::new(State::default());
+}
+
+fn with<F: FnOnce(&mut State) -> R, R>(f: F) -> R {
+    STATE.with(|v| f(&mut v.borrow_mut()))
+}
+
+fn record(func: &'static str, args: &[i64]) {
+    with(|s| s.calls.push(Call::new(func, args)));
+}
+
+/// Loads the mock as the gl implementation and resets the calling
+/// thread's mock state.
+pub fn init() {
+    with(|s| *s = State::default());
+    super::init_with(lookup);
+}
+
+/// Returns the calls recorded on this thread since `init` or the
+/// last `clear_calls`.
+pub fn calls() -> Vec<Call> {
+    with(|s| s.calls.clone())
+}
+
+/// Makes every following `glClientWaitSync` fail, as on a lost
+/// context.
+pub fn fail_waits() {
+    with(|s| s.fail_waits = true);
+}
+
+/// Places the attributes of programs linked afterwards from `base`
+/// instead of 0, as another driver might.
+pub fn set_attribute_base(base: i32) {
+    with(|s| s.attribute_base = base);
+}
+
+/// Limits the samples reported as supported, as a driver without
+/// multisampling might.
+pub fn set_max_samples(samples: i32) {
+    with(|s| s.max_samples = Some(samples));
+}
+
+pub fn clear_calls() {
+    with(|s| s.calls.clear());
+}
+
+/// Returns the label given to the object with `label`, if any.
+pub fn label(id: u32) -> Option<String> {
+    with(|s| s.labels.get(&id).cloned())
+}
+
+/// Returns the ids of every object (textures, buffers, programs
+/// etc) that has been created but not deleted.
+pub fn live_objects() -> Vec<u32> {
+    with(|s| {
+        let mut ids: Vec<u32> = s.live.iter().cloned().collect();
+        ids.sort_unstable();
+        ids
+    })
+}
+
+/// Returns the data last uploaded to the buffer.
+pub fn buffer_data(id: u32) -> Option<Vec<u8>> {
+    with(|s| s.buffer_data.get(&id).cloned())
+}
+
+pub fn bound_program() -> u32 {
+    with(|s| s.program)
+}
+
+pub fn bound_vertex_array() -> u32 {
+    with(|s| s.vertex_array)
+}
+
+pub fn bound_buffer(target: super::BufferTarget) -> u32 {
+    with(|s| s.bound_buffer(target.0))
+}
+
+/// Returns the buffer bound to the indexed binding point of the target.
+pub fn bound_indexed_buffer(target: super::BufferTarget, index: u32) -> u32 {
+    with(|s| s.indexed_buffers.get(&(target.0, index)).cloned().unwrap_or(0))
+}
+
+/// Returns the binding point set for the named uniform block of the
+/// program, if the program has the block.
+pub fn block_binding(program: u32, name: &str) -> Option<u32> {
+    with(|s| s.programs.get(&program)
+        .and_then(|p| p.blocks.iter().find(|v| v.0 == name))
+        .map(|v| v.1))
+}
+
+/// Returns the texture bound to the target of the passed texture unit.
+pub fn bound_texture(unit: u32, target: super::TextureTarget) -> u32 {
+    with(|s| s.textures.get(&(unit, target.0)).cloned().unwrap_or(0))
+}
+
+/// Returns the (read, draw) framebuffers, 0 is the default one.
+pub fn bound_framebuffers() -> (u32, u32) {
+    with(|s| (s.read_framebuffer, s.draw_framebuffer))
+}
+
+pub fn bound_renderbuffer() -> u32 {
+    with(|s| s.renderbuffer)
+}
+
+pub fn is_enabled(flag: super::Flag) -> bool {
+    with(|s| s.flags.contains(&flag.0))
+}
+
+const EXTENSIONS: &[&[u8]] = &[b"GL_KHR_debug\0", b"GL_ARB_instanced_arrays\0", b"GL_ARB_timer_query\0", b"GL_ARB_clip_control\0"];
+
+fn lookup(name: &str) -> *const c_void {
+    match name {
+        "glGetError" => get_error as *const c_void,
+        "glGetString" => get_string as *const c_void,
+        "glGetStringi" => get_stringi as *const c_void,
+        "glGetIntegerv" => get_integerv as *const c_void,
+        "glObjectLabel" => object_label as *const c_void,
+
+        "glDrawArrays" => draw_arrays as *const c_void,
+        "glDrawElements" => draw_elements as *const c_void,
+        "glMultiDrawElements" => multi_draw_elements as *const c_void,
+        "glDrawArraysInstanced" => draw_arrays_instanced as *const c_void,
+        "glDrawElementsInstanced" => draw_elements_instanced as *const c_void,
+        "glDrawElementsBaseVertex" => draw_elements_base_vertex as *const c_void,
+        "glViewport" => viewport as *const c_void,
+        "glClearColor" => clear_color as *const c_void,
+        "glClear" => clear as *const c_void,
+        "glClearDepth" => clear_depth as *const c_void,
+        "glClipControl" => clip_control as *const c_void,
+        "glDepthMask" => depth_mask as *const c_void,
+        "glDepthFunc" => depth_func as *const c_void,
+        "glEnable" => enable as *const c_void,
+        "glDisable" => disable as *const c_void,
+        "glActiveTexture" => active_texture as *const c_void,
+        "glBlendFunc" => blend_func as *const c_void,
+        "glBlendFuncSeparate" => blend_func_separate as *const c_void,
+        "glCullFace" => cull_face as *const c_void,
+        "glFrontFace" => front_face as *const c_void,
+
+        "glGenTextures" => gen_textures as *const c_void,
+        "glDeleteTextures" => delete_textures as *const c_void,
+        "glBindTexture" => bind_texture as *const c_void,
+        "glGetTexImage" => get_tex_image as *const c_void,
+        "glTexImage2D" => tex_image_2d as *const c_void,
+        "glTexSubImage2D" => tex_sub_image_2d as *const c_void,
+        "glTexImage2DMultisample" => tex_image_2d_multisample as *const c_void,
+        "glTexImage3D" => tex_image_3d as *const c_void,
+        "glTexSubImage3D" => tex_sub_image_3d as *const c_void,
+        "glTexParameteri" => tex_parameteri as *const c_void,
+
+        "glCreateShader" => create_shader as *const c_void,
+        "glDeleteShader" => delete_shader as *const c_void,
+        "glShaderSource" => shader_source as *const c_void,
+        "glCompileShader" => compile_shader as *const c_void,
+        "glGetShaderiv" => get_shaderiv as *const c_void,
+        "glGetShaderInfoLog" => get_shader_info_log as *const c_void,
+        "glCreateProgram" => create_program as *const c_void,
+        "glDeleteProgram" => delete_program as *const c_void,
+        "glAttachShader" => attach_shader as *const c_void,
+        "glLinkProgram" => link_program as *const c_void,
+        "glUseProgram" => use_program as *const c_void,
+        "glGetProgramiv" => get_programiv as *const c_void,
+        "glGetProgramInfoLog" => get_program_info_log as *const c_void,
+        "glGetActiveUniform" => get_active_uniform as *const c_void,
+        "glGetActiveAttrib" => get_active_attrib as *const c_void,
+        "glGetUniformLocation" => get_uniform_location as *const c_void,
+        "glGetAttribLocation" => get_attrib_location as *const c_void,
+        "glBindFragDataLocation" => bind_frag_data_location as *const c_void,
+        "glGetUniformBlockIndex" => get_uniform_block_index as *const c_void,
+        "glUniformBlockBinding" => uniform_block_binding as *const c_void,
+
+        "glUniform1i" => uniform_1i as *const c_void,
+        "glUniform3i" => uniform_3i as *const c_void,
+        "glUniform1f" => uniform_1f as *const c_void,
+        "glUniform2f" => uniform_2f as *const c_void,
+        "glUniform3f" => uniform_3f as *const c_void,
+        "glUniform4f" => uniform_4f as *const c_void,
+        "glUniformMatrix4fv" => uniform_matrix_4fv as *const c_void,
+
+        "glEnableVertexAttribArray" => enable_vertex_attrib_array as *const c_void,
+        "glDisableVertexAttribArray" => disable_vertex_attrib_array as *const c_void,
+        "glVertexAttribPointer" => vertex_attrib_pointer as *const c_void,
+        "glVertexAttribIPointer" => vertex_attrib_i_pointer as *const c_void,
+        "glVertexAttribDivisor" => vertex_attrib_divisor as *const c_void,
+        "glVertexAttrib4f" => vertex_attrib_4f as *const c_void,
+
+        "glGenVertexArrays" => gen_vertex_arrays as *const c_void,
+        "glDeleteVertexArrays" => delete_vertex_arrays as *const c_void,
+        "glBindVertexArray" => bind_vertex_array as *const c_void,
+
+        "glGenBuffers" => gen_buffers as *const c_void,
+        "glDeleteBuffers" => delete_buffers as *const c_void,
+        "glBindBuffer" => bind_buffer as *const c_void,
+        "glBufferData" => buffer_data_fn as *const c_void,
+        "glBufferSubData" => buffer_sub_data as *const c_void,
+        "glBindBufferBase" => bind_buffer_base as *const c_void,
+        "glMapBuffer" => map_buffer as *const c_void,
+        "glMapBufferRange" => map_buffer_range as *const c_void,
+        "glUnmapBuffer" => unmap_buffer as *const c_void,
+
+        "glGenFramebuffers" => gen_framebuffers as *const c_void,
+        "glDeleteFramebuffers" => delete_framebuffers as *const c_void,
+        "glBindFramebuffer" => bind_framebuffer as *const c_void,
+        "glFramebufferTexture2D" => framebuffer_texture_2d as *const c_void,
+        "glCheckFramebufferStatus" => check_framebuffer_status as *const c_void,
+        "glDrawBuffers" => draw_buffers as *const c_void,
+        "glBlitFramebuffer" => blit_framebuffer as *const c_void,
+        "glReadBuffer" => read_buffer as *const c_void,
+        "glReadPixels" => read_pixels as *const c_void,
+        "glClearBufferfv" => clear_bufferfv as *const c_void,
+        "glFramebufferRenderbuffer" => framebuffer_renderbuffer as *const c_void,
+
+        "glGenRenderbuffers" => gen_renderbuffers as *const c_void,
+        "glDeleteRenderbuffers" => delete_renderbuffers as *const c_void,
+        "glBindRenderbuffer" => bind_renderbuffer as *const c_void,
+        "glRenderbufferStorage" => renderbuffer_storage as *const c_void,
+        "glRenderbufferStorageMultisample" => renderbuffer_storage_multisample as *const c_void,
+
+        "glFenceSync" => fence_sync as *const c_void,
+        "glClientWaitSync" => client_wait_sync as *const c_void,
+        "glDeleteSync" => delete_sync as *const c_void,
+
+        "glGenQueries" => gen_queries as *const c_void,
+        "glDeleteQueries" => delete_queries as *const c_void,
+        "glBeginQuery" => begin_query as *const c_void,
+        "glEndQuery" => end_query as *const c_void,
+        "glQueryCounter" => query_counter as *const c_void,
+        "glGetQueryObjectuiv" => get_query_objectuiv as *const c_void,
+        "glGetQueryObjectui64v" => get_query_objectui64v as *const c_void,
+        _ => ptr::null(),
+    }
+}
+
+// Queries
+
+extern "system" fn get_error() -> GLenum {
+    with(|s| if s.errors.is_empty() { gl::NO_ERROR } else { s.errors.remove(0) })
+}
+
+extern "system" fn get_string(name: GLenum) -> *const GLubyte {
+    let s: &'static [u8] = match name {
+        gl::VENDOR => b"steven\0",
+        gl::RENDERER => b"mock\0",
+        gl::VERSION => b"3.2 mock\0",
+        gl::SHADING_LANGUAGE_VERSION => b"1.50\0",
+        _ => return ptr::null(),
+    };
+    s.as_ptr()
+}
+
+extern "system" fn get_stringi(name: GLenum, index: GLuint) -> *const GLubyte {
+    match EXTENSIONS.get(index as usize) {
+        Some(ext) if name == gl::EXTENSIONS => ext.as_ptr(),
+        _ => ptr::null(),
+    }
+}
+
+extern "system" fn get_integerv(pname: GLenum, data: *mut GLint) {
+    let (viewport, max_samples) = with(|s| (s.viewport, s.max_samples.unwrap_or(4)));
+    let values: &[GLint] = match pname {
+        gl::MAJOR_VERSION => &[3],
+        gl::MINOR_VERSION => &[2],
+        gl::NUM_EXTENSIONS => &[EXTENSIONS.len() as GLint],
+        gl::VIEWPORT => &viewport,
+        gl::MAX_SAMPLES | gl::MAX_COLOR_TEXTURE_SAMPLES | gl::MAX_DEPTH_TEXTURE_SAMPLES => &[max_samples],
+        gl::MAX_TEXTURE_SIZE => &[8192],
+        gl::MAX_ARRAY_TEXTURE_LAYERS => &[2048],
+        gl::MAX_DRAW_BUFFERS | gl::MAX_COLOR_ATTACHMENTS => &[8],
+        gl::MAX_VERTEX_ATTRIBS => &[16],
+        gl::MAX_VERTEX_UNIFORM_COMPONENTS | gl::MAX_FRAGMENT_UNIFORM_COMPONENTS => &[1024],
+        gl::MAX_COMBINED_TEXTURE_IMAGE_UNITS => &[48],
+        _ => &[0],
+    };
+    unsafe {
+        ptr::copy_nonoverlapping(values.as_ptr(), data, values.len());
+    }
+}
+
+extern "system" fn object_label(identifier: GLenum, name: GLuint, length: GLsizei, label: *const GLchar) {
+    let label = unsafe { read_string(label, length) };
+    with(|s| if identifier == gl::QUERY && s.unused_queries.contains(&name) {
+        s.errors.push(gl::INVALID_VALUE);
+    } else {
+        s.labels.insert(name, label);
+    });
+}
+
+unsafe fn read_string(s: *const GLchar, length: GLint) -> String {
+    if length >= 0 {
+        String::from_utf8_lossy(slice::from_raw_parts(s as *const u8, length as usize)).into_owned()
+    } else {
+        ffi::CStr::from_ptr(s).to_string_lossy().into_owned()
+    }
+}
+
+/// Copies the string into a buffer passed to a `glGet*` function.
+unsafe fn write_string(s: &str, buf_size: GLsizei, length: *mut GLsizei, out: *mut GLchar) {
+    if buf_size <= 0 {
+        return;
+    }
+    let len = s.len().min(buf_size as usize - 1);
+    ptr::copy_nonoverlapping(s.as_ptr() as *const GLchar, out, len);
+    *out.add(len) = 0;
+    if !length.is_null() {
+        *length = len as GLsizei;
+    }
+}
+
+// State
+
+extern "system" fn draw_arrays(mode: GLenum, first: GLint, count: GLsizei) {
+    record("glDrawArrays", &[mode as i64, first as i64, count as i64]);
+}
+
+extern "system" fn draw_elements(mode: GLenum, count: GLsizei, ty: GLenum, indices: *const c_void) {
+    record("glDrawElements", &[mode as i64, count as i64, ty as i64, indices as i64]);
+}
+
+extern "system" fn draw_arrays_instanced(mode: GLenum, first: GLint, count: GLsizei, instances: GLsizei) {
+    record("glDrawArraysInstanced", &[mode as i64, first as i64, count as i64, instances as i64]);
+}
+
+extern "system" fn draw_elements_instanced(mode: GLenum, count: GLsizei, ty: GLenum, indices: *const c_void, instances: GLsizei) {
+    record("glDrawElementsInstanced", &[mode as i64, count as i64, ty as i64, indices as i64, instances as i64]);
+}
+
+extern "system" fn draw_elements_base_vertex(mode: GLenum, count: GLsizei, ty: GLenum, indices: *const c_void, base_vertex: GLint) {
+    record("glDrawElementsBaseVertex", &[mode as i64, count as i64, ty as i64, indices as i64, base_vertex as i64]);
+}
+
+extern "system" fn multi_draw_elements(mode: GLenum, count: *const GLsizei, ty: GLenum, indices: *const *const c_void, draw_count: GLsizei) {
+    let mut args = vec![mode as i64, ty as i64];
+    unsafe {
+        for i in 0..draw_count as usize {
+            args.push(*count.add(i) as i64);
+            args.push(*indices.add(i) as i64);
+        }
+    }
+    record("glMultiDrawElements", &args);
+}
+
+extern "system" fn viewport(x: GLint, y: GLint, w: GLsizei, h: GLsizei) {
+    with(|s| s.viewport = [x, y, w, h]);
+    record("glViewport", &[x as i64, y as i64, w as i64, h as i64]);
+}
+
+extern "system" fn clear_color(r: GLfloat, g: GLfloat, b: GLfloat, a: GLfloat) {
+    with(|s| s.calls.push(Call {
+        floats: vec![r, g, b, a],
+        ..Call::new("glClearColor", &[])
+    }));
+}
+
+extern "system" fn clear(mask: GLbitfield) {
+    record("glClear", &[mask as i64]);
+}
+
+extern "system" fn clear_depth(depth: GLdouble) {
+    with(|s| s.calls.push(Call {
+        floats: vec![depth as f32],
+        ..Call::new("glClearDepth", &[])
+    }));
+}
+
+extern "system" fn clip_control(origin: GLenum, depth: GLenum) {
+    record("glClipControl", &[origin as i64, depth as i64]);
+}
+
+extern "system" fn depth_mask(flag: GLboolean) {
+    record("glDepthMask", &[flag as i64]);
+}
+
+extern "system" fn depth_func(func: GLenum) {
+    record("glDepthFunc", &[func as i64]);
+}
+
+extern "system" fn enable(cap: GLenum) {
+    with(|s| s.flags.insert(cap));
+    record("glEnable", &[cap as i64]);
+}
+
+extern "system" fn disable(cap: GLenum) {
+    with(|s| s.flags.remove(&cap));
+    record("glDisable", &[cap as i64]);
+}
+
+extern "system" fn active_texture(texture: GLenum) {
+    with(|s| s.active_texture = texture - gl::TEXTURE0);
+    record("glActiveTexture", &[texture as i64]);
+}
+
+extern "system" fn blend_func(sfactor: GLenum, dfactor: GLenum) {
+    record("glBlendFunc", &[sfactor as i64, dfactor as i64]);
+}
+
+extern "system" fn blend_func_separate(src_rgb: GLenum, dst_rgb: GLenum, src_alpha: GLenum, dst_alpha: GLenum) {
+    record("glBlendFuncSeparate", &[src_rgb as i64, dst_rgb as i64, src_alpha as i64, dst_alpha as i64]);
+}
+
+extern "system" fn cull_face(mode: GLenum) {
+    record("glCullFace", &[mode as i64]);
+}
+
+extern "system" fn front_face(mode: GLenum) {
+    record("glFrontFace", &[mode as i64]);
+}
+
+// Textures
+
+extern "system" fn gen_textures(n: GLsizei, textures: *mut GLuint) {
+    gen_objects("glGenTextures", n, textures);
+}
+
+extern "system" fn delete_textures(n: GLsizei, textures: *const GLuint) {
+    delete_objects("glDeleteTextures", n, textures);
+}
+
+fn gen_objects(func: &'static str, n: GLsizei, out: *mut GLuint) {
+    let mut args = vec![];
+    for i in 0..n as usize {
+        let id = with(|s| s.gen());
+        unsafe {
+            *out.add(i) = id;
+        }
+        args.push(id as i64);
+    }
+    record(func, &args);
+}
+
+fn delete_objects(func: &'static str, n: GLsizei, ids: *const GLuint) {
+    let ids = unsafe { slice::from_raw_parts(ids, n as usize) };
+    with(|s| ids.iter().for_each(|id| s.delete(*id)));
+    record(func, &ids.iter().map(|v| *v as i64).collect::<Vec<_>>());
+}
+
+extern "system" fn bind_texture(target: GLenum, texture: GLuint) {
+    with(|s| s.textures.insert((s.active_texture, target), texture));
+    record("glBindTexture", &[target as i64, texture as i64]);
+}
+
+extern "system" fn get_tex_image(target: GLenum, level: GLint, format: GLenum, ty: GLenum, _pixels: *mut c_void) {
+    record("glGetTexImage", &[target as i64, level as i64, format as i64, ty as i64]);
+}
+
+extern "system" fn tex_image_2d(target: GLenum, level: GLint, internal_format: GLint, width: GLsizei, height: GLsizei,
+                                border: GLint, format: GLenum, ty: GLenum, _pixels: *const c_void) {
+    record("glTexImage2D", &[target as i64, level as i64, internal_format as i64, width as i64, height as i64,
+                              border as i64, format as i64, ty as i64]);
+}
+
+extern "system" fn tex_sub_image_2d(target: GLenum, level: GLint, x: GLint, y: GLint, width: GLsizei, height: GLsizei,
+                                    format: GLenum, ty: GLenum, _pixels: *const c_void) {
+    record("glTexSubImage2D", &[target as i64, level as i64, x as i64, y as i64, width as i64, height as i64,
+                                 format as i64, ty as i64]);
+}
+
+extern "system" fn tex_image_2d_multisample(target: GLenum, samples: GLsizei, internal_format: GLenum,
+                                            width: GLsizei, height: GLsizei, fixed: GLboolean) {
+    record("glTexImage2DMultisample", &[target as i64, samples as i64, internal_format as i64,
+                                         width as i64, height as i64, fixed as i64]);
+}
+
+extern "system" fn tex_image_3d(target: GLenum, level: GLint, internal_format: GLint,
+                                width: GLsizei, height: GLsizei, depth: GLsizei,
+                                border: GLint, format: GLenum, ty: GLenum, _pixels: *const c_void) {
+    record("glTexImage3D", &[target as i64, level as i64, internal_format as i64,
+                              width as i64, height as i64, depth as i64, border as i64, format as i64, ty as i64]);
+}
+
+extern "system" fn tex_sub_image_3d(target: GLenum, level: GLint, x: GLint, y: GLint, z: GLint,
+                                    width: GLsizei, height: GLsizei, depth: GLsizei,
+                                    format: GLenum, ty: GLenum, _pixels: *const c_void) {
+    record("glTexSubImage3D", &[target as i64, level as i64, x as i64, y as i64, z as i64,
+                                 width as i64, height as i64, depth as i64, format as i64, ty as i64]);
+}
+
+extern "system" fn tex_parameteri(target: GLenum, pname: GLenum, param: GLint) {
+    record("glTexParameteri", &[target as i64, pname as i64, param as i64]);
+}
+
+// Shaders and programs
+
+extern "system" fn create_shader(ty: GLenum) -> GLuint {
+    let id = with(|s| {
+        let id = s.gen();
+        s.shaders.insert(id, (ty, String::new()));
+        id
+    });
+    record("glCreateShader", &[ty as i64, id as i64]);
+    id
+}
+
+extern "system" fn delete_shader(shader: GLuint) {
+    with(|s| {
+        s.delete(shader);
+        s.shaders.remove(&shader);
+    });
+    record("glDeleteShader", &[shader as i64]);
+}
+
+extern "system" fn shader_source(shader: GLuint, count: GLsizei, string: *const *const GLchar, length: *const GLint) {
+    let mut src = String::new();
+    unsafe {
+        for i in 0..count as usize {
+            let len = if length.is_null() { -1 } else { *length.add(i) };
+            src.push_str(&read_string(*string.add(i), len));
+        }
+    }
+    with(|s| s.shaders.entry(shader).or_insert((0, String::new())).1 = src);
+    record("glShaderSource", &[shader as i64]);
+}
+
+extern "system" fn compile_shader(shader: GLuint) {
+    record("glCompileShader", &[shader as i64]);
+}
+
+/// Returns the compile log of the shader, shaders with an `#error`
+/// line fail to compile.
+fn shader_log(src: &str) -> Option<String> {
+    src.lines()
+        .enumerate()
+        .find(|(_, line)| line.trim_start().starts_with("#error"))
+        .map(|(i, line)| format!("0:{}(1): error: {}", i + 1, line.trim()))
+}
+
+extern "system" fn get_shaderiv(shader: GLuint, pname: GLenum, params: *mut GLint) {
+    let log = with(|s| s.shaders.get(&shader).and_then(|v| shader_log(&v.1)));
+    let v = match pname {
+        gl::COMPILE_STATUS => log.is_none() as GLint,
+        gl::INFO_LOG_LENGTH => log.map_or(0, |v| v.len() as GLint + 1),
+        gl::SHADER_TYPE => with(|s| s.shaders.get(&shader).map_or(0, |v| v.0 as GLint)),
+        _ => 0,
+    };
+    unsafe {
+        *params = v;
+    }
+}
+
+extern "system" fn get_shader_info_log(shader: GLuint, buf_size: GLsizei, length: *mut GLsizei, info_log: *mut GLchar) {
+    let log = with(|s| s.shaders.get(&shader).and_then(|v| shader_log(&v.1))).unwrap_or_default();
+    unsafe {
+        write_string(&log, buf_size, length, info_log);
+    }
+}
+
+extern "system" fn create_program() -> GLuint {
+    let id = with(|s| {
+        let id = s.gen();
+        s.programs.insert(id, Program::default());
+        id
+    });
+    record("glCreateProgram", &[id as i64]);
+    id
+}
+
+extern "system" fn delete_program(program: GLuint) {
+    with(|s| {
+        s.delete(program);
+        s.programs.remove(&program);
+    });
+    record("glDeleteProgram", &[program as i64]);
+}
+
+extern "system" fn attach_shader(program: GLuint, shader: GLuint) {
+    with(|s| s.programs.entry(program).or_default().shaders.push(shader));
+    record("glAttachShader", &[program as i64, shader as i64]);
+}
+
+extern "system" fn link_program(program: GLuint) {
+    with(|s| {
+        let sources: Vec<(GLenum, String)> = s.programs.get(&program)
+            .map(|p| p.shaders.iter().filter_map(|v| s.shaders.get(v).cloned()).collect())
+            .unwrap_or_default();
+        let mut uniforms: Vec<Variable> = vec![];
+        let mut attributes: Vec<Variable> = vec![];
+        let mut blocks: Vec<(String, GLuint)> = vec![];
+        for (ty, src) in &sources {
+            for (kind, var) in parse_variables(src) {
+                if kind == "block" {
+                    if !blocks.iter().any(|v| v.0 == var.name) {
+                        blocks.push((var.name, 0));
+                    }
+                    continue;
+                }
+                let (vars, loc) = if kind == "uniform" {
+                    let loc = uniforms.last().map_or(0, |v| v.location + v.size);
+                    (&mut uniforms, loc)
+                } else if kind == "in" && *ty == gl::VERTEX_SHADER {
+                    // Matrices take a location per column
+                    let loc = attributes.last().map_or(s.attribute_base, |v| v.location + v.size * columns(v.ty));
+                    (&mut attributes, loc)
+                } else {
+                    continue;
+                };
+                if !vars.iter().any(|v| v.name == var.name) {
+                    vars.push(Variable { location: loc, ..var });
+                }
+            }
+        }
+        if let Some(p) = s.programs.get_mut(&program) {
+            p.linked = true;
+            p.uniforms = uniforms;
+            p.attributes = attributes;
+            p.blocks = blocks;
+        }
+    });
+    record("glLinkProgram", &[program as i64]);
+}
+
+/// Returns the `uniform` and `in` variables and the uniform blocks
+/// (as `block`) declared by the shader, following
+/// `#ifdef`/`#ifndef`/`#else`/`#endif` for simple defines.
+fn parse_variables(src: &str) -> Vec<(&'static str, Variable)> {
+    let mut defines = HashSet::new();
+    // Whether each enclosing #if block is active
+    let mut active: Vec<bool> = vec![];
+    let mut vars = vec![];
+    for line in src.lines() {
+        let line = line.trim();
+        let enabled = active.iter().all(|v| *v);
+        if let Some(name) = line.strip_prefix("#ifdef ") {
+            active.push(defines.contains(name.trim()));
+        } else if let Some(name) = line.strip_prefix("#ifndef ") {
+            active.push(!defines.contains(name.trim()));
+        } else if line.starts_with("#else") {
+            if let Some(v) = active.last_mut() {
+                *v = !*v;
+            }
+        } else if line.starts_with("#endif") {
+            active.pop();
+        } else if !enabled {
+            continue;
+        } else if let Some(def) = line.strip_prefix("#define ") {
+            defines.insert(def.split_whitespace().next().unwrap_or("").to_owned());
+        } else {
+            let line = line.split("//").next().unwrap();
+            // Block members are skipped as they don't start with `uniform`
+            let line = match line.strip_prefix("layout(") {
+                Some(rest) => rest.split_once(')').map_or("", |v| v.1),
+                None => line,
+            };
+            if let Some(block) = line.trim().strip_prefix("uniform ").and_then(|v| v.strip_suffix('{')) {
+                vars.push(("block", Variable {
+                    name: block.trim().to_owned(),
+                    ty: 0,
+                    size: 1,
+                    location: -1,
+                }));
+                continue;
+            }
+            let mut words = line.trim_end_matches(';').split_whitespace();
+            let kind = match words.next() {
+                Some("uniform") => "uniform",
+                Some("in") => "in",
+                _ => continue,
+            };
+            let ty = match words.next() {
+                Some(val) => glsl_type(val),
+                None => continue,
+            };
+            let names: String = words.collect();
+            for name in names.split(',').filter(|v| !v.is_empty()) {
+                let (name, size) = match name.find('[') {
+                    Some(i) => (&name[..i], name[i + 1..].trim_end_matches(']').parse().unwrap_or(1)),
+                    None => (name, 1),
+                };
+                vars.push((kind, Variable {
+                    name: name.to_owned(),
+                    ty,
+                    size,
+                    location: -1,
+                }));
+            }
+        }
+    }
+    vars
+}
+
+fn glsl_type(name: &str) -> GLenum {
+    match name {
+        "float" => gl::FLOAT,
+        "vec2" => gl::FLOAT_VEC2,
+        "vec3" => gl::FLOAT_VEC3,
+        "vec4" => gl::FLOAT_VEC4,
+        "int" => gl::INT,
+        "ivec2" => gl::INT_VEC2,
+        "ivec3" => gl::INT_VEC3,
+        "ivec4" => gl::INT_VEC4,
+        "uint" => gl::UNSIGNED_INT,
+        "bool" => gl::BOOL,
+        "mat3" => gl::FLOAT_MAT3,
+        "mat4" => gl::FLOAT_MAT4,
+        "sampler2D" => gl::SAMPLER_2D,
+        "sampler2DArray" => gl::SAMPLER_2D_ARRAY,
+        "sampler2DMS" => gl::SAMPLER_2D_MULTISAMPLE,
+        "sampler3D" => gl::SAMPLER_3D,
+        _ => 0,
+    }
+}
+
+fn columns(ty: GLenum) -> GLint {
+    match ty {
+        gl::FLOAT_MAT3 => 3,
+        gl::FLOAT_MAT4 => 4,
+        _ => 1,
+    }
+}
+
+/// Returns the name drivers report for the variable, arrays get a
+/// `[0]` suffix.
+fn reported_name(v: &Variable) -> String {
+    if v.size > 1 {
+        format!("{}[0]", v.name)
+    } else {
+        v.name.clone()
+    }
+}
+
+extern "system" fn use_program(program: GLuint) {
+    with(|s| s.program = program);
+    record("glUseProgram", &[program as i64]);
+}
+
+extern "system" fn get_programiv(program: GLuint, pname: GLenum, params: *mut GLint) {
+    let v = with(|s| {
+        let p = match s.programs.get(&program) {
+            Some(val) => val,
+            None => return 0,
+        };
+        let max_len = |vars: &[Variable]| vars.iter().map(|v| reported_name(v).len() as GLint + 1).max().unwrap_or(0);
+        match pname {
+            gl::LINK_STATUS => p.linked as GLint,
+            gl::INFO_LOG_LENGTH => 0,
+            gl::ACTIVE_UNIFORMS => p.uniforms.len() as GLint,
+            gl::ACTIVE_UNIFORM_MAX_LENGTH => max_len(&p.uniforms),
+            gl::ACTIVE_ATTRIBUTES => p.attributes.len() as GLint,
+            gl::ACTIVE_ATTRIBUTE_MAX_LENGTH => max_len(&p.attributes),
+            _ => 0,
+        }
+    });
+    unsafe {
+        *params = v;
+    }
+}
+
+extern "system" fn get_program_info_log(_program: GLuint, buf_size: GLsizei, length: *mut GLsizei, info_log: *mut GLchar) {
+    unsafe {
+        write_string("", buf_size, length, info_log);
+    }
+}
+
+fn get_active(program: GLuint, index: GLuint, uniform: bool,
+              buf_size: GLsizei, length: *mut GLsizei, size: *mut GLint, ty: *mut GLenum, name: *mut GLchar) {
+    let var = with(|s| s.programs.get(&program).and_then(|p| {
+        let vars = if uniform { &p.uniforms } else { &p.attributes };
+        vars.get(index as usize).cloned()
+    }));
+    if let Some(var) = var {
+        unsafe {
+            *size = var.size;
+            *ty = var.ty;
+            write_string(&reported_name(&var), buf_size, length, name);
+        }
+    }
+}
+
+extern "system" fn get_active_uniform(program: GLuint, index: GLuint, buf_size: GLsizei, length: *mut GLsizei,
+                                      size: *mut GLint, ty: *mut GLenum, name: *mut GLchar) {
+    record("glGetActiveUniform", &[program as i64, index as i64]);
+    get_active(program, index, true, buf_size, length, size, ty, name);
+}
+
+extern "system" fn get_active_attrib(program: GLuint, index: GLuint, buf_size: GLsizei, length: *mut GLsizei,
+                                     size: *mut GLint, ty: *mut GLenum, name: *mut GLchar) {
+    record("glGetActiveAttrib", &[program as i64, index as i64]);
+    get_active(program, index, false, buf_size, length, size, ty, name);
+}
+
+/// Finds the location of `name`, `name[0]` or `name[i]`.
+fn find_location(vars: &[Variable], name: &str) -> GLint {
+    let (base, index) = match name.find('[') {
+        Some(i) => (&name[..i], name[i + 1..].trim_end_matches(']').parse().unwrap_or(-1)),
+        None => (name, 0),
+    };
+    vars.iter()
+        .find(|v| v.name == base)
+        .filter(|v| index >= 0 && index < v.size)
+        .map_or(-1, |v| v.location + index)
+}
+
+extern "system" fn get_uniform_location(program: GLuint, name: *const GLchar) -> GLint {
+    let name = unsafe { read_string(name, -1) };
+    with(|s| s.programs.get(&program).map_or(-1, |p| find_location(&p.uniforms, &name)))
+}
+
+extern "system" fn get_attrib_location(program: GLuint, name: *const GLchar) -> GLint {
+    let name = unsafe { read_string(name, -1) };
+    with(|s| s.programs.get(&program).map_or(-1, |p| find_location(&p.attributes, &name)))
+}
+
+extern "system" fn get_uniform_block_index(program: GLuint, name: *const GLchar) -> GLuint {
+    let name = unsafe { read_string(name, -1) };
+    with(|s| s.programs.get(&program)
+        .and_then(|p| p.blocks.iter().position(|v| v.0 == name))
+        .map_or(gl::INVALID_INDEX, |v| v as GLuint))
+}
+
+extern "system" fn uniform_block_binding(program: GLuint, index: GLuint, binding: GLuint) {
+    with(|s| {
+        if let Some(block) = s.programs.get_mut(&program).and_then(|p| p.blocks.get_mut(index as usize)) {
+            block.1 = binding;
+        }
+    });
+    record("glUniformBlockBinding", &[program as i64, index as i64, binding as i64]);
+}
+
+extern "system" fn bind_frag_data_location(program: GLuint, color: GLuint, _name: *const GLchar) {
+    record("glBindFragDataLocation", &[program as i64, color as i64]);
+}
+
+// Uniforms
+
+fn record_uniform(func: &'static str, location: GLint, args: &[i64], floats: &[f32]) {
+    with(|s| {
+        let uniform = s.uniform_name(location);
+        let mut call = Call::new(func, &[location as i64]);
+        call.args.extend_from_slice(args);
+        call.floats = floats.to_vec();
+        call.uniform = uniform;
+        s.calls.push(call);
+    });
+}
+
+extern "system" fn uniform_1i(location: GLint, v0: GLint) {
+    record_uniform("glUniform1i", location, &[v0 as i64], &[]);
+}
+
+extern "system" fn uniform_3i(location: GLint, v0: GLint, v1: GLint, v2: GLint) {
+    record_uniform("glUniform3i", location, &[v0 as i64, v1 as i64, v2 as i64], &[]);
+}
+
+extern "system" fn uniform_1f(location: GLint, v0: GLfloat) {
+    record_uniform("glUniform1f", location, &[], &[v0]);
+}
+
+extern "system" fn uniform_2f(location: GLint, v0: GLfloat, v1: GLfloat) {
+    record_uniform("glUniform2f", location, &[], &[v0, v1]);
+}
+
+extern "system" fn uniform_3f(location: GLint, v0: GLfloat, v1: GLfloat, v2: GLfloat) {
+    record_uniform("glUniform3f", location, &[], &[v0, v1, v2]);
+}
+
+extern "system" fn uniform_4f(location: GLint, v0: GLfloat, v1: GLfloat, v2: GLfloat, v3: GLfloat) {
+    record_uniform("glUniform4f", location, &[], &[v0, v1, v2, v3]);
+}
+
+extern "system" fn uniform_matrix_4fv(location: GLint, count: GLsizei, transpose: GLboolean, value: *const GLfloat) {
+    let values = unsafe { slice::from_raw_parts(value, count as usize * 16) };
+    record_uniform("glUniformMatrix4fv", location, &[count as i64, transpose as i64], values);
+}
+
+// Vertex attributes
+
+extern "system" fn enable_vertex_attrib_array(index: GLuint) {
+    record("glEnableVertexAttribArray", &[index as i64]);
+}
+
+extern "system" fn disable_vertex_attrib_array(index: GLuint) {
+    record("glDisableVertexAttribArray", &[index as i64]);
+}
+
+extern "system" fn vertex_attrib_pointer(index: GLuint, size: GLint, ty: GLenum, normalized: GLboolean,
+                                         stride: GLsizei, pointer: *const c_void) {
+    record("glVertexAttribPointer", &[index as i64, size as i64, ty as i64, normalized as i64,
+                                       stride as i64, pointer as i64]);
+}
+
+extern "system" fn vertex_attrib_i_pointer(index: GLuint, size: GLint, ty: GLenum, stride: GLsizei, pointer: *const c_void) {
+    record("glVertexAttribIPointer", &[index as i64, size as i64, ty as i64, stride as i64, pointer as i64]);
+}
+
+extern "system" fn vertex_attrib_divisor(index: GLuint, divisor: GLuint) {
+    record("glVertexAttribDivisor", &[index as i64, divisor as i64]);
+}
+
+extern "system" fn vertex_attrib_4f(index: GLuint, x: GLfloat, y: GLfloat, z: GLfloat, w: GLfloat) {
+    with(|s| s.calls.push(Call {
+        floats: vec![x, y, z, w],
+        ..Call::new("glVertexAttrib4f", &[index as i64])
+    }));
+}
+
+extern "system" fn gen_vertex_arrays(n: GLsizei, arrays: *mut GLuint) {
+    gen_objects("glGenVertexArrays", n, arrays);
+}
+
+extern "system" fn delete_vertex_arrays(n: GLsizei, arrays: *const GLuint) {
+    // Deleting the bound vertex array binds 0
+    let ids = unsafe { slice::from_raw_parts(arrays, n as usize) };
+    with(|s| if ids.contains(&s.vertex_array) {
+        s.vertex_array = 0;
+    });
+    delete_objects("glDeleteVertexArrays", n, arrays);
+}
+
+extern "system" fn bind_vertex_array(array: GLuint) {
+    with(|s| s.vertex_array = array);
+    record("glBindVertexArray", &[array as i64]);
+}
+
+// Buffers
+
+extern "system" fn gen_buffers(n: GLsizei, buffers: *mut GLuint) {
+    gen_objects("glGenBuffers", n, buffers);
+}
+
+extern "system" fn delete_buffers(n: GLsizei, buffers: *const GLuint) {
+    let ids = unsafe { slice::from_raw_parts(buffers, n as usize) };
+    with(|s| ids.iter().for_each(|id| {
+        s.buffer_data.remove(id);
+    }));
+    delete_objects("glDeleteBuffers", n, buffers);
+}
+
+extern "system" fn bind_buffer(target: GLenum, buffer: GLuint) {
+    with(|s| if target == gl::ELEMENT_ARRAY_BUFFER {
+        s.element_buffers.insert(s.vertex_array, buffer);
+    } else {
+        s.buffers.insert(target, buffer);
+    });
+    record("glBindBuffer", &[target as i64, buffer as i64]);
+}
+
+extern "system" fn bind_buffer_base(target: GLenum, index: GLuint, buffer: GLuint) {
+    with(|s| {
+        s.buffers.insert(target, buffer);
+        s.indexed_buffers.insert((target, index), buffer);
+    });
+    record("glBindBufferBase", &[target as i64, index as i64, buffer as i64]);
+}
+
+extern "system" fn buffer_data_fn(target: GLenum, size: GLsizeiptr, data: *const c_void, usage: GLenum) {
+    with(|s| {
+        let buffer = s.bound_buffer(target);
+        let data = if data.is_null() {
+            vec![0; size as usize]
+        } else {
+            unsafe { slice::from_raw_parts(data as *const u8, size as usize).to_vec() }
+        };
+        s.buffer_data.insert(buffer, data);
+    });
+    record("glBufferData", &[target as i64, size as i64, usage as i64]);
+}
+
+extern "system" fn buffer_sub_data(target: GLenum, offset: GLintptr, size: GLsizeiptr, data: *const c_void) {
+    with(|s| {
+        let buffer = s.bound_buffer(target);
+        let src = unsafe { slice::from_raw_parts(data as *const u8, size as usize) };
+        let dst = s.buffer_data.entry(buffer).or_default();
+        let end = offset as usize + size as usize;
+        if dst.len() < end {
+            dst.resize(end, 0);
+        }
+        dst[offset as usize..end].copy_from_slice(src);
+    });
+    record("glBufferSubData", &[target as i64, offset as i64, size as i64]);
+}
+
+extern "system" fn map_buffer(target: GLenum, access: GLenum) -> *mut c_void {
+    record("glMapBuffer", &[target as i64, access as i64]);
+    // Writes go straight into the tracked data
+    with(|s| {
+        let buffer = s.bound_buffer(target);
+        s.buffer_data.entry(buffer).or_default().as_mut_ptr() as *mut c_void
+    })
+}
+
+extern "system" fn map_buffer_range(target: GLenum, offset: GLintptr, length: GLsizeiptr, access: GLbitfield) -> *mut c_void {
+    record("glMapBufferRange", &[target as i64, offset as i64, length as i64, access as i64]);
+    with(|s| {
+        let buffer = s.bound_buffer(target);
+        let data = s.buffer_data.entry(buffer).or_default();
+        let end = offset as usize + length as usize;
+        if data.len() < end {
+            data.resize(end, 0);
+        }
+        data[offset as usize..].as_mut_ptr() as *mut c_void
+    })
+}
+
+extern "system" fn unmap_buffer(target: GLenum) -> GLboolean {
+    record("glUnmapBuffer", &[target as i64]);
+    gl::TRUE
+}
+
+// Framebuffers
+
+extern "system" fn gen_framebuffers(n: GLsizei, framebuffers: *mut GLuint) {
+    gen_objects("glGenFramebuffers", n, framebuffers);
+}
+
+extern "system" fn delete_framebuffers(n: GLsizei, framebuffers: *const GLuint) {
+    delete_objects("glDeleteFramebuffers", n, framebuffers);
+}
+
+extern "system" fn bind_framebuffer(target: GLenum, framebuffer: GLuint) {
+    with(|s| {
+        if target != gl::DRAW_FRAMEBUFFER {
+            s.read_framebuffer = framebuffer;
+        }
+        if target != gl::READ_FRAMEBUFFER {
+            s.draw_framebuffer = framebuffer;
+        }
+    });
+    record("glBindFramebuffer", &[target as i64, framebuffer as i64]);
+}
+
+extern "system" fn framebuffer_texture_2d(target: GLenum, attachment: GLenum, textarget: GLenum, texture: GLuint, level: GLint) {
+    record("glFramebufferTexture2D", &[target as i64, attachment as i64, textarget as i64, texture as i64, level as i64]);
+}
+
+extern "system" fn check_framebuffer_status(_target: GLenum) -> GLenum {
+    gl::FRAMEBUFFER_COMPLETE
+}
+
+extern "system" fn draw_buffers(n: GLsizei, bufs: *const GLenum) {
+    let bufs = unsafe { slice::from_raw_parts(bufs, n as usize) };
+    record("glDrawBuffers", &bufs.iter().map(|v| *v as i64).collect::<Vec<_>>());
+}
+
+extern "system" fn blit_framebuffer(sx0: GLint, sy0: GLint, sx1: GLint, sy1: GLint,
+                                    dx0: GLint, dy0: GLint, dx1: GLint, dy1: GLint,
+                                    mask: GLbitfield, filter: GLenum) {
+    record("glBlitFramebuffer", &[sx0 as i64, sy0 as i64, sx1 as i64, sy1 as i64,
+                                   dx0 as i64, dy0 as i64, dx1 as i64, dy1 as i64, mask as i64, filter as i64]);
+}
+
+extern "system" fn read_buffer(src: GLenum) {
+    record("glReadBuffer", &[src as i64]);
+}
+
+extern "system" fn read_pixels(x: GLint, y: GLint, width: GLsizei, height: GLsizei, format: GLenum, ty: GLenum, _pixels: *mut c_void) {
+    record("glReadPixels", &[x as i64, y as i64, width as i64, height as i64, format as i64, ty as i64]);
+}
+
+extern "system" fn clear_bufferfv(buffer: GLenum, draw_buffer: GLint, value: *const GLfloat) {
+    let values = unsafe { slice::from_raw_parts(value, 4) };
+    with(|s| s.calls.push(Call {
+        floats: values.to_vec(),
+        ..Call::new("glClearBufferfv", &[buffer as i64, draw_buffer as i64])
+    }));
+}
+
+extern "system" fn framebuffer_renderbuffer(target: GLenum, attachment: GLenum, rb_target: GLenum, renderbuffer: GLuint) {
+    record("glFramebufferRenderbuffer", &[target as i64, attachment as i64, rb_target as i64, renderbuffer as i64]);
+}
+
+// Renderbuffers
+
+extern "system" fn gen_renderbuffers(n: GLsizei, renderbuffers: *mut GLuint) {
+    gen_objects("glGenRenderbuffers", n, renderbuffers);
+}
+
+extern "system" fn delete_renderbuffers(n: GLsizei, renderbuffers: *const GLuint) {
+    delete_objects("glDeleteRenderbuffers", n, renderbuffers);
+}
+
+extern "system" fn bind_renderbuffer(target: GLenum, renderbuffer: GLuint) {
+    with(|s| s.renderbuffer = renderbuffer);
+    record("glBindRenderbuffer", &[target as i64, renderbuffer as i64]);
+}
+
+extern "system" fn renderbuffer_storage(target: GLenum, format: GLenum, width: GLsizei, height: GLsizei) {
+    record("glRenderbufferStorage", &[target as i64, format as i64, width as i64, height as i64]);
+}
+
+extern "system" fn renderbuffer_storage_multisample(target: GLenum, samples: GLsizei, format: GLenum, width: GLsizei, height: GLsizei) {
+    record("glRenderbufferStorageMultisample", &[target as i64, samples as i64, format as i64, width as i64, height as i64]);
+}
+
+// Sync objects
+
+extern "system" fn fence_sync(condition: GLenum, flags: GLbitfield) -> GLsync {
+    let id = with(|s| s.gen());
+    record("glFenceSync", &[condition as i64, flags as i64, id as i64]);
+    id as usize as GLsync
+}
+
+/// Fences are always signaled, unless `fail_waits` was called.
+extern "system" fn client_wait_sync(sync: GLsync, flags: GLbitfield, timeout: GLuint64) -> GLenum {
+    record("glClientWaitSync", &[sync as usize as i64, flags as i64, timeout as i64]);
+    if with(|s| s.fail_waits) {
+        gl::WAIT_FAILED
+    } else {
+        gl::ALREADY_SIGNALED
+    }
+}
+
+extern "system" fn delete_sync(sync: GLsync) {
+    with(|s| s.delete(sync as usize as GLuint));
+    record("glDeleteSync", &[sync as usize as i64]);
+}
+
+// Query objects
+
+/// 1ms, the result of every time query.
+const QUERY_TIME: u64 = 1_000_000;
+
+extern "system" fn gen_queries(n: GLsizei, ids: *mut GLuint) {
+    gen_objects("glGenQueries", n, ids);
+    let ids = unsafe { slice::from_raw_parts(ids, n as usize) };
+    with(|s| s.unused_queries.extend(ids));
+}
+
+extern "system" fn delete_queries(n: GLsizei, ids: *const GLuint) {
+    delete_objects("glDeleteQueries", n, ids);
+}
+
+extern "system" fn begin_query(target: GLenum, id: GLuint) {
+    with(|s| {
+        let start = s.calls.len();
+        s.active_queries.insert(target, (id, start));
+        s.unused_queries.remove(&id);
+    });
+    record("glBeginQuery", &[target as i64, id as i64]);
+}
+
+extern "system" fn end_query(target: GLenum) {
+    with(|s| {
+        if let Some((id, start)) = s.active_queries.remove(&target) {
+            let result = match target {
+                gl::TIME_ELAPSED => QUERY_TIME,
+                _ => {
+                    // The calls may have been cleared since
+                    let draws = s.calls.get(start..).unwrap_or(&[]).iter().filter(|v| v.func.starts_with("glDraw")).count() as u64;
+                    if target == gl::SAMPLES_PASSED { draws } else { draws.min(1) }
+                },
+            };
+            s.query_results.insert(id, result);
+        }
+    });
+    record("glEndQuery", &[target as i64]);
+}
+
+extern "system" fn query_counter(id: GLuint, target: GLenum) {
+    with(|s| {
+        s.timestamp += QUERY_TIME;
+        let time = s.timestamp;
+        s.query_results.insert(id, time);
+        s.unused_queries.remove(&id);
+    });
+    record("glQueryCounter", &[id as i64, target as i64]);
+}
+
+fn query_object(id: GLuint, pname: GLenum) -> u64 {
+    with(|s| match pname {
+        gl::QUERY_RESULT_AVAILABLE => s.query_results.contains_key(&id) as u64,
+        gl::QUERY_RESULT => s.query_results.get(&id).cloned().unwrap_or(0),
+        _ => 0,
+    })
+}
+
+extern "system" fn get_query_objectuiv(id: GLuint, pname: GLenum, params: *mut GLuint) {
+    unsafe {
+        *params = query_object(id, pname) as GLuint;
+    }
+}
+
+extern "system" fn get_query_objectui64v(id: GLuint, pname: GLenum, params: *mut GLuint64) {
+    unsafe {
+        *params = query_object(id, pname);
+    }
+}
@@ -13,6 +13,7 @@
 // limitations under the License.
 
 extern crate steven_gl as gl;
+#[cfg(feature = "sdl")]
 use sdl2;
 
 use std::ops::BitOr;
@@ -20,46 +21,236 @@ use std::ffi;
 use std::mem;
 use std::ptr;
 use std::ops::{Deref, DerefMut};
+use std::time::Duration;
+
+#[macro_use]
+pub mod trace;
+mod debug;
+pub use self::debug::{DebugSource, DebugType, DebugSeverity, DebugMessage,
+                      set_debug_callback, print_debug_message};
+pub mod caps;
+pub mod quirks;
+mod error;
+#[cfg(feature = "mock")]
+pub mod mock;
+mod state;
+pub mod std140;
+pub use self::state::{StateStats, invalidate_state_cache, set_state_cache, take_state_stats};
+use self::state::Slot;
+pub use self::error::{GlError, FramebufferError, get_error, gl_errors, check_gl_error,
+                      framebuffer_status, check_framebuffer_status, set_debug_checks, debug_checks};
+use self::error::debug_check;
+
+/// Declares a wrapper around a gl enum so that a value for one kind of
+/// parameter can't be passed as another, e.g. a `TextureTarget` where
+/// a `BufferTarget` is expected.
+macro_rules! gl_enum {
+    ($(#[$attr:meta])* $name:ident) => (
+        gl_enum!($(#[$attr])* $name: u32);
+    );
+    ($(#[$attr:meta])* $name:ident: $raw:ty) => (
+        $(#[$attr])*
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+        #[repr(transparent)]
+        pub struct $name($raw);
+
+        impl $name {
+            /// Returns the raw gl value.
+            pub const fn raw(self) -> $raw {
+                self.0
+            }
+        }
+
+        impl From<$name> for $raw {
+            fn from(v: $name) -> $raw {
+                v.0
+            }
+        }
+
+        impl trace::Arg for $name {
+            fn encode(&self, out: &mut Vec<u8>) {
+                self.0.encode(out)
+            }
+        }
+    );
+}
 
 /// Inits the gl library. This should be called once a context is ready.
+#[cfg(feature = "sdl")]
 pub fn init(vid: & sdl2::VideoSubsystem) {
-    gl::load_with(|s| vid.gl_get_proc_address(s) as *const _);
+    init_with(|s| vid.gl_get_proc_address(s) as *const _);
 }
 
-/// Dsed to specify how the vertices will be handled
-/// to draw.
-pub type DrawType = u32;
+/// Inits the gl library using the passed function to look up
+/// each gl function. Used for contexts that aren't created by
+/// sdl, e.g. headless ones.
+pub fn init_with<F>(loader: F)
+    where F: FnMut(&str) -> *const ffi::c_void {
+    gl::load_with(loader);
+    state::invalidate_state_cache();
+    debug::init();
+    quirks::init();
+    caps::init();
+    trace::init();
+}
+
+gl_enum! {
+    /// `StringName` is a string describing the current context.
+    StringName
+}
+
+pub const VENDOR: StringName = StringName(gl::VENDOR);
+pub const RENDERER: StringName = StringName(gl::RENDERER);
+pub const VERSION: StringName = StringName(gl::VERSION);
+pub const SHADING_LANGUAGE_VERSION: StringName = StringName(gl::SHADING_LANGUAGE_VERSION);
+
+/// Returns the passed string describing the current context.
+#[track_caller]
+pub fn get_string(name: StringName) -> String {
+    let _check = debug_check("glGetString");
+    unsafe {
+        let s = gl::GetString(name.0);
+        if s.is_null() {
+            return String::new();
+        }
+        ffi::CStr::from_ptr(s as *const _).to_string_lossy().into_owned()
+    }
+}
+
+gl_enum! {
+    /// `IntegerParameter` is a value of the context that can be queried.
+    IntegerParameter
+}
+
+pub const MAJOR_VERSION: IntegerParameter = IntegerParameter(gl::MAJOR_VERSION);
+pub const MINOR_VERSION: IntegerParameter = IntegerParameter(gl::MINOR_VERSION);
+pub const NUM_EXTENSIONS: IntegerParameter = IntegerParameter(gl::NUM_EXTENSIONS);
+pub const CONTEXT_FLAGS: IntegerParameter = IntegerParameter(gl::CONTEXT_FLAGS);
+/// Set in `CONTEXT_FLAGS` for debug contexts.
+pub const CONTEXT_FLAG_DEBUG: i32 = gl::CONTEXT_FLAG_DEBUG_BIT as i32;
+
+pub const MAX_SAMPLES: IntegerParameter = IntegerParameter(gl::MAX_SAMPLES);
+pub const MAX_COLOR_TEXTURE_SAMPLES: IntegerParameter = IntegerParameter(gl::MAX_COLOR_TEXTURE_SAMPLES);
+pub const MAX_DEPTH_TEXTURE_SAMPLES: IntegerParameter = IntegerParameter(gl::MAX_DEPTH_TEXTURE_SAMPLES);
+pub const MAX_TEXTURE_SIZE: IntegerParameter = IntegerParameter(gl::MAX_TEXTURE_SIZE);
+pub const MAX_ARRAY_TEXTURE_LAYERS: IntegerParameter = IntegerParameter(gl::MAX_ARRAY_TEXTURE_LAYERS);
+pub const MAX_DRAW_BUFFERS: IntegerParameter = IntegerParameter(gl::MAX_DRAW_BUFFERS);
+pub const MAX_COLOR_ATTACHMENTS: IntegerParameter = IntegerParameter(gl::MAX_COLOR_ATTACHMENTS);
+pub const MAX_VERTEX_ATTRIBS: IntegerParameter = IntegerParameter(gl::MAX_VERTEX_ATTRIBS);
+pub const MAX_VERTEX_UNIFORM_COMPONENTS: IntegerParameter = IntegerParameter(gl::MAX_VERTEX_UNIFORM_COMPONENTS);
+pub const MAX_FRAGMENT_UNIFORM_COMPONENTS: IntegerParameter = IntegerParameter(gl::MAX_FRAGMENT_UNIFORM_COMPONENTS);
+pub const MAX_COMBINED_TEXTURE_IMAGE_UNITS: IntegerParameter = IntegerParameter(gl::MAX_COMBINED_TEXTURE_IMAGE_UNITS);
+
+#[track_caller]
+pub fn get_integer(param: IntegerParameter) -> i32 {
+    let _check = debug_check("glGetIntegerv");
+    let mut ret = 0;
+    unsafe {
+        gl::GetIntegerv(param.0, &mut ret);
+    }
+    ret
+}
+
+/// Returns the (major, minor) version of the current context.
+pub fn version() -> (i32, i32) {
+    (get_integer(MAJOR_VERSION), get_integer(MINOR_VERSION))
+}
+
+/// Returns the names of the extensions supported by the current
+/// context.
+pub fn extensions() -> Vec<String> {
+    (0..get_integer(NUM_EXTENSIONS))
+        .map(|i| unsafe {
+            let s = gl::GetStringi(gl::EXTENSIONS, i as u32);
+            ffi::CStr::from_ptr(s as *const _).to_string_lossy().into_owned()
+        })
+        .collect()
+}
+
+pub fn has_extension(name: &str) -> bool {
+    extensions().iter().any(|v| v == name)
+}
+
+gl_enum! {
+    /// Dsed to specify how the vertices will be handled
+    /// to draw.
+    DrawType
+}
 
 /// Treats each set of 3 vertices as a triangle
-pub const TRIANGLES: DrawType = gl::TRIANGLES;
+pub const TRIANGLES: DrawType = DrawType(gl::TRIANGLES);
 /// Means the previous vertex connects to the next
 /// one in a continuous strip.
-pub const LINE_STRIP: DrawType = gl::LINE_STRIP;
+pub const LINE_STRIP: DrawType = DrawType(gl::LINE_STRIP);
 /// Treats each set of 2 vertices as a line
-pub const LINES: DrawType = gl::LINES;
+pub const LINES: DrawType = DrawType(gl::LINES);
 /// Treats each vertex as a point
-pub const POINTS: DrawType = gl::POINTS;
+pub const POINTS: DrawType = DrawType(gl::POINTS);
 
+#[track_caller]
 pub fn draw_arrays(ty: DrawType, offset: usize, count: usize) {
+    let _check = debug_check("glDrawArrays");
+    trace_call!(DrawArrays, ty, offset, count);
     unsafe {
-        gl::DrawArrays(ty, offset as i32, count as i32);
+        gl::DrawArrays(ty.0, offset as i32, count as i32);
     }
 }
 
+#[track_caller]
 pub fn draw_elements(ty: DrawType, count: i32, dty: Type, offset: usize) {
+    let _check = debug_check("glDrawElements");
+    trace_call!(DrawElements, ty, count, dty, offset);
     unsafe {
-        gl::DrawElements(ty, count, dty, offset as *const gl::types::GLvoid);
+        gl::DrawElements(ty.0, count, dty.0, offset as *const gl::types::GLvoid);
     }
 }
 
+/// Draws `instances` copies of the vertices, attributes with a divisor
+/// advance per instance.
+#[track_caller]
+pub fn draw_arrays_instanced(ty: DrawType, offset: usize, count: usize, instances: usize) {
+    let _check = debug_check("glDrawArraysInstanced");
+    trace_call!(DrawArraysInstanced, ty, offset, count, instances);
+    unsafe {
+        gl::DrawArraysInstanced(ty.0, offset as i32, count as i32, instances as i32);
+    }
+}
+
+#[track_caller]
+pub fn draw_elements_instanced(ty: DrawType, count: i32, dty: Type, offset: usize, instances: usize) {
+    let _check = debug_check("glDrawElementsInstanced");
+    trace_call!(DrawElementsInstanced, ty, count, dty, offset, instances);
+    unsafe {
+        gl::DrawElementsInstanced(ty.0, count, dty.0, offset as *const gl::types::GLvoid, instances as i32);
+    }
+}
+
+/// Like `draw_elements` but `base_vertex` is added to each index
+/// before fetching the vertex, letting several meshes share one
+/// index buffer.
+#[track_caller]
+pub fn draw_elements_base_vertex(ty: DrawType, count: i32, dty: Type, offset: usize, base_vertex: i32) {
+    let _check = debug_check("glDrawElementsBaseVertex");
+    trace_call!(DrawElementsBaseVertex, ty, count, dty, offset, base_vertex);
+    unsafe {
+        gl::DrawElementsBaseVertex(ty.0, count, dty.0, offset as *const gl::types::GLvoid, base_vertex);
+    }
+}
+
+#[track_caller]
 pub fn multi_draw_elements(ty: DrawType, count: &[i32], dty: Type, offsets: &[usize]) {
+    let _check = debug_check("glMultiDrawElements");
+    trace_call!(MultiDrawElements, ty, count, dty, offsets);
     unsafe {
-        gl::MultiDrawElements(ty, count.as_ptr(), dty, offsets.as_ptr() as *const _, count.len() as i32);
+        gl::MultiDrawElements(ty.0, count.as_ptr(), dty.0, offsets.as_ptr() as *const _, count.len() as i32);
     }
 }
 
 /// Sets the size of the viewport of this context.
+#[track_caller]
 pub fn viewport(x: i32, y: i32, w: i32, h: i32) {
+    let _check = debug_check("glViewport");
+    trace_call!(Viewport, x, y, w, h);
     unsafe {
         gl::Viewport(x, y, w, h);
     }
@@ -67,12 +258,25 @@ pub fn viewport(x: i32, y: i32, w: i32, h: i32) {
 
 /// Sets the color the color buffer should be cleared to
 /// when Clear is called with the color flag.
+#[track_caller]
 pub fn clear_color(r: f32, g: f32, b: f32, a: f32) {
+    let _check = debug_check("glClearColor");
+    trace_call!(ClearColor, r, g, b, a);
     unsafe {
         gl::ClearColor(r, g, b, a);
     }
 }
 
+/// Sets the depth the depth buffer is cleared to.
+#[track_caller]
+pub fn clear_depth(depth: f32) {
+    let _check = debug_check("glClearDepth");
+    trace_call!(ClearDepth, depth);
+    unsafe {
+        gl::ClearDepth(depth as f64);
+    }
+}
+
 /// `ClearFlags` is a set of flags to mark what should be cleared during
 /// a Clear call.
 pub enum ClearFlags {
@@ -102,189 +306,294 @@ impl BitOr for ClearFlags {
 }
 
 /// Clears the buffers specified by the passed flags.
+#[track_caller]
 pub fn clear(flags: ClearFlags) {
-    unsafe { gl::Clear(flags.internal()) }
+    let _check = debug_check("glClear");
+    let flags = flags.internal();
+    trace_call!(Clear, flags);
+    unsafe { gl::Clear(flags) }
 }
 
+#[track_caller]
 pub fn depth_mask(f: bool) {
+    let _check = debug_check("glDepthMask");
+    trace_call!(DepthMask, f);
     unsafe { gl::DepthMask(f as u8); }
 }
 
-/// `Func` is a function to be preformed on two values.
-pub type Func = u32;
+gl_enum! {
+    /// `Func` is a function to be preformed on two values.
+    Func
+}
 
-pub const NEVER: Func = gl::NEVER;
-pub const LESS: Func = gl::LESS;
-pub const LESS_OR_EQUAL: Func = gl::LEQUAL;
-pub const GREATER: Func = gl::GREATER;
-pub const ALWAYS: Func = gl::ALWAYS;
-pub const EQUAL: Func = gl::EQUAL;
+pub const NEVER: Func = Func(gl::NEVER);
+pub const LESS: Func = Func(gl::LESS);
+pub const LESS_OR_EQUAL: Func = Func(gl::LEQUAL);
+pub const GREATER: Func = Func(gl::GREATER);
+pub const GREATER_OR_EQUAL: Func = Func(gl::GEQUAL);
+pub const ALWAYS: Func = Func(gl::ALWAYS);
+pub const EQUAL: Func = Func(gl::EQUAL);
 
+#[track_caller]
 pub fn depth_func(f: Func) {
+    let _check = debug_check("glDepthFunc");
+    trace_call!(DepthFunc, f);
+    unsafe {
+        gl::DepthFunc(f.0);
+    }
+}
+
+gl_enum! {
+    /// `ClipDepth` is the range of normalized device depth mapped onto
+    /// the depth range.
+    ClipDepth
+}
+
+pub const NEGATIVE_ONE_TO_ONE: ClipDepth = ClipDepth(gl::NEGATIVE_ONE_TO_ONE);
+pub const ZERO_TO_ONE: ClipDepth = ClipDepth(gl::ZERO_TO_ONE);
+
+/// Sets the clip space depth range, the origin is always the lower
+/// left. Needs GL 4.5 or `GL_ARB_clip_control`, see
+/// `caps::Cap::ClipControl`.
+#[track_caller]
+pub fn clip_control(depth: ClipDepth) {
+    let _check = debug_check("glClipControl");
+    trace_call!(ClipControl, depth);
     unsafe {
-        gl::DepthFunc(f);
+        gl::ClipControl(gl::LOWER_LEFT, depth.0);
     }
 }
 
-/// Flag is a setting that can be enabled or disabled on the context.
-pub type Flag = u32;
+gl_enum! {
+    /// Flag is a setting that can be enabled or disabled on the context.
+    Flag
+}
 
-pub const DEPTH_TEST: Flag = gl::DEPTH_TEST;
-pub const CULL_FACE_FLAG: Flag = gl::CULL_FACE;
-pub const STENCIL_TEST: Flag = gl::STENCIL_TEST;
-pub const BLEND: Flag = gl::BLEND;
-pub const MULTISAMPLE: Flag = gl::MULTISAMPLE;
+pub const DEPTH_TEST: Flag = Flag(gl::DEPTH_TEST);
+pub const CULL_FACE_FLAG: Flag = Flag(gl::CULL_FACE);
+pub const STENCIL_TEST: Flag = Flag(gl::STENCIL_TEST);
+pub const BLEND: Flag = Flag(gl::BLEND);
+pub const MULTISAMPLE: Flag = Flag(gl::MULTISAMPLE);
 
 /// Enables the passed flag.
+#[track_caller]
 pub fn enable(f: Flag) {
+    if !state::set(&[Slot::Flag(f)], 1) {
+        return;
+    }
+    let _check = debug_check("glEnable");
+    trace_call!(Enable, f);
     unsafe {
-        gl::Enable(f);
+        gl::Enable(f.0);
     }
 }
 
 /// Disables the passed flag.
+#[track_caller]
 pub fn disable(f: Flag) {
+    if !state::set(&[Slot::Flag(f)], 0) {
+        return;
+    }
+    let _check = debug_check("glDisable");
+    trace_call!(Disable, f);
     unsafe {
-        gl::Disable(f);
+        gl::Disable(f.0);
     }
 }
 
 /// Sets the texture slot with the passed id as the
 /// currently active one.
+#[track_caller]
 pub fn active_texture(id: u32) {
+    if !state::set(&[Slot::ActiveTexture], id) {
+        return;
+    }
+    let _check = debug_check("glActiveTexture");
+    trace_call!(ActiveTexture, id);
     unsafe {
         gl::ActiveTexture(gl::TEXTURE0 + id);
     }
 }
 
-/// `Factor` is used in blending
-pub type Factor = u32;
-pub const SRC_ALPHA: Factor = gl::SRC_ALPHA;
-pub const ONE_MINUS_SRC_ALPHA: Factor = gl::ONE_MINUS_SRC_ALPHA;
-pub const ONE_FACTOR: Factor = gl::ONE;
-pub const ZERO_FACTOR: Factor = gl::ZERO;
+gl_enum! {
+    /// `Factor` is used in blending
+    Factor
+}
+pub const SRC_ALPHA: Factor = Factor(gl::SRC_ALPHA);
+pub const ONE_MINUS_SRC_ALPHA: Factor = Factor(gl::ONE_MINUS_SRC_ALPHA);
+pub const ONE_FACTOR: Factor = Factor(gl::ONE);
+pub const ZERO_FACTOR: Factor = Factor(gl::ZERO);
 
 /// Sets the factors to be used when blending.
+#[track_caller]
 pub fn blend_func(s_factor: Factor, d_factor: Factor) {
+    let _check = debug_check("glBlendFunc");
+    trace_call!(BlendFunc, s_factor, d_factor);
     unsafe {
-        gl::BlendFunc(s_factor, d_factor);
+        gl::BlendFunc(s_factor.0, d_factor.0);
     }
 }
 
+#[track_caller]
 pub fn blend_func_separate(s_factor_rgb: Factor, d_factor_rgb: Factor, s_factor_a: Factor, d_factor_a: Factor) {
+    let _check = debug_check("glBlendFuncSeparate");
+    trace_call!(BlendFuncSeparate, s_factor_rgb, d_factor_rgb, s_factor_a, d_factor_a);
     unsafe {
-        gl::BlendFuncSeparate(s_factor_rgb, d_factor_rgb, s_factor_a, d_factor_a);
+        gl::BlendFuncSeparate(s_factor_rgb.0, d_factor_rgb.0, s_factor_a.0, d_factor_a.0);
     }
 }
 
-// Face specifies a face to act on.
-pub type Face = u32;
-pub const BACK: Face = gl::BACK;
-pub const FRONT: Face = gl::FRONT;
+gl_enum! {
+    /// `Face` specifies a face to act on.
+    Face
+}
+pub const BACK: Face = Face(gl::BACK);
+pub const FRONT: Face = Face(gl::FRONT);
 
 /// Sets the face to be culled by the gpu.
+#[track_caller]
 pub fn cull_face(face: Face) {
+    let _check = debug_check("glCullFace");
+    trace_call!(CullFace, face);
     unsafe {
-        gl::CullFace(face);
+        gl::CullFace(face.0);
     }
 }
 
-// FaceDirection is used to specify an order of vertices, normally
-// used to set which is considered to be the front face.
-pub type FaceDirection = u32;
-pub const CLOCK_WISE: FaceDirection = gl::CW;
-pub const COUNTER_CLOCK_WISE: FaceDirection = gl::CCW;
+gl_enum! {
+    /// `FaceDirection` is used to specify an order of vertices, normally
+    /// used to set which is considered to be the front face.
+    FaceDirection
+}
+pub const CLOCK_WISE: FaceDirection = FaceDirection(gl::CW);
+pub const COUNTER_CLOCK_WISE: FaceDirection = FaceDirection(gl::CCW);
 
 /// Sets the direction of vertices used to specify the
 /// front face (e.g. for culling).
+#[track_caller]
 pub fn front_face(dir: FaceDirection) {
-    unsafe { gl::FrontFace(dir) }
-}
-
-/// `Type` is a type of data used by various operations.
-pub type Type = u32;
-pub const UNSIGNED_BYTE: Type = gl::UNSIGNED_BYTE;
-pub const UNSIGNED_SHORT: Type = gl::UNSIGNED_SHORT;
-pub const UNSIGNED_INT: Type = gl::UNSIGNED_INT;
-pub const SHORT: Type = gl::SHORT;
-pub const FLOAT: Type = gl::FLOAT;
-
-/// `TextureTarget` is a target were a texture can be bound to
-pub type TextureTarget = u32;
-
-pub const TEXTURE_2D: TextureTarget = gl::TEXTURE_2D;
-pub const TEXTURE_2D_MULTISAMPLE: TextureTarget = gl::TEXTURE_2D_MULTISAMPLE;
-pub const TEXTURE_2D_ARRAY: TextureTarget = gl::TEXTURE_2D_ARRAY;
-pub const TEXTURE_3D: TextureTarget = gl::TEXTURE_3D;
-
-/// `TextureFormat` is the format of a texture either internally or
-/// to be uploaded.
-pub type TextureFormat = u32;
-
-pub const RED: TextureFormat = gl::RED;
-pub const RGB: TextureFormat = gl::RGB;
-pub const RGBA: TextureFormat = gl::RGBA;
-pub const RGBA8: TextureFormat = gl::RGBA8;
-pub const RGBA16F: TextureFormat = gl::RGBA16F;
-pub const R16F: TextureFormat = gl::R16F;
-pub const DEPTH_COMPONENT24: TextureFormat = gl::DEPTH_COMPONENT24;
-pub const DEPTH_COMPONENT: TextureFormat = gl::DEPTH_COMPONENT;
-
-/// `TextureParameter` is a parameter that can be read or set on a texture.
-pub type TextureParameter = u32;
-
-pub const TEXTURE_MIN_FILTER: TextureParameter = gl::TEXTURE_MIN_FILTER;
-pub const TEXTURE_MAG_FILTER: TextureParameter = gl::TEXTURE_MAG_FILTER;
-pub const TEXTURE_WRAP_S: TextureParameter = gl::TEXTURE_WRAP_S;
-pub const TEXTURE_WRAP_T: TextureParameter = gl::TEXTURE_WRAP_T;
-pub const TEXTURE_MAX_LEVEL: TextureParameter = gl::TEXTURE_MAX_LEVEL;
-
-/// `TextureValue` is a value that be set on a texture's parameter.
-pub type TextureValue = i32;
-
-pub const NEAREST: TextureValue = gl::NEAREST as TextureValue;
-pub const LINEAR: TextureValue = gl::LINEAR as TextureValue;
-pub const LINEAR_MIPMAP_LINEAR: TextureValue = gl::LINEAR_MIPMAP_LINEAR as TextureValue;
-pub const LINEAR_MIPMAP_NEAREST: TextureValue = gl::LINEAR_MIPMAP_NEAREST as TextureValue;
-pub const NEAREST_MIPMAP_NEAREST: TextureValue = gl::NEAREST_MIPMAP_NEAREST as TextureValue;
-pub const NEAREST_MIPMAP_LINEAR: TextureValue = gl::NEAREST_MIPMAP_LINEAR as TextureValue;
-pub const CLAMP_TO_EDGE: TextureValue = gl::CLAMP_TO_EDGE as TextureValue;
+    let _check = debug_check("glFrontFace");
+    trace_call!(FrontFace, dir);
+    unsafe { gl::FrontFace(dir.0) }
+}
+
+gl_enum! {
+    /// `Type` is a type of data used by various operations.
+    Type
+}
+pub const UNSIGNED_BYTE: Type = Type(gl::UNSIGNED_BYTE);
+pub const UNSIGNED_SHORT: Type = Type(gl::UNSIGNED_SHORT);
+pub const UNSIGNED_INT: Type = Type(gl::UNSIGNED_INT);
+pub const SHORT: Type = Type(gl::SHORT);
+pub const FLOAT: Type = Type(gl::FLOAT);
+
+gl_enum! {
+    /// `TextureTarget` is a target were a texture can be bound to
+    TextureTarget
+}
+
+pub const TEXTURE_2D: TextureTarget = TextureTarget(gl::TEXTURE_2D);
+pub const TEXTURE_2D_MULTISAMPLE: TextureTarget = TextureTarget(gl::TEXTURE_2D_MULTISAMPLE);
+pub const TEXTURE_2D_ARRAY: TextureTarget = TextureTarget(gl::TEXTURE_2D_ARRAY);
+pub const TEXTURE_3D: TextureTarget = TextureTarget(gl::TEXTURE_3D);
+
+gl_enum! {
+    /// `TextureFormat` is the format of a texture either internally or
+    /// to be uploaded.
+    TextureFormat
+}
+
+pub const RED: TextureFormat = TextureFormat(gl::RED);
+pub const RGB: TextureFormat = TextureFormat(gl::RGB);
+pub const RGBA: TextureFormat = TextureFormat(gl::RGBA);
+pub const RGBA8: TextureFormat = TextureFormat(gl::RGBA8);
+pub const RGBA16F: TextureFormat = TextureFormat(gl::RGBA16F);
+pub const R16F: TextureFormat = TextureFormat(gl::R16F);
+pub const DEPTH_COMPONENT24: TextureFormat = TextureFormat(gl::DEPTH_COMPONENT24);
+pub const DEPTH_COMPONENT32F: TextureFormat = TextureFormat(gl::DEPTH_COMPONENT32F);
+pub const DEPTH_COMPONENT: TextureFormat = TextureFormat(gl::DEPTH_COMPONENT);
+
+gl_enum! {
+    /// `TextureParameter` is a parameter that can be read or set on a texture.
+    TextureParameter
+}
+
+pub const TEXTURE_MIN_FILTER: TextureParameter = TextureParameter(gl::TEXTURE_MIN_FILTER);
+pub const TEXTURE_MAG_FILTER: TextureParameter = TextureParameter(gl::TEXTURE_MAG_FILTER);
+pub const TEXTURE_WRAP_S: TextureParameter = TextureParameter(gl::TEXTURE_WRAP_S);
+pub const TEXTURE_WRAP_T: TextureParameter = TextureParameter(gl::TEXTURE_WRAP_T);
+pub const TEXTURE_MAX_LEVEL: TextureParameter = TextureParameter(gl::TEXTURE_MAX_LEVEL);
+
+gl_enum! {
+    /// `TextureValue` is a value that be set on a texture's parameter.
+    TextureValue: i32
+}
+
+pub const NEAREST: TextureValue = TextureValue(gl::NEAREST as i32);
+pub const LINEAR: TextureValue = TextureValue(gl::LINEAR as i32);
+pub const LINEAR_MIPMAP_LINEAR: TextureValue = TextureValue(gl::LINEAR_MIPMAP_LINEAR as i32);
+pub const LINEAR_MIPMAP_NEAREST: TextureValue = TextureValue(gl::LINEAR_MIPMAP_NEAREST as i32);
+pub const NEAREST_MIPMAP_NEAREST: TextureValue = TextureValue(gl::NEAREST_MIPMAP_NEAREST as i32);
+pub const NEAREST_MIPMAP_LINEAR: TextureValue = TextureValue(gl::NEAREST_MIPMAP_LINEAR as i32);
+pub const CLAMP_TO_EDGE: TextureValue = TextureValue(gl::CLAMP_TO_EDGE as i32);
 
 /// `Texture` is a buffer of data used by fragment shaders.
 pub struct Texture(u32);
 
 impl Texture {
     // Allocates a new texture.
+    #[track_caller]
     pub fn new() -> Texture {
+        let _check = debug_check("glGenTextures");
         let mut t = Texture(0);
         unsafe {
             gl::GenTextures(1, &mut t.0);
         }
+        trace_call!(GenTexture, t.0);
         t
     }
 
+    /// Names the texture in driver debug messages and debugging tools.
+    #[track_caller]
+    pub fn label(&self, label: &str) {
+        debug::object_label(gl::TEXTURE, self.0, label);
+    }
+
     /// Binds the texture to the passed target.
+    #[track_caller]
     pub fn bind(&self, target: TextureTarget) {
+        // Unknown units can't be cached
+        if let Some(unit) = state::get(Slot::ActiveTexture) {
+            if !state::set(&[Slot::Texture(unit, target)], self.0) {
+                return;
+            }
+        }
+        let _check = debug_check("glBindTexture");
+        trace_call!(BindTexture, target, self.0);
         unsafe {
-            gl::BindTexture(target, self.0);
+            gl::BindTexture(target.0, self.0);
         }
     }
 
+    #[track_caller]
     pub fn get_pixels(&self,
                       target: TextureTarget,
                       level: i32,
                       format: TextureFormat,
                       ty: Type,
                       pixels: &mut [u8]) {
+        let _check = debug_check("glGetTexImage");
+        trace_call!(GetTexImage, target, level, format, ty, pixels.len());
         unsafe {
-            gl::GetTexImage(target,
+            gl::GetTexImage(target.0,
                             level,
-                            format,
-                            ty,
+                            format.0,
+                            ty.0,
                             pixels.as_mut_ptr() as *mut gl::types::GLvoid);
         }
     }
 
+    #[track_caller]
     pub fn image_2d(&self,
                     target: TextureTarget,
                     level: i32,
@@ -293,24 +602,27 @@ impl Texture {
                     format: TextureFormat,
                     ty: Type,
                     pix: Option<&[u8]>) {
+        let _check = debug_check("glTexImage2D");
+        trace_call!(TexImage2D, target, level, format, width, height, format, ty, pix);
         unsafe {
             let ptr = match pix {
                 Some(val) => val.as_ptr() as *const gl::types::GLvoid,
                 None => ptr::null(),
             };
-            gl::TexImage2D(target,
+            gl::TexImage2D(target.0,
                            level,
-                           format as i32,
+                           format.0 as i32,
                            width as i32,
                            height as i32,
                            0,
-                           format,
-                           ty,
+                           format.0,
+                           ty.0,
                            ptr
             );
         }
     }
 
+    #[track_caller]
     pub fn sub_image_2d(&self,
                     target: TextureTarget,
                     level: i32,
@@ -321,20 +633,23 @@ impl Texture {
                     format: TextureFormat,
                     ty: Type,
                     pix: &[u8]) {
+        let _check = debug_check("glTexSubImage2D");
+        trace_call!(TexSubImage2D, target, level, x, y, width, height, format, ty, pix);
         unsafe {
-            gl::TexSubImage2D(target,
+            gl::TexSubImage2D(target.0,
                            level,
                            x as i32,
                            y as i32,
                            width as i32,
                            height as i32,
-                           format,
-                           ty,
+                           format.0,
+                           ty.0,
                            pix.as_ptr() as *const _
             );
         }
     }
 
+    #[track_caller]
     pub fn image_2d_ex(&self,
                     target: TextureTarget,
                     level: i32,
@@ -344,24 +659,27 @@ impl Texture {
                     format: TextureFormat,
                     ty: Type,
                     pix: Option<&[u8]>) {
+        let _check = debug_check("glTexImage2D");
+        trace_call!(TexImage2D, target, level, internal_format, width, height, format, ty, pix);
         unsafe {
             let ptr = match pix {
                 Some(val) => val.as_ptr() as *const gl::types::GLvoid,
                 None => ptr::null(),
             };
-            gl::TexImage2D(target,
+            gl::TexImage2D(target.0,
                            level,
-                           internal_format as i32,
+                           internal_format.0 as i32,
                            width as i32,
                            height as i32,
                            0,
-                           format,
-                           ty,
+                           format.0,
+                           ty.0,
                            ptr
             );
         }
     }
 
+    #[track_caller]
     pub fn image_2d_sample(&self,
                     target: TextureTarget,
                     samples: i32,
@@ -369,6 +687,8 @@ impl Texture {
                     height: u32,
                     format: TextureFormat,
                     fixed: bool) {
+        let _check = debug_check("glTexImage2DMultisample");
+        trace_call!(TexImage2DMultisample, target, samples, width, height, format, fixed);
         unsafe {
             let result: &mut [i32] = &mut [0; 1];
             gl::GetIntegerv(gl::MAX_SAMPLES, &mut result[0]);
@@ -379,9 +699,9 @@ impl Texture {
                     samples
                 };
 
-            gl::TexImage2DMultisample(target,
+            gl::TexImage2DMultisample(target.0,
                            use_samples,
-                           format,
+                           format.0,
                            width as i32,
                            height as i32,
                            fixed as u8
@@ -389,6 +709,7 @@ impl Texture {
         }
     }
 
+    #[track_caller]
     pub fn image_3d(&self,
                     target: TextureTarget,
                     level: i32,
@@ -398,20 +719,23 @@ impl Texture {
                     format: TextureFormat,
                     ty: Type,
                     pix: &[u8]) {
+        let _check = debug_check("glTexImage3D");
+        trace_call!(TexImage3D, target, level, width, height, depth, format, ty, pix);
         unsafe {
-            gl::TexImage3D(target,
+            gl::TexImage3D(target.0,
                            level,
-                           format as i32,
+                           format.0 as i32,
                            width as i32,
                            height as i32,
                            depth as i32,
                            0,
-                           format,
-                           ty,
+                           format.0,
+                           ty.0,
                            pix.as_ptr() as *const gl::types::GLvoid);
         }
     }
 
+    #[track_caller]
     pub fn sub_image_3d(&self,
                         target: TextureTarget,
                         level: i32,
@@ -424,8 +748,10 @@ impl Texture {
                         format: TextureFormat,
                         ty: Type,
                         pix: &[u8]) {
+        let _check = debug_check("glTexSubImage3D");
+        trace_call!(TexSubImage3D, target, level, x, y, z, width, height, depth, format, ty, pix);
         unsafe {
-            gl::TexSubImage3D(target,
+            gl::TexSubImage3D(target.0,
                               level,
                               x as i32,
                               y as i32,
@@ -433,71 +759,312 @@ impl Texture {
                               width as i32,
                               height as i32,
                               depth as i32,
-                              format,
-                              ty,
+                              format.0,
+                              ty.0,
                               pix.as_ptr() as *const gl::types::GLvoid);
         }
     }
 
+    #[track_caller]
     pub fn set_parameter(&self,
                          target: TextureTarget,
                          param: TextureParameter,
                          value: TextureValue) {
+        let _check = debug_check("glTexParameteri");
+        trace_call!(TexParameteri, target, param, value);
         unsafe {
-            gl::TexParameteri(target, param, value);
+            gl::TexParameteri(target.0, param.0, value.0);
         }
     }
 }
 
 impl Drop for Texture {
     fn drop(&mut self) {
+        let _check = debug_check("glDeleteTextures");
+        state::deleted(state::Object::Texture, self.0);
+        trace_call!(DeleteTexture, self.0);
         unsafe {
             gl::DeleteTextures(1, &self.0);
         }
     }
 }
 
-pub type ShaderType = u32;
+gl_enum! {
+    ShaderType
+}
 
-pub const VERTEX_SHADER: ShaderType = gl::VERTEX_SHADER;
-pub const FRAGMENT_SHADER: ShaderType = gl::FRAGMENT_SHADER;
-pub const GEOMETRY_SHADER: ShaderType = gl::GEOMETRY_SHADER;
+pub const VERTEX_SHADER: ShaderType = ShaderType(gl::VERTEX_SHADER);
+pub const FRAGMENT_SHADER: ShaderType = ShaderType(gl::FRAGMENT_SHADER);
+pub const GEOMETRY_SHADER: ShaderType = ShaderType(gl::GEOMETRY_SHADER);
+
+gl_enum! {
+    ShaderParameter
+}
 
-pub type ShaderParameter = u32;
+pub const COMPILE_STATUS: ShaderParameter = ShaderParameter(gl::COMPILE_STATUS);
+pub const INFO_LOG_LENGTH: ShaderParameter = ShaderParameter(gl::INFO_LOG_LENGTH);
 
-pub const COMPILE_STATUS: ShaderParameter = gl::COMPILE_STATUS;
-pub const INFO_LOG_LENGTH: ShaderParameter = gl::INFO_LOG_LENGTH;
+gl_enum! {
+    ProgramParameter
+}
+
+pub const LINK_STATUS: ProgramParameter = ProgramParameter(gl::LINK_STATUS);
+pub const PROGRAM_INFO_LOG_LENGTH: ProgramParameter = ProgramParameter(gl::INFO_LOG_LENGTH);
+pub const ACTIVE_UNIFORMS: ProgramParameter = ProgramParameter(gl::ACTIVE_UNIFORMS);
+pub const ACTIVE_UNIFORM_MAX_LENGTH: ProgramParameter = ProgramParameter(gl::ACTIVE_UNIFORM_MAX_LENGTH);
+pub const ACTIVE_ATTRIBUTES: ProgramParameter = ProgramParameter(gl::ACTIVE_ATTRIBUTES);
+pub const ACTIVE_ATTRIBUTE_MAX_LENGTH: ProgramParameter = ProgramParameter(gl::ACTIVE_ATTRIBUTE_MAX_LENGTH);
+
+gl_enum! {
+    /// `VariableType` is the type of a uniform or attribute in a shader.
+    VariableType
+}
+
+pub const FLOAT_TYPE: VariableType = VariableType(gl::FLOAT);
+pub const FLOAT_VEC2: VariableType = VariableType(gl::FLOAT_VEC2);
+pub const FLOAT_VEC3: VariableType = VariableType(gl::FLOAT_VEC3);
+pub const FLOAT_VEC4: VariableType = VariableType(gl::FLOAT_VEC4);
+pub const INT_TYPE: VariableType = VariableType(gl::INT);
+pub const INT_VEC2: VariableType = VariableType(gl::INT_VEC2);
+pub const INT_VEC3: VariableType = VariableType(gl::INT_VEC3);
+pub const INT_VEC4: VariableType = VariableType(gl::INT_VEC4);
+pub const UNSIGNED_INT_TYPE: VariableType = VariableType(gl::UNSIGNED_INT);
+pub const BOOL_TYPE: VariableType = VariableType(gl::BOOL);
+pub const FLOAT_MAT3: VariableType = VariableType(gl::FLOAT_MAT3);
+pub const FLOAT_MAT4: VariableType = VariableType(gl::FLOAT_MAT4);
+pub const SAMPLER_2D: VariableType = VariableType(gl::SAMPLER_2D);
+pub const SAMPLER_2D_ARRAY: VariableType = VariableType(gl::SAMPLER_2D_ARRAY);
+pub const SAMPLER_2D_MULTISAMPLE: VariableType = VariableType(gl::SAMPLER_2D_MULTISAMPLE);
+pub const SAMPLER_3D: VariableType = VariableType(gl::SAMPLER_3D);
+
+/// Returns the glsl name of the type, for error messages.
+pub fn variable_type_name(ty: VariableType) -> &'static str {
+    match ty {
+        FLOAT_TYPE => "float",
+        FLOAT_VEC2 => "vec2",
+        FLOAT_VEC3 => "vec3",
+        FLOAT_VEC4 => "vec4",
+        INT_TYPE => "int",
+        INT_VEC2 => "ivec2",
+        INT_VEC3 => "ivec3",
+        INT_VEC4 => "ivec4",
+        UNSIGNED_INT_TYPE => "uint",
+        BOOL_TYPE => "bool",
+        FLOAT_MAT3 => "mat3",
+        FLOAT_MAT4 => "mat4",
+        SAMPLER_2D => "sampler2D",
+        SAMPLER_2D_ARRAY => "sampler2DArray",
+        SAMPLER_2D_MULTISAMPLE => "sampler2DMS",
+        SAMPLER_3D => "sampler3D",
+        _ => "unknown",
+    }
+}
+
+/// An active uniform or attribute of a linked program.
+#[derive(Clone, Debug)]
+pub struct ActiveVariable {
+    /// The name as reported by the driver. For arrays some drivers
+    /// add a `[0]` suffix and some don't.
+    pub name: String,
+    pub ty: VariableType,
+    /// The number of elements for arrays, otherwise 1.
+    pub size: i32,
+    /// -1 for uniforms inside of uniform blocks.
+    pub location: i32,
+}
+
+impl ActiveVariable {
+    /// Returns whether this is the variable with the passed name,
+    /// ignoring whether either has a `[0]` array suffix.
+    pub fn is_named(&self, name: &str) -> bool {
+        fn base(name: &str) -> &str {
+            name.strip_suffix("[0]").unwrap_or(name)
+        }
+        base(&self.name) == base(name)
+    }
+}
 
 pub struct Program(u32);
 
 impl Program {
+    #[track_caller]
     pub fn new() -> Program {
-        Program(unsafe { gl::CreateProgram() })
+        let _check = debug_check("glCreateProgram");
+        let p = Program(unsafe { gl::CreateProgram() });
+        trace_call!(CreateProgram, p.0);
+        p
     }
 
+    /// Names the program in driver debug messages and debugging tools.
+    #[track_caller]
+    pub fn label(&self, label: &str) {
+        debug::object_label(gl::PROGRAM, self.0, label);
+    }
+
+    #[track_caller]
     pub fn attach_shader(&self, shader: Shader) {
+        let _check = debug_check("glAttachShader");
+        trace_call!(AttachShader, self.0, shader.0);
         unsafe {
             gl::AttachShader(self.0, shader.0);
         }
     }
 
+    #[track_caller]
     pub fn link(&self) {
+        let _check = debug_check("glLinkProgram");
+        trace_call!(LinkProgram, self.0);
         unsafe {
             gl::LinkProgram(self.0);
         }
     }
 
+    #[track_caller]
+    pub fn get_parameter(&self, param: ProgramParameter) -> i32 {
+        let _check = debug_check("glGetProgramiv");
+        let mut ret: i32 = 0;
+        unsafe {
+            gl::GetProgramiv(self.0, param.0, &mut ret);
+        }
+        ret
+    }
+
+    /// Returns whether the last call to `link` succeeded.
+    pub fn is_linked(&self) -> bool {
+        self.get_parameter(LINK_STATUS) != 0
+    }
+
+    #[track_caller]
+    pub fn get_info_log(&self) -> String {
+        let _check = debug_check("glGetProgramInfoLog");
+        let len = self.get_parameter(PROGRAM_INFO_LOG_LENGTH);
+
+        let mut data = vec![0u8; len as usize];
+        unsafe {
+            gl::GetProgramInfoLog(self.0, len, ptr::null_mut(), data.as_mut_ptr() as *mut i8);
+        }
+        String::from_utf8_lossy(&data).trim_end_matches('\u{0}').to_owned()
+    }
+
+    #[track_caller]
     pub fn use_program(&self) {
+        if !state::set(&[Slot::Program], self.0) {
+            return;
+        }
+        let _check = debug_check("glUseProgram");
+        trace_call!(UseProgram, self.0);
         unsafe {
             gl::UseProgram(self.0);
         }
     }
 
-    pub fn uniform_location(&self, name: &str) -> Option<Uniform> {
-        let u = unsafe {
+    /// Returns the uniforms used by the linked program, including ones
+    /// inside of uniform blocks.
+    #[track_caller]
+    pub fn active_uniforms(&self) -> Vec<ActiveVariable> {
+        let _check = debug_check("glGetActiveUniform");
+        let count = self.get_parameter(ACTIVE_UNIFORMS);
+        let max_len = self.get_parameter(ACTIVE_UNIFORM_MAX_LENGTH);
+        (0..count as u32)
+            .map(|i| {
+                let (name, ty, size) = self.active_variable(max_len, |len, out_len, size, ty, name| unsafe {
+                    gl::GetActiveUniform(self.0, i, len, out_len, size, ty, name);
+                });
+                let location = self.raw_uniform_location(&name);
+                ActiveVariable { name, ty, size, location }
+            })
+            .collect()
+    }
+
+    /// Returns the vertex attributes used by the linked program.
+    #[track_caller]
+    pub fn active_attributes(&self) -> Vec<ActiveVariable> {
+        let _check = debug_check("glGetActiveAttrib");
+        let count = self.get_parameter(ACTIVE_ATTRIBUTES);
+        let max_len = self.get_parameter(ACTIVE_ATTRIBUTE_MAX_LENGTH);
+        (0..count as u32)
+            .map(|i| {
+                let (name, ty, size) = self.active_variable(max_len, |len, out_len, size, ty, name| unsafe {
+                    gl::GetActiveAttrib(self.0, i, len, out_len, size, ty, name);
+                });
+                let location = self.raw_attribute_location(&name);
+                ActiveVariable { name, ty, size, location }
+            })
+            .collect()
+    }
+
+    fn active_variable<F>(&self, max_len: i32, get: F) -> (String, VariableType, i32)
+        where F: FnOnce(i32, *mut i32, *mut i32, *mut u32, *mut gl::types::GLchar) {
+        let mut name = vec![0u8; max_len.max(1) as usize];
+        let mut len = 0;
+        let mut size = 0;
+        let mut ty = 0;
+        get(name.len() as i32, &mut len, &mut size, &mut ty, name.as_mut_ptr() as *mut _);
+        name.truncate(len as usize);
+        (String::from_utf8_lossy(&name).into_owned(), VariableType(ty), size)
+    }
+
+    /// Looks up the active uniform with the passed name. Arrays are
+    /// found with or without a `[0]` suffix, whichever way the
+    /// driver reports them.
+    pub fn find_uniform(&self, name: &str) -> Option<ActiveVariable> {
+        self.active_uniforms().into_iter().find(|v| v.is_named(name))
+    }
+
+    /// Looks up the active attribute with the passed name.
+    pub fn find_attribute(&self, name: &str) -> Option<ActiveVariable> {
+        self.active_attributes().into_iter().find(|v| v.is_named(name))
+    }
+
+    #[track_caller]
+    fn raw_uniform_location(&self, name: &str) -> i32 {
+        let _check = debug_check("glGetUniformLocation");
+        let loc = unsafe {
             let s = ffi::CString::new(name);
             gl::GetUniformLocation(self.0, s.unwrap().as_ptr())
         };
+        // Recorded so the replayer can map the location to its own
+        trace_call!(UniformLocation, self.0, name, loc);
+        loc
+    }
+
+    #[track_caller]
+    fn raw_attribute_location(&self, name: &str) -> i32 {
+        let _check = debug_check("glGetAttribLocation");
+        let loc = unsafe {
+            let name_c = ffi::CString::new(name).unwrap();
+            gl::GetAttribLocation(self.0, name_c.as_ptr())
+        };
+        trace_call!(AttribLocation, self.0, name, loc);
+        loc
+    }
+
+    /// Returns the location of the uniform. `uniforms` is the
+    /// program's `active_uniforms`, queried once for every lookup.
+    /// Arrays are looked up by their bare name unless the driver has
+    /// the `UniformArrayIndexSuffix` quirk. If that fails the name the
+    /// driver reports for it is used, so both `name` and `name[0]`
+    /// work.
+    #[track_caller]
+    pub fn uniform_location(&self, uniforms: &[ActiveVariable], name: &str) -> Option<Uniform> {
+        let find = |name| uniforms.iter().find(|v| v.is_named(name));
+        let base = name.strip_suffix("[0]").unwrap_or(name);
+        let is_array = find(base)
+            .is_some_and(|v| v.size > 1 || v.name.ends_with("[0]"));
+        let lookup = if !is_array {
+            name.to_owned()
+        } else if quirks::has(quirks::Quirk::UniformArrayIndexSuffix) {
+            format!("{}[0]", base)
+        } else {
+            base.to_owned()
+        };
+
+        let mut u = self.raw_uniform_location(&lookup);
+        if u == -1 {
+            if let Some(v) = find(name) {
+                u = v.location;
+            }
+        }
         if u != -1 {
             Some(Uniform(u))
         } else {
@@ -506,13 +1073,38 @@ impl Program {
         }
     }
 
-    pub fn attribute_location(&self, name: &str) -> Option<Attribute> {
-        let a = unsafe {
+    /// Returns the named uniform block, if it's active in the program.
+    #[track_caller]
+    pub fn uniform_block(&self, name: &str) -> Option<UniformBlock> {
+        let _check = debug_check("glGetUniformBlockIndex");
+        let index = unsafe {
             let name_c = ffi::CString::new(name).unwrap();
-            gl::GetAttribLocation(self.0, name_c.as_ptr())
+            gl::GetUniformBlockIndex(self.0, name_c.as_ptr())
         };
+        trace_call!(UniformBlockIndex, self.0, name, index);
+        if index != gl::INVALID_INDEX {
+            Some(UniformBlock(index))
+        } else {
+            println!("glGetUniformBlockIndex failed for {}", name);
+            None
+        }
+    }
+
+    /// Sets the `UNIFORM_BUFFER` binding point the block reads from.
+    #[track_caller]
+    pub fn bind_uniform_block(&self, block: &UniformBlock, binding: u32) {
+        let _check = debug_check("glUniformBlockBinding");
+        trace_call!(UniformBlockBinding, self.0, block.0, binding);
+        unsafe {
+            gl::UniformBlockBinding(self.0, block.0, binding);
+        }
+    }
+
+    #[track_caller]
+    pub fn attribute_location(&self, name: &str) -> Option<Attribute> {
+        let a = self.raw_attribute_location(name);
         if a != -1 {
-            Some(Attribute(a))
+            Some(Attribute(a, 0))
         } else {
             println!("glGetAttributeLocation failed for {}", name);
             None
@@ -522,6 +1114,9 @@ impl Program {
 
 impl Drop for Program {
     fn drop(&mut self) {
+        let _check = debug_check("glDeleteProgram");
+        state::deleted(state::Object::Program, self.0);
+        trace_call!(DeleteProgram, self.0);
         unsafe {
             gl::DeleteProgram(self.0);
         }
@@ -531,11 +1126,18 @@ impl Drop for Program {
 pub struct Shader(u32);
 
 impl Shader {
+    #[track_caller]
     pub fn new(ty: ShaderType) -> Shader {
-        Shader(unsafe { gl::CreateShader(ty) })
+        let _check = debug_check("glCreateShader");
+        let s = Shader(unsafe { gl::CreateShader(ty.0) });
+        trace_call!(CreateShader, ty, s.0);
+        s
     }
 
+    #[track_caller]
     pub fn set_source(&self, src: &str) {
+        let _check = debug_check("glShaderSource");
+        trace_call!(ShaderSource, self.0, src);
         unsafe {
             let src_c = ffi::CString::new(src).unwrap();
             gl::ShaderSource(self.0,
@@ -545,122 +1147,202 @@ impl Shader {
         }
     }
 
+    #[track_caller]
     pub fn compile(&self) {
+        let _check = debug_check("glCompileShader");
+        trace_call!(CompileShader, self.0);
         unsafe {
             gl::CompileShader(self.0);
         }
     }
 
+    #[track_caller]
     pub fn get_parameter(&self, param: ShaderParameter) -> i32 {
+        let _check = debug_check("glGetShaderiv");
         let mut ret: i32 = 0;
         unsafe {
-            gl::GetShaderiv(self.0, param, &mut ret);
+            gl::GetShaderiv(self.0, param.0, &mut ret);
         }
         ret
     }
 
+    #[track_caller]
     pub fn get_info_log(&self) -> String {
+        let _check = debug_check("glGetShaderInfoLog");
         let len = self.get_parameter(INFO_LOG_LENGTH);
 
-        let mut data = Vec::<u8>::with_capacity(len as usize);
+        let mut data = vec![0u8; len as usize];
         unsafe {
-            data.set_len(len as usize);
             gl::GetShaderInfoLog(self.0, len, ptr::null_mut(), data.as_mut_ptr() as *mut i8);
         }
         String::from_utf8(data).unwrap()
     }
 }
 
+/// `UniformBlock` is a `uniform` block of a program whose values are
+/// read from a buffer.
+#[derive(Clone, Copy)]
+pub struct UniformBlock(u32);
+
 #[derive(Clone, Copy)]
 pub struct Uniform(i32);
 
 impl Uniform {
+    #[track_caller]
     pub fn set_int(&self, val: i32) {
+        let _check = debug_check("glUniform1i");
+        trace_call!(Uniform1i, self.0, val);
         unsafe {
             gl::Uniform1i(self.0, val);
         }
     }
 
+    #[track_caller]
     pub fn set_int3(&self, x: i32, y: i32, z: i32) {
+        let _check = debug_check("glUniform3i");
+        trace_call!(Uniform3i, self.0, x, y, z);
         unsafe {
             gl::Uniform3i(self.0, x, y, z);
         }
     }
 
+    #[track_caller]
     pub fn set_float(&self, val: f32) {
+        let _check = debug_check("glUniform1f");
+        trace_call!(Uniform1f, self.0, val);
         unsafe {
             gl::Uniform1f(self.0, val);
         }
     }
 
+    #[track_caller]
     pub fn set_float2(&self, x: f32, y: f32) {
+        let _check = debug_check("glUniform2f");
+        trace_call!(Uniform2f, self.0, x, y);
         unsafe {
             gl::Uniform2f(self.0, x, y);
         }
     }
 
+    #[track_caller]
     pub fn set_float3(&self, x: f32, y: f32, z: f32) {
+        let _check = debug_check("glUniform3f");
+        trace_call!(Uniform3f, self.0, x, y, z);
         unsafe {
             gl::Uniform3f(self.0, x, y, z);
         }
     }
 
+    #[track_caller]
     pub fn set_float4(&self, x: f32, y: f32, z: f32, w: f32) {
+        let _check = debug_check("glUniform4f");
+        trace_call!(Uniform4f, self.0, x, y, z, w);
         unsafe {
             gl::Uniform4f(self.0, x, y, z, w);
         }
     }
 
+    #[track_caller]
     pub fn set_matrix4(&self, m: &::cgmath::Matrix4<f32>) {
+        let _check = debug_check("glUniformMatrix4fv");
+        trace_call!(UniformMatrix4fv, self.0, std::slice::from_ref(m));
         use cgmath::Matrix;
         unsafe {
             gl::UniformMatrix4fv(self.0, 1, false as u8, m.as_ptr());
         }
     }
 
+    #[track_caller]
     pub fn set_matrix4_multi(&self, m: &[::cgmath::Matrix4<f32>]) {
+        let _check = debug_check("glUniformMatrix4fv");
+        trace_call!(UniformMatrix4fv, self.0, m);
         unsafe {
             gl::UniformMatrix4fv(self.0, m.len() as i32, false as u8, m.as_ptr() as *const _); // TODO: Most likely isn't safe
         }
     }
 }
 
+/// The location of an attribute and, for matrices, which column.
+/// Columns are traced separately so replays only remap the location.
 #[derive(Clone, Copy)]
-pub struct Attribute(i32);
+pub struct Attribute(i32, i32);
 
 impl Attribute {
+    fn index(&self) -> u32 {
+        (self.0 + self.1) as u32
+    }
+
+    #[track_caller]
     pub fn enable(&self) {
+        let _check = debug_check("glEnableVertexAttribArray");
+        trace_call!(EnableVertexAttribArray, self.0, self.1);
         unsafe {
-            gl::EnableVertexAttribArray(self.0 as u32);
+            gl::EnableVertexAttribArray(self.index());
         }
     }
 
+    #[track_caller]
     pub fn disable(&self) {
+        let _check = debug_check("glDisableVertexAttribArray");
+        trace_call!(DisableVertexAttribArray, self.0, self.1);
         unsafe {
-            gl::DisableVertexAttribArray(self.0 as u32);
+            gl::DisableVertexAttribArray(self.index());
         }
     }
 
+    #[track_caller]
     pub fn vertex_pointer(&self, size: i32, ty: Type, normalized: bool, stride: i32, offset: i32) {
+        let _check = debug_check("glVertexAttribPointer");
+        trace_call!(VertexAttribPointer, self.0, self.1, size, ty, normalized, stride, offset);
         unsafe {
-            gl::VertexAttribPointer(self.0 as u32,
+            gl::VertexAttribPointer(self.index(),
                                     size,
-                                    ty,
+                                    ty.0,
                                     normalized as u8,
                                     stride,
                                     offset as *const gl::types::GLvoid);
         }
     }
 
+    #[track_caller]
     pub fn vertex_pointer_int(&self, size: i32, ty: Type, stride: i32, offset: i32) {
+        let _check = debug_check("glVertexAttribIPointer");
+        trace_call!(VertexAttribIPointer, self.0, self.1, size, ty, stride, offset);
         unsafe {
-            gl::VertexAttribIPointer(self.0 as u32,
+            gl::VertexAttribIPointer(self.index(),
                                      size,
-                                     ty,
+                                     ty.0,
                                      stride,
                                      offset as *const gl::types::GLvoid);
         }
     }
+
+    /// Sets how many instances share each value of the attribute, 0
+    /// advances it per vertex. Needs `caps::Cap::InstancedArrays`.
+    #[track_caller]
+    pub fn divisor(&self, divisor: u32) {
+        let _check = debug_check("glVertexAttribDivisor");
+        trace_call!(VertexAttribDivisor, self.0, self.1, divisor);
+        unsafe {
+            gl::VertexAttribDivisor(self.index(), divisor);
+        }
+    }
+
+    /// Sets the value used whilst the attribute's array is disabled.
+    #[track_caller]
+    pub fn set_float4(&self, x: f32, y: f32, z: f32, w: f32) {
+        let _check = debug_check("glVertexAttrib4f");
+        trace_call!(VertexAttrib4f, self.0, self.1, x, y, z, w);
+        unsafe {
+            gl::VertexAttrib4f(self.index(), x, y, z, w);
+        }
+    }
+
+    /// Returns the attribute of column `i` of a matrix attribute,
+    /// each column takes its own location.
+    pub fn column(&self, i: i32) -> Attribute {
+        Attribute(self.0, self.1 + i)
+    }
 }
 
 // VertexArray is used to store state needed to render vertices.
@@ -670,18 +1352,33 @@ pub struct VertexArray(u32);
 
 impl VertexArray {
     /// Allocates a new `VertexArray`.
+    #[track_caller]
     pub fn new() -> VertexArray {
+        let _check = debug_check("glGenVertexArrays");
         let mut va = VertexArray(0);
         unsafe {
             gl::GenVertexArrays(1, &mut va.0);
         }
+        trace_call!(GenVertexArray, va.0);
         va
     }
 
+    /// Names the vertex array in driver debug messages and debugging tools.
+    #[track_caller]
+    pub fn label(&self, label: &str) {
+        debug::object_label(gl::VERTEX_ARRAY, self.0, label);
+    }
+
     /// Marks the `VertexArray` as the currently active one, this
     /// means buffers/the format of the buffers etc will be bound to
     /// this `VertexArray`.
+    #[track_caller]
     pub fn bind(&self) {
+        if !state::set(&[Slot::VertexArray], self.0) {
+            return;
+        }
+        let _check = debug_check("glBindVertexArray");
+        trace_call!(BindVertexArray, self.0);
         unsafe {
             gl::BindVertexArray(self.0);
         }
@@ -690,6 +1387,9 @@ impl VertexArray {
 
 impl Drop for VertexArray {
     fn drop(&mut self) {
+        let _check = debug_check("glDeleteVertexArrays");
+        state::deleted(state::Object::VertexArray, self.0);
+        trace_call!(DeleteVertexArray, self.0);
         unsafe {
             gl::DeleteVertexArrays(1, &self.0);
         }
@@ -697,68 +1397,118 @@ impl Drop for VertexArray {
     }
 }
 
-/// `BufferTarget` is a target for a buffer to be bound to.
-pub type BufferTarget = u32;
+gl_enum! {
+    /// `BufferTarget` is a target for a buffer to be bound to.
+    BufferTarget
+}
 
-pub const ARRAY_BUFFER: BufferTarget = gl::ARRAY_BUFFER;
-pub const ELEMENT_ARRAY_BUFFER: BufferTarget = gl::ELEMENT_ARRAY_BUFFER;
+pub const ARRAY_BUFFER: BufferTarget = BufferTarget(gl::ARRAY_BUFFER);
+pub const ELEMENT_ARRAY_BUFFER: BufferTarget = BufferTarget(gl::ELEMENT_ARRAY_BUFFER);
+pub const UNIFORM_BUFFER: BufferTarget = BufferTarget(gl::UNIFORM_BUFFER);
 
-/// `BufferUsage` states how a buffer is going to be used by the program.
-pub type BufferUsage = u32;
+gl_enum! {
+    /// `BufferUsage` states how a buffer is going to be used by the program.
+    BufferUsage
+}
 
 /// Marks the buffer as 'not going to change' after the
 /// initial data upload to be rendered by the gpu.
-pub const STATIC_DRAW: BufferUsage = gl::STATIC_DRAW;
+pub const STATIC_DRAW: BufferUsage = BufferUsage(gl::STATIC_DRAW);
 /// Marks the buffer as 'changed frequently' during the
 /// course of the program whilst being rendered by the gpu.
-pub const DYNAMIC_DRAW: BufferUsage = gl::DYNAMIC_DRAW;
+pub const DYNAMIC_DRAW: BufferUsage = BufferUsage(gl::DYNAMIC_DRAW);
 /// Marks the buffer as 'changed every frame' whilst being
 /// rendered by the gpu.
-pub const STREAM_DRAW: BufferUsage = gl::STREAM_DRAW;
+pub const STREAM_DRAW: BufferUsage = BufferUsage(gl::STREAM_DRAW);
 
-/// Access states how a value will be accesed by the program.
-pub type Access = u32;
+gl_enum! {
+    /// Access states how a value will be accesed by the program.
+    Access
+}
 
 /// States that the returned value will only be read.
-pub const READ_ONLY: Access = gl::READ_ONLY;
+pub const READ_ONLY: Access = Access(gl::READ_ONLY);
 /// States that the returned value will only be written
 /// to.
-pub const WRITE_ONLY: Access = gl::WRITE_ONLY;
+pub const WRITE_ONLY: Access = Access(gl::WRITE_ONLY);
 
 /// `Buffer` is a storage for vertex data.
 pub struct Buffer(u32);
 
 impl Buffer {
     /// Allocates a new Buffer.
+    #[track_caller]
     pub fn new() -> Buffer {
+        let _check = debug_check("glGenBuffers");
         let mut b = Buffer(0);
         unsafe {
             gl::GenBuffers(1, &mut b.0);
         }
+        trace_call!(GenBuffer, b.0);
         b
     }
 
+    /// Names the buffer in driver debug messages and debugging tools.
+    #[track_caller]
+    pub fn label(&self, label: &str) {
+        debug::object_label(gl::BUFFER, self.0, label);
+    }
+
     /// Makes the buffer the currently active one for the given target.
     /// This will allow it to be the source of operations that act on a buffer
     /// (Data, Map etc).
+    #[track_caller]
     pub fn bind(&self, target: BufferTarget) {
+        if !state::set(&[Slot::Buffer(target)], self.0) {
+            return;
+        }
+        let _check = debug_check("glBindBuffer");
+        trace_call!(BindBuffer, target, self.0);
         unsafe {
-            gl::BindBuffer(target, self.0);
+            gl::BindBuffer(target.0, self.0);
         }
     }
 
+    /// Binds the buffer to the indexed binding point of the target,
+    /// e.g. the one a uniform block reads from. Also binds it to the
+    /// target itself.
+    #[track_caller]
+    pub fn bind_base(&self, target: BufferTarget, index: u32) {
+        if !state::set(&[Slot::Buffer(target), Slot::IndexedBuffer(target, index)], self.0) {
+            return;
+        }
+        let _check = debug_check("glBindBufferBase");
+        trace_call!(BindBufferBase, target, index, self.0);
+        unsafe {
+            gl::BindBufferBase(target.0, index, self.0);
+        }
+    }
+
+    #[track_caller]
     pub fn set_data(&self, target: BufferTarget, data: &[u8], usage: BufferUsage) {
+        let _check = debug_check("glBufferData");
+        trace_call!(BufferData, target, data, usage);
         unsafe {
-            gl::BufferData(target,
+            gl::BufferData(target.0,
                            data.len() as isize,
                            data.as_ptr() as *const gl::types::GLvoid,
-                           usage);
+                           usage.0);
         }
     }
 
+    #[track_caller]
     pub fn re_set_data(&self, target: BufferTarget, data: &[u8]) {
+        self.set_sub_data(target, 0, data);
+    }
+
+    /// Replaces part of the buffer's data starting at `offset` bytes,
+    /// the buffer must already be large enough.
+    #[track_caller]
+    pub fn set_sub_data(&self, target: BufferTarget, offset: usize, data: &[u8]) {
+        let _check = debug_check("glBufferSubData");
+        trace_call!(BufferSubData, target, offset, data);
         unsafe {
-            gl::BufferSubData(target, 0, data.len() as isize, data.as_ptr() as *const _);
+            gl::BufferSubData(target.0, offset as isize, data.len() as isize, data.as_ptr() as *const _);
         }
     }
 
@@ -769,18 +1519,74 @@ impl Buffer {
     /// Warning: the passed length value is not checked in anyway so it is
     /// possible to overrun the memory. It is up to the program to ensure this
     /// length is valid.
+    #[track_caller]
     pub fn map(&self, target: BufferTarget, access: Access, length: usize) -> MappedBuffer {
+        let _check = debug_check("glMapBuffer");
+        unsafe {
+            MappedBuffer {
+                inner: Vec::from_raw_parts(gl::MapBuffer(target.0, access.0) as *mut u8, 0, length),
+                target,
+                offset: 0,
+            }
+        }
+    }
+
+    /// Maps `length` bytes of the buffer starting at `offset`, like
+    /// `map`. The flags can avoid waiting for the gpu to finish with
+    /// the buffer, see `MapFlags`.
+    #[track_caller]
+    pub fn map_range(&self, target: BufferTarget, offset: usize, length: usize, flags: MapFlags) -> MappedBuffer {
+        let _check = debug_check("glMapBufferRange");
         unsafe {
+            let ptr = gl::MapBufferRange(target.0, offset as isize, length as isize, flags.internal());
             MappedBuffer {
-                inner: Vec::from_raw_parts(gl::MapBuffer(target, access) as *mut u8, 0, length),
+                inner: Vec::from_raw_parts(ptr as *mut u8, 0, length),
                 target,
+                offset,
             }
         }
     }
 }
 
+/// `MapFlags` is a set of flags for `Buffer::map_range`.
+pub enum MapFlags {
+    /// Marks the mapping as written to
+    Write,
+    /// The previous contents of the range may be discarded
+    InvalidateRange,
+    /// The previous contents of the whole buffer may be discarded
+    InvalidateBuffer,
+    /// Doesn't wait for the gpu to finish using the buffer, the caller
+    /// must ensure the range isn't in use, e.g. with a `Fence`
+    Unsynchronized,
+    Internal(u32),
+}
+
+impl MapFlags {
+    fn internal(self) -> u32 {
+        match self {
+            MapFlags::Write => gl::MAP_WRITE_BIT,
+            MapFlags::InvalidateRange => gl::MAP_INVALIDATE_RANGE_BIT,
+            MapFlags::InvalidateBuffer => gl::MAP_INVALIDATE_BUFFER_BIT,
+            MapFlags::Unsynchronized => gl::MAP_UNSYNCHRONIZED_BIT,
+            MapFlags::Internal(val) => val,
+        }
+    }
+}
+
+impl BitOr for MapFlags {
+    type Output = MapFlags;
+
+    fn bitor(self, rhs: MapFlags) -> MapFlags {
+        MapFlags::Internal(self.internal() | rhs.internal())
+    }
+}
+
 impl Drop for Buffer {
     fn drop(&mut self) {
+        let _check = debug_check("glDeleteBuffers");
+        state::deleted(state::Object::Buffer, self.0);
+        trace_call!(DeleteBuffer, self.0);
         unsafe {
             gl::DeleteBuffers(1, &self.0);
         }
@@ -790,6 +1596,7 @@ impl Drop for Buffer {
 pub struct MappedBuffer {
     inner: Vec<u8>,
     target: BufferTarget,
+    offset: usize,
 }
 
 impl Deref for MappedBuffer {
@@ -808,162 +1615,451 @@ impl DerefMut for MappedBuffer {
 
 impl Drop for MappedBuffer {
     fn drop(&mut self) {
+        let _check = debug_check("glUnmapBuffer");
+        // Traced as an upload of what was written to the mapping
+        trace_call!(BufferSubData, self.target, self.offset, &self.inner[..]);
+        unsafe {
+            gl::UnmapBuffer(self.target.0);
+        }
+        mem::forget(mem::take(&mut self.inner));
+    }
+}
+
+/// Signals once the gpu has finished the commands issued before the
+/// fence was created. Fences aren't traced as they don't change what
+/// is drawn.
+pub struct Fence(gl::types::GLsync);
+
+impl Fence {
+    #[track_caller]
+    pub fn new() -> Fence {
+        let _check = debug_check("glFenceSync");
+        Fence(unsafe { gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0) })
+    }
+
+    /// Waits up to `timeout` for the fence to signal, returning
+    /// whether it did. Pending commands are flushed so the wait can't
+    /// deadlock. Fails if the wait itself did (e.g. the context was
+    /// lost), the fence will then never signal.
+    #[track_caller]
+    pub fn wait(&self, timeout: Duration) -> Result<bool, GlError> {
+        let _check = debug_check("glClientWaitSync");
+        let timeout = timeout.as_nanos().min(u64::MAX as u128) as u64;
+        let status = unsafe { gl::ClientWaitSync(self.0, gl::SYNC_FLUSH_COMMANDS_BIT, timeout) };
+        match status {
+            gl::ALREADY_SIGNALED | gl::CONDITION_SATISFIED => Ok(true),
+            gl::TIMEOUT_EXPIRED => Ok(false),
+            status => Err(get_error().unwrap_or(GlError::Unknown(status))),
+        }
+    }
+
+    /// Returns whether the fence has signaled without waiting.
+    #[track_caller]
+    pub fn is_signaled(&self) -> Result<bool, GlError> {
+        self.wait(Duration::from_secs(0))
+    }
+}
+
+impl Drop for Fence {
+    fn drop(&mut self) {
+        let _check = debug_check("glDeleteSync");
         unsafe {
-            gl::UnmapBuffer(self.target);
+            gl::DeleteSync(self.0);
         }
-        mem::forget(mem::replace(&mut self.inner, Vec::new()));
     }
 }
 
 // Frame buffers
 
-pub type Attachment = u32;
-pub const COLOR_ATTACHMENT_0: Attachment = gl::COLOR_ATTACHMENT0;
-pub const COLOR_ATTACHMENT_1: Attachment = gl::COLOR_ATTACHMENT1;
-pub const COLOR_ATTACHMENT_2: Attachment = gl::COLOR_ATTACHMENT2;
-pub const DEPTH_ATTACHMENT: Attachment = gl::DEPTH_ATTACHMENT;
+gl_enum! {
+    Attachment
+}
+pub const COLOR_ATTACHMENT_0: Attachment = Attachment(gl::COLOR_ATTACHMENT0);
+pub const COLOR_ATTACHMENT_1: Attachment = Attachment(gl::COLOR_ATTACHMENT1);
+pub const COLOR_ATTACHMENT_2: Attachment = Attachment(gl::COLOR_ATTACHMENT2);
+pub const DEPTH_ATTACHMENT: Attachment = Attachment(gl::DEPTH_ATTACHMENT);
+
+/// `Renderbuffer` is an image that can only be rendered to, used for
+/// framebuffer attachments that are never sampled.
+pub struct Renderbuffer(u32);
+
+impl Renderbuffer {
+    #[track_caller]
+    pub fn new() -> Renderbuffer {
+        let _check = debug_check("glGenRenderbuffers");
+        let mut rb = Renderbuffer(0);
+        unsafe {
+            gl::GenRenderbuffers(1, &mut rb.0);
+        }
+        trace_call!(GenRenderbuffer, rb.0);
+        rb
+    }
 
-pub struct Framebuffer(u32);
+    /// Names the renderbuffer in driver debug messages and debugging tools.
+    #[track_caller]
+    pub fn label(&self, label: &str) {
+        debug::object_label(gl::RENDERBUFFER, self.0, label);
+    }
 
-pub fn check_framebuffer_status() {
-    unsafe {
-        let status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
-        let s =
-        match status {
-            gl::FRAMEBUFFER_UNDEFINED => "GL_FRAMEBUFFER_UNDEFINED",
-            gl::FRAMEBUFFER_INCOMPLETE_ATTACHMENT => "GL_FRAMEBUFFER_INCOMPLETE_ATTACHMENT",
-            gl::FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT => "GL_FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT",
-            gl::FRAMEBUFFER_INCOMPLETE_DRAW_BUFFER => "GL_FRAMEBUFFER_INCOMPLETE_DRAW_BUFFER",
-            gl::FRAMEBUFFER_INCOMPLETE_READ_BUFFER => "GL_FRAMEBUFFER_INCOMPLETE_READ_BUFFER",
-            gl::FRAMEBUFFER_UNSUPPORTED => "GL_FRAMEBUFFER_UNSUPPORTED",
-            gl::FRAMEBUFFER_INCOMPLETE_MULTISAMPLE => "GL_FRAMEBUFFER_INCOMPLETE_MULTISAMPLE",
-            gl::FRAMEBUFFER_INCOMPLETE_LAYER_TARGETS => "GL_FRAMEBUFFER_INCOMPLETE_LAYER_TARGETS",
-
-            gl::FRAMEBUFFER_COMPLETE => "GL_FRAMEBUFFER_COMPLETE",
-            //gl::FRAMEBUFFER_INCOMPLETE_DIMENSIONS => "GL_FRAMEBUFFER_INCOMPLETE_DIMENSIONS",
-            _ => "unknown"
-        };
+    #[track_caller]
+    pub fn bind(&self) {
+        if !state::set(&[Slot::Renderbuffer], self.0) {
+            return;
+        }
+        let _check = debug_check("glBindRenderbuffer");
+        trace_call!(BindRenderbuffer, self.0);
+        unsafe {
+            gl::BindRenderbuffer(gl::RENDERBUFFER, self.0);
+        }
+    }
+
+    /// Allocates the storage of the bound renderbuffer.
+    #[track_caller]
+    pub fn storage(&self, format: TextureFormat, width: u32, height: u32) {
+        let _check = debug_check("glRenderbufferStorage");
+        trace_call!(RenderbufferStorage, format, width, height);
+        unsafe {
+            gl::RenderbufferStorage(gl::RENDERBUFFER, format.0, width as i32, height as i32);
+        }
+    }
 
-        if status != gl::FRAMEBUFFER_COMPLETE {
-            println!("glBindFramebuffer failed, glCheckFrameBufferStatus(GL_FRAMEBUFFER) = {} {}", status, s);
-            panic!("glBindFramebuffer failed, glCheckFrameBufferStatus(GL_FRAMEBUFFER) = {} {}", status, s);
+    /// Allocates multisampled storage for the bound renderbuffer.
+    #[track_caller]
+    pub fn storage_multisample(&self, samples: i32, format: TextureFormat, width: u32, height: u32) {
+        let _check = debug_check("glRenderbufferStorageMultisample");
+        trace_call!(RenderbufferStorageMultisample, samples, format, width, height);
+        unsafe {
+            gl::RenderbufferStorageMultisample(gl::RENDERBUFFER,
+                                               samples,
+                                               format.0,
+                                               width as i32,
+                                               height as i32);
         }
     }
 }
 
-pub fn check_gl_error() {
-    unsafe {
-        loop {
-            let err = gl::GetError();
-            if err == gl::NO_ERROR {
-                break
+impl Drop for Renderbuffer {
+    fn drop(&mut self) {
+        let _check = debug_check("glDeleteRenderbuffers");
+        state::deleted(state::Object::Renderbuffer, self.0);
+        trace_call!(DeleteRenderbuffer, self.0);
+        unsafe {
+            gl::DeleteRenderbuffers(1, &self.0);
+        }
+    }
+}
+
+gl_enum! {
+    /// What a `Query` measures.
+    QueryTarget
+}
+
+/// The number of samples that passed the depth test.
+pub const SAMPLES_PASSED: QueryTarget = QueryTarget(gl::SAMPLES_PASSED);
+/// Whether any sample passed the depth test, may be cheaper than
+/// counting them.
+pub const ANY_SAMPLES_PASSED: QueryTarget = QueryTarget(gl::ANY_SAMPLES_PASSED);
+/// The gpu time taken in nanoseconds, needs
+/// `caps::Cap::TimerQuery`.
+pub const TIME_ELAPSED: QueryTarget = QueryTarget(gl::TIME_ELAPSED);
+/// The gpu's clock in nanoseconds, recorded with `Query::counter`.
+pub const TIMESTAMP: QueryTarget = QueryTarget(gl::TIMESTAMP);
+
+/// Measures something on the gpu between `begin` and `end_query`.
+/// The result is ready some time later, check `is_available` before
+/// reading it to avoid waiting on the gpu. Result reads aren't traced.
+pub struct Query(u32);
+
+impl Query {
+    #[track_caller]
+    pub fn new() -> Query {
+        let _check = debug_check("glGenQueries");
+        let mut query = Query(0);
+        unsafe {
+            gl::GenQueries(1, &mut query.0);
+        }
+        trace_call!(GenQuery, query.0);
+        query
+    }
+
+    /// Names the query in driver debug messages and debugging tools.
+    /// The query must have been begun (or used by `counter`) first,
+    /// until then it isn't an object.
+    #[track_caller]
+    pub fn label(&self, label: &str) {
+        debug::object_label(gl::QUERY, self.0, label);
+    }
+
+    /// Starts measuring, only one query can be active per target.
+    #[track_caller]
+    pub fn begin(&self, target: QueryTarget) {
+        let _check = debug_check("glBeginQuery");
+        trace_call!(BeginQuery, target, self.0);
+        unsafe {
+            gl::BeginQuery(target.0, self.0);
+        }
+    }
+
+    /// Records the gpu's clock once the previous commands have
+    /// finished, read as a `TIMESTAMP`.
+    #[track_caller]
+    pub fn counter(&self) {
+        let _check = debug_check("glQueryCounter");
+        trace_call!(QueryCounter, self.0);
+        unsafe {
+            gl::QueryCounter(self.0, gl::TIMESTAMP);
+        }
+    }
+
+    /// Returns whether the result can be read without waiting.
+    #[track_caller]
+    pub fn is_available(&self) -> bool {
+        let _check = debug_check("glGetQueryObjectuiv");
+        let mut available = 0;
+        unsafe {
+            gl::GetQueryObjectuiv(self.0, gl::QUERY_RESULT_AVAILABLE, &mut available);
+        }
+        available != 0
+    }
+
+    /// Returns the result, waiting for it if it isn't available yet.
+    #[track_caller]
+    pub fn result(&self) -> u64 {
+        if !caps::has(caps::Cap::TimerQuery) {
+            let _check = debug_check("glGetQueryObjectuiv");
+            let mut result = 0;
+            unsafe {
+                gl::GetQueryObjectuiv(self.0, gl::QUERY_RESULT, &mut result);
             }
+            return result as u64;
+        }
+        let _check = debug_check("glGetQueryObjectui64v");
+        let mut result = 0;
+        unsafe {
+            gl::GetQueryObjectui64v(self.0, gl::QUERY_RESULT, &mut result);
+        }
+        result
+    }
+}
 
-            println!("glGetError = {}", err);
+/// Stops the active query of the target.
+#[track_caller]
+pub fn end_query(target: QueryTarget) {
+    let _check = debug_check("glEndQuery");
+    trace_call!(EndQuery, target);
+    unsafe {
+        gl::EndQuery(target.0);
+    }
+}
+
+impl Drop for Query {
+    fn drop(&mut self) {
+        let _check = debug_check("glDeleteQueries");
+        state::deleted(state::Object::Query, self.0);
+        trace_call!(DeleteQuery, self.0);
+        unsafe {
+            gl::DeleteQueries(1, &self.0);
         }
     }
 }
 
+pub struct Framebuffer(u32);
+
 impl Framebuffer {
+    #[track_caller]
     pub fn new() -> Framebuffer {
+        let _check = debug_check("glGenFramebuffers");
         let mut fb = Framebuffer(0);
         unsafe {
             gl::GenFramebuffers(1, &mut fb.0);
         }
+        trace_call!(GenFramebuffer, fb.0);
         fb
     }
 
+    /// Names the framebuffer in driver debug messages and debugging tools.
+    #[track_caller]
+    pub fn label(&self, label: &str) {
+        debug::object_label(gl::FRAMEBUFFER, self.0, label);
+    }
+
+    #[track_caller]
     pub fn bind(&self) {
+        if !state::set(&[Slot::ReadFramebuffer, Slot::DrawFramebuffer], self.0) {
+            return;
+        }
+        let _check = debug_check("glBindFramebuffer");
+        trace_call!(BindFramebuffer, gl::FRAMEBUFFER, self.0);
         unsafe {
             gl::BindFramebuffer(gl::FRAMEBUFFER, self.0);
         }
     }
 
+    #[track_caller]
     pub fn bind_read(&self) {
+        if !state::set(&[Slot::ReadFramebuffer], self.0) {
+            return;
+        }
+        let _check = debug_check("glBindFramebuffer");
+        trace_call!(BindFramebuffer, gl::READ_FRAMEBUFFER, self.0);
         unsafe {
             gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.0);
         }
     }
 
+    #[track_caller]
     pub fn bind_draw(&self) {
+        if !state::set(&[Slot::DrawFramebuffer], self.0) {
+            return;
+        }
+        let _check = debug_check("glBindFramebuffer");
+        trace_call!(BindFramebuffer, gl::DRAW_FRAMEBUFFER, self.0);
         unsafe {
             gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, self.0);
         }
     }
 
+    #[track_caller]
     pub fn texture_2d(&self, attachment: Attachment, target: TextureTarget, tex: &Texture, level: i32) {
+        let _check = debug_check("glFramebufferTexture2D");
+        trace_call!(FramebufferTexture2D, attachment, target, tex.0, level);
         unsafe {
-            gl::FramebufferTexture2D(gl::FRAMEBUFFER, attachment, target, tex.0, level);
+            gl::FramebufferTexture2D(gl::FRAMEBUFFER, attachment.0, target.0, tex.0, level);
+        }
+    }
+
+    #[track_caller]
+    pub fn renderbuffer(&self, attachment: Attachment, rb: &Renderbuffer) {
+        let _check = debug_check("glFramebufferRenderbuffer");
+        trace_call!(FramebufferRenderbuffer, attachment, rb.0);
+        unsafe {
+            gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, attachment.0, gl::RENDERBUFFER, rb.0);
         }
     }
 }
 
 impl Drop for Framebuffer {
     fn drop(&mut self) {
+        let _check = debug_check("glDeleteFramebuffers");
+        state::deleted(state::Object::Framebuffer, self.0);
+        trace_call!(DeleteFramebuffer, self.0);
         unsafe {
             gl::DeleteFramebuffers(1, &self.0);
         }
     }
 }
 
+#[track_caller]
 pub fn unbind_framebuffer() {
+    if !state::set(&[Slot::ReadFramebuffer, Slot::DrawFramebuffer], 0) {
+        return;
+    }
+    let _check = debug_check("glBindFramebuffer");
+    trace_call!(BindFramebuffer, gl::FRAMEBUFFER, 0u32);
     unsafe {
         gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
     }
 }
 
+#[track_caller]
 pub fn unbind_framebuffer_read() {
+    if !state::set(&[Slot::ReadFramebuffer], 0) {
+        return;
+    }
+    let _check = debug_check("glBindFramebuffer");
+    trace_call!(BindFramebuffer, gl::READ_FRAMEBUFFER, 0u32);
     unsafe {
         gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
     }
 }
 
+#[track_caller]
 pub fn unbind_framebuffer_draw() {
+    if !state::set(&[Slot::DrawFramebuffer], 0) {
+        return;
+    }
+    let _check = debug_check("glBindFramebuffer");
+    trace_call!(BindFramebuffer, gl::DRAW_FRAMEBUFFER, 0u32);
     unsafe {
         gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, 0);
     }
 }
 
+#[track_caller]
 pub fn draw_buffers(bufs: &[Attachment]) {
+    let _check = debug_check("glDrawBuffers");
+    trace_call!(DrawBuffers, bufs);
     unsafe {
         gl::DrawBuffers(
                 bufs.len() as i32,
-                bufs.as_ptr()
+                bufs.as_ptr() as *const u32
         );
     }
 }
 
+#[track_caller]
 pub fn bind_frag_data_location(p: &Program, cn: u32, name: &str) {
+    let _check = debug_check("glBindFragDataLocation");
+    trace_call!(BindFragDataLocation, p.0, cn, name);
     unsafe {
         let name_c = ffi::CString::new(name).unwrap();
         gl::BindFragDataLocation(p.0, cn, name_c.as_ptr());
     }
 }
 
+#[track_caller]
 pub fn blit_framebuffer(
     sx0: i32, sy0: i32, sx1: i32, sy1: i32,
     dx0: i32, dy0: i32, dx1: i32, dy1: i32,
     mask: ClearFlags, filter: TextureValue) {
+    let _check = debug_check("glBlitFramebuffer");
+    let mask = mask.internal();
+    trace_call!(BlitFramebuffer, sx0, sy0, sx1, sy1, dx0, dy0, dx1, dy1, mask, filter);
     unsafe {
         gl::BlitFramebuffer(
             sx0, sy0, sx1, sy1,
             dx0, dy0, dx1, dy1,
-            mask.internal(), filter as u32
+            mask, filter.0 as u32
         );
     }
 }
 
+#[track_caller]
 pub fn read_buffer(a: Attachment) {
+    let _check = debug_check("glReadBuffer");
+    trace_call!(ReadBuffer, a);
+    unsafe {
+        gl::ReadBuffer(a.0);
+    }
+}
+
+/// Reads a block of pixels from the currently bound read framebuffer.
+/// Rows are returned bottom-up, as is usual for gl.
+#[track_caller]
+pub fn read_pixels(x: i32, y: i32, width: u32, height: u32, format: TextureFormat, ty: Type, pixels: &mut [u8]) {
+    let _check = debug_check("glReadPixels");
+    trace_call!(ReadPixels, x, y, width, height, format, ty, pixels.len());
     unsafe {
-        gl::ReadBuffer(a);
+        gl::ReadPixels(x, y,
+                       width as i32,
+                       height as i32,
+                       format.0,
+                       ty.0,
+                       pixels.as_mut_ptr() as *mut gl::types::GLvoid);
     }
 }
 
-pub type TargetBuffer = u32;
-pub const COLOR: TargetBuffer = gl::COLOR;
+gl_enum! {
+    TargetBuffer
+}
+pub const COLOR: TargetBuffer = TargetBuffer(gl::COLOR);
 
+#[track_caller]
 pub fn clear_buffer(buffer: TargetBuffer, draw_buffer: i32, values: &[f32]) {
+    let _check = debug_check("glClearBufferfv");
+    trace_call!(ClearBufferfv, buffer, draw_buffer, values);
     unsafe {
-        gl::ClearBufferfv(buffer, draw_buffer, values.as_ptr());
+        gl::ClearBufferfv(buffer.0, draw_buffer, values.as_ptr());
     }
 }
+
@@ -0,0 +1,169 @@
+//! Known driver bugs and the workarounds the renderer should use
+//! for them.
+//!
+//! The driver is identified at `gl::init` from `GL_VENDOR`,
+//! `GL_RENDERER` and `GL_VERSION`. Missing features aren't quirks,
+//! see `caps`. Quirks can also be forced on
+//! (e.g. to test a workaround on a working driver) with `force` or
+//! the `STEVEN_GL_QUIRKS` environment variable, which takes a comma
+//! separated list of quirk names (or `all`).
+
+use std::env;
+use std::fmt;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// `Quirk` is a driver behaviour that needs a workaround.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Quirk {
+    /// Array uniforms are only found when looked up with a `[0]`
+    /// suffix (e.g. `modelMatrix[0]`). By default arrays are looked
+    /// up by their bare name, see steven#25.
+    UniformArrayIndexSuffix,
+    /// `texelFetch` from a multisampled texture returns garbage, the
+    /// scene is resolved with a framebuffer blit instead.
+    MultisampleTexelFetch,
+    /// 16 bit index buffers are mishandled, 32 bit indices are
+    /// always used.
+    NoShortIndices,
+}
+
+pub const ALL: [Quirk; 3] = [
+    Quirk::UniformArrayIndexSuffix,
+    Quirk::MultisampleTexelFetch,
+    Quirk::NoShortIndices,
+];
+
+impl Quirk {
+    fn bit(self) -> u32 {
+        1 << (self as u32)
+    }
+
+    /// The name used for the quirk in `STEVEN_GL_QUIRKS`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Quirk::UniformArrayIndexSuffix => "uniform_array_index_suffix",
+            Quirk::MultisampleTexelFetch => "multisample_texel_fetch",
+            Quirk::NoShortIndices => "no_short_indices",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Quirk> {
+        ALL.iter().cloned().find(|v| v.name() == name)
+    }
+}
+
+impl fmt::Display for Quirk {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Strings identifying the driver of the current context.
+#[derive(Clone, Debug, Default)]
+pub struct DriverInfo {
+    pub vendor: String,
+    pub renderer: String,
+    pub version: String,
+}
+
+/// A known quirk. Each non-empty string must be contained in the
+/// matching driver string (case insensitive).
+struct Entry {
+    vendor: &'static str,
+    renderer: &'static str,
+    version: &'static str,
+    quirk: Quirk,
+}
+
+const KNOWN: &[Entry] = &[
+    // Legacy AMD drivers (fglrx/Catalyst)
+    Entry { vendor: "ATI Technologies", renderer: "", version: "", quirk: Quirk::UniformArrayIndexSuffix },
+    Entry { vendor: "ATI Technologies", renderer: "", version: "", quirk: Quirk::MultisampleTexelFetch },
+    // Mesa's old i915/i965 classic drivers
+    Entry { vendor: "Intel Open Source Technology Center", renderer: "", version: "Mesa 10.", quirk: Quirk::MultisampleTexelFetch },
+    // Intel's drivers on macOS
+    Entry { vendor: "Intel", renderer: "Intel", version: "INTEL-", quirk: Quirk::UniformArrayIndexSuffix },
+    // VMware's virtual gpu
+    Entry { vendor: "VMware", renderer: "SVGA3D", version: "", quirk: Quirk::NoShortIndices },
+];
+
+static DETECTED: AtomicU32 = AtomicU32::new(0);
+static FORCED: AtomicU32 = AtomicU32::new(0);
+static DRIVER: Mutex<Option<DriverInfo>> = Mutex::new(None);
+
+fn contains(haystack: &str, needle: &str) -> bool {
+    needle.is_empty() || haystack.to_lowercase().contains(&needle.to_lowercase())
+}
+
+/// Returns the known quirks of the passed driver.
+pub fn lookup(info: &DriverInfo) -> Vec<Quirk> {
+    let mut quirks = vec![];
+    for entry in KNOWN {
+        if contains(&info.vendor, entry.vendor)
+            && contains(&info.renderer, entry.renderer)
+            && contains(&info.version, entry.version)
+            && !quirks.contains(&entry.quirk) {
+            quirks.push(entry.quirk);
+        }
+    }
+    quirks
+}
+
+/// Identifies the driver of the current context and enables the
+/// quirks known for it. Called by `gl::init`.
+pub(super) fn init() {
+    let info = DriverInfo {
+        vendor: super::get_string(super::VENDOR),
+        renderer: super::get_string(super::RENDERER),
+        version: super::get_string(super::VERSION),
+    };
+    let detected = lookup(&info).into_iter().fold(0, |bits, q| bits | q.bit());
+    DETECTED.store(detected, Ordering::Relaxed);
+    *DRIVER.lock().unwrap_or_else(|v| v.into_inner()) = Some(info);
+
+    if let Ok(names) = env::var("STEVEN_GL_QUIRKS") {
+        for name in names.split(',').map(str::trim).filter(|v| !v.is_empty()) {
+            if name == "all" {
+                ALL.iter().for_each(|q| force(*q));
+            } else if let Some(q) = Quirk::from_name(name) {
+                force(q);
+            } else {
+                println!("Unknown gl quirk {}", name);
+            }
+        }
+    }
+    for q in &ALL {
+        if has(*q) {
+            println!("Using workaround for gl quirk {}", q);
+        }
+    }
+}
+
+/// Returns the driver of the current context, if gl has been
+/// initialized.
+pub fn driver_info() -> Option<DriverInfo> {
+    DRIVER.lock().unwrap_or_else(|v| v.into_inner()).clone()
+}
+
+/// Returns whether the workaround for the quirk should be used.
+pub fn has(quirk: Quirk) -> bool {
+    (DETECTED.load(Ordering::Relaxed) | FORCED.load(Ordering::Relaxed)) & quirk.bit() != 0
+}
+
+/// Enables the quirk regardless of the driver in use. Forced quirks
+/// are kept when gl is re-initialized.
+pub fn force(quirk: Quirk) {
+    FORCED.fetch_or(quirk.bit(), Ordering::Relaxed);
+}
+
+/// Stops forcing the quirk, it will still be used if the driver is
+/// known to need it.
+pub fn unforce(quirk: Quirk) {
+    FORCED.fetch_and(!quirk.bit(), Ordering::Relaxed);
+}
+
+/// Returns the quirks currently in use.
+pub fn active() -> Vec<Quirk> {
+    ALL.iter().cloned().filter(|q| has(*q)).collect()
+}
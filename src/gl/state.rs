@@ -0,0 +1,138 @@
+//! A shadow of the context's bindings so redundant binds can be
+//! skipped.
+//!
+//! Only state changed through this module is tracked, code calling gl
+//! directly must call `invalidate_state_cache` afterwards.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// A piece of context state that is shadowed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(super) enum Slot {
+    Program,
+    VertexArray,
+    /// Element buffer bindings belong to the bound vertex array.
+    Buffer(super::BufferTarget),
+    /// (target, index)
+    IndexedBuffer(super::BufferTarget, u32),
+    ActiveTexture,
+    /// (unit, target)
+    Texture(u32, super::TextureTarget),
+    ReadFramebuffer,
+    DrawFramebuffer,
+    Renderbuffer,
+    Flag(super::Flag),
+}
+
+/// The kind of object a slot binds.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(super) enum Object {
+    Program,
+    VertexArray,
+    Buffer,
+    Texture,
+    Framebuffer,
+    Renderbuffer,
+    Query,
+}
+
+impl Slot {
+    fn object(self) -> Option<Object> {
+        match self {
+            Slot::Program => Some(Object::Program),
+            Slot::VertexArray => Some(Object::VertexArray),
+            Slot::Buffer(_) | Slot::IndexedBuffer(_, _) => Some(Object::Buffer),
+            Slot::Texture(_, _) => Some(Object::Texture),
+            Slot::ReadFramebuffer | Slot::DrawFramebuffer => Some(Object::Framebuffer),
+            Slot::Renderbuffer => Some(Object::Renderbuffer),
+            Slot::ActiveTexture | Slot::Flag(_) => None,
+        }
+    }
+}
+
+/// Counts of the binds/state changes made through the `gl` module.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StateStats {
+    /// Changes passed on to the driver.
+    pub issued: u64,
+    /// Changes that were redundant. When the cache is disabled these
+    /// are still issued.
+    pub skipped: u64,
+}
+
+struct Cache {
+    enabled: bool,
+    // Missing slots are unknown
+    values: HashMap<Slot, u32>,
+    stats: StateStats,
+}
+
+thread_local! {
+    // Contexts are current on a single thread
+    static CACHE: RefCell<Cache> = RefCell::new(Cache {
+        enabled: true,
+        values: HashMap::new(),
+        stats: StateStats::default(),
+    });
+}
+
+/// Records `slots` being set to `value`, returning false if the call
+/// is redundant and should be skipped.
+pub(super) fn set(slots: &[Slot], value: u32) -> bool {
+    CACHE.with(|c| {
+        let mut c = c.borrow_mut();
+        let mut changed = false;
+        for slot in slots {
+            let slot_changed = c.values.insert(*slot, value) != Some(value);
+            // The element buffer binding is part of the vertex array
+            if *slot == Slot::VertexArray && slot_changed {
+                c.values.remove(&Slot::Buffer(super::ELEMENT_ARRAY_BUFFER));
+            }
+            changed |= slot_changed;
+        }
+        if changed {
+            c.stats.issued += 1;
+        } else {
+            c.stats.skipped += 1;
+        }
+        changed || !c.enabled
+    })
+}
+
+/// Returns the shadowed value of the slot, if known.
+pub(super) fn get(slot: Slot) -> Option<u32> {
+    CACHE.with(|c| c.borrow().values.get(&slot).cloned())
+}
+
+/// Forgets any binding of the deleted object, its id may be reused.
+pub(super) fn deleted(object: Object, id: u32) {
+    CACHE.with(|c| {
+        let mut c = c.borrow_mut();
+        // Deleting the bound vertex array binds 0, which has its own
+        // element buffer
+        if object == Object::VertexArray && c.values.get(&Slot::VertexArray) == Some(&id) {
+            c.values.remove(&Slot::Buffer(super::ELEMENT_ARRAY_BUFFER));
+        }
+        c.values.retain(|slot, v| !(*v == id && slot.object() == Some(object)));
+    });
+}
+
+/// Forgets all shadowed state, e.g. after gl has been used without
+/// going through this module. Done by `gl::init` for new contexts.
+pub fn invalidate_state_cache() {
+    CACHE.with(|c| c.borrow_mut().values.clear());
+}
+
+/// Enables skipping redundant state changes, on by default. When
+/// disabled every change is issued but still counted, for measuring
+/// the cache.
+pub fn set_state_cache(enabled: bool) {
+    CACHE.with(|c| c.borrow_mut().enabled = enabled);
+}
+
+/// Returns the state change counts since the last call, e.g. once per
+/// frame.
+pub fn take_state_stats() -> StateStats {
+    CACHE.with(|c| std::mem::take(&mut c.borrow_mut().stats))
+}
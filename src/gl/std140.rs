@@ -0,0 +1,102 @@
+//! Lays out data following the std140 rules used by
+//! `layout(std140)` uniform blocks, so a buffer can be filled without
+//! querying the offsets of each member.
+//!
+//! Values must be written in the order the block declares them.
+
+use byteorder::{WriteBytesExt, NativeEndian};
+use cgmath::{Matrix4, Vector2, Vector3, Vector4};
+
+/// Builds the contents of a uniform buffer, inserting the padding
+/// std140 requires before each value.
+#[derive(Default)]
+pub struct Writer {
+    data: Vec<u8>,
+}
+
+impl Writer {
+    pub fn new() -> Writer {
+        Default::default()
+    }
+
+    fn align(&mut self, align: usize) {
+        let len = self.data.len().next_multiple_of(align);
+        self.data.resize(len, 0);
+    }
+
+    fn floats(&mut self, v: &[f32]) {
+        for f in v {
+            self.data.write_f32::<NativeEndian>(*f).unwrap();
+        }
+    }
+
+    pub fn float(&mut self, v: f32) {
+        self.align(4);
+        self.floats(&[v]);
+    }
+
+    pub fn int(&mut self, v: i32) {
+        self.align(4);
+        self.data.write_i32::<NativeEndian>(v).unwrap();
+    }
+
+    pub fn vec2(&mut self, v: Vector2<f32>) {
+        self.align(8);
+        self.floats(&[v.x, v.y]);
+    }
+
+    /// Aligned like a `vec4`, a following scalar fills the gap.
+    pub fn vec3(&mut self, v: Vector3<f32>) {
+        self.align(16);
+        self.floats(&[v.x, v.y, v.z]);
+    }
+
+    pub fn vec4(&mut self, v: Vector4<f32>) {
+        self.align(16);
+        self.floats(&[v.x, v.y, v.z, v.w]);
+    }
+
+    /// Written as 4 `vec4` columns.
+    pub fn mat4(&mut self, m: &Matrix4<f32>) {
+        self.align(16);
+        let m: &[f32; 16] = m.as_ref();
+        self.floats(m);
+    }
+
+    /// Each element of an array takes at least 16 bytes.
+    pub fn float_array(&mut self, v: &[f32]) {
+        for f in v {
+            self.align(16);
+            self.floats(&[*f]);
+        }
+        self.align(16);
+    }
+
+    pub fn vec4_array(&mut self, v: &[Vector4<f32>]) {
+        for v in v {
+            self.vec4(*v);
+        }
+    }
+
+    pub fn mat4_array(&mut self, m: &[Matrix4<f32>]) {
+        for m in m {
+            self.mat4(m);
+        }
+    }
+
+    /// Returns the number of bytes written so far.
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Returns the data, padded to a multiple of 16 bytes like the
+    /// block's size.
+    pub fn finish(mut self) -> Vec<u8> {
+        self.align(16);
+        self.data
+    }
+}
@@ -0,0 +1,905 @@
+//! Recording of the calls made through the `gl` module into a trace
+//! file, and replaying them on another context.
+//!
+//! Recording is started at `gl::init` when `STEVEN_GL_TRACE` is set to
+//! the path of the file to write, or with `start` straight after init.
+//! Traces have to start with the context as objects created before
+//! recording started can't be replayed. `frame` marks the end of each
+//! frame and flushes the file. Only calls made on the thread that
+//! started recording, the one the context is current on, are recorded.
+//!
+//! A trace is a header (`MAGIC`, `VERSION`, the initial viewport size)
+//! followed by the calls, each a little endian `u16` opcode followed by
+//! its arguments. Slices and strings are prefixed by their length as a
+//! `u32`. Object ids and uniform/attribute locations are the ones of the
+//! recording context, the `Replayer` maps them to its own.
+
+use super::gl;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use cgmath::Matrix4;
+use std::collections::HashMap;
+use std::ffi;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+use std::ptr;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, ThreadId};
+
+pub const MAGIC: &[u8; 8] = b"STGLTRCE";
+pub const VERSION: u32 = 2;
+
+/// Records a call if a trace is being recorded.
+macro_rules! trace_call {
+    ($op:ident $(, $arg:expr)*) => (
+        if $crate::gl::trace::recording() {
+            $crate::gl::trace::record($crate::gl::trace::Op::$op, &[$(&$arg as &dyn $crate::gl::trace::Arg),*]);
+        }
+    );
+}
+
+macro_rules! ops {
+    ($($name:ident = $val:expr,)*) => (
+        /// `Op` identifies a call in a trace.
+        #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+        pub enum Op {
+            $($name = $val,)*
+        }
+
+        impl Op {
+            fn from_raw(v: u16) -> Option<Op> {
+                match v {
+                    $($val => Some(Op::$name),)*
+                    _ => None,
+                }
+            }
+        }
+    );
+}
+
+ops! {
+    Frame = 0,
+
+    DrawArrays = 1,
+    DrawElements = 2,
+    MultiDrawElements = 3,
+    Viewport = 4,
+    ClearColor = 5,
+    Clear = 6,
+    DepthMask = 7,
+    DepthFunc = 8,
+    Enable = 9,
+    Disable = 10,
+    ActiveTexture = 11,
+    BlendFunc = 12,
+    BlendFuncSeparate = 13,
+    CullFace = 14,
+    FrontFace = 15,
+    DrawArraysInstanced = 16,
+    DrawElementsInstanced = 17,
+    DrawElementsBaseVertex = 18,
+    ClearDepth = 19,
+
+    GenTexture = 20,
+    DeleteTexture = 21,
+    BindTexture = 22,
+    GetTexImage = 23,
+    TexImage2D = 24,
+    TexSubImage2D = 25,
+    TexImage2DMultisample = 26,
+    TexImage3D = 27,
+    TexSubImage3D = 28,
+    TexParameteri = 29,
+
+    CreateProgram = 40,
+    DeleteProgram = 41,
+    AttachShader = 42,
+    LinkProgram = 43,
+    UseProgram = 44,
+    UniformLocation = 45,
+    AttribLocation = 46,
+    BindFragDataLocation = 47,
+    CreateShader = 48,
+    ShaderSource = 49,
+    CompileShader = 50,
+    UniformBlockIndex = 51,
+    UniformBlockBinding = 52,
+
+    Uniform1i = 60,
+    Uniform3i = 61,
+    Uniform1f = 62,
+    Uniform2f = 63,
+    Uniform3f = 64,
+    Uniform4f = 65,
+    UniformMatrix4fv = 66,
+
+    EnableVertexAttribArray = 70,
+    DisableVertexAttribArray = 71,
+    VertexAttribPointer = 72,
+    VertexAttribIPointer = 73,
+    VertexAttribDivisor = 74,
+    VertexAttrib4f = 75,
+
+    GenVertexArray = 80,
+    DeleteVertexArray = 81,
+    BindVertexArray = 82,
+
+    GenBuffer = 90,
+    DeleteBuffer = 91,
+    BindBuffer = 92,
+    BufferData = 93,
+    BufferSubData = 94,
+    BindBufferBase = 95,
+
+    GenFramebuffer = 100,
+    DeleteFramebuffer = 101,
+    BindFramebuffer = 102,
+    FramebufferTexture2D = 103,
+    DrawBuffers = 104,
+    BlitFramebuffer = 105,
+    ReadBuffer = 106,
+    ReadPixels = 107,
+    ClearBufferfv = 108,
+    FramebufferRenderbuffer = 109,
+
+    GenRenderbuffer = 110,
+    DeleteRenderbuffer = 111,
+    BindRenderbuffer = 112,
+    RenderbufferStorage = 113,
+    RenderbufferStorageMultisample = 114,
+
+    GenQuery = 120,
+    DeleteQuery = 121,
+    BeginQuery = 122,
+    EndQuery = 123,
+    QueryCounter = 124,
+
+    ClipControl = 130,
+}
+
+/// A value that can be written as an argument of a call.
+pub trait Arg {
+    fn encode(&self, out: &mut Vec<u8>);
+}
+
+impl<T: Arg + ?Sized> Arg for &T {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (**self).encode(out)
+    }
+}
+
+impl Arg for u32 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.write_u32::<LittleEndian>(*self).unwrap();
+    }
+}
+
+impl Arg for i32 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.write_i32::<LittleEndian>(*self).unwrap();
+    }
+}
+
+impl Arg for usize {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.write_u64::<LittleEndian>(*self as u64).unwrap();
+    }
+}
+
+impl Arg for f32 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.write_f32::<LittleEndian>(*self).unwrap();
+    }
+}
+
+impl Arg for bool {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8);
+    }
+}
+
+impl<T: Arg> Arg for [T] {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (self.len() as u32).encode(out);
+        for v in self {
+            v.encode(out);
+        }
+    }
+}
+
+impl Arg for [u8] {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (self.len() as u32).encode(out);
+        out.extend_from_slice(self);
+    }
+}
+
+impl Arg for str {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.as_bytes().encode(out);
+    }
+}
+
+impl Arg for Matrix4<f32> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        let m: &[f32; 16] = self.as_ref();
+        for v in m {
+            v.encode(out);
+        }
+    }
+}
+
+impl Arg for Option<&[u8]> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.is_some().encode(out);
+        if let Some(data) = self {
+            data.encode(out);
+        }
+    }
+}
+
+struct Recorder {
+    out: Box<dyn Write + Send>,
+    buf: Vec<u8>,
+    thread: ThreadId,
+}
+
+static RECORDING: AtomicBool = AtomicBool::new(false);
+static RECORDER: Mutex<Option<Recorder>> = Mutex::new(None);
+
+/// Returns whether a trace is being recorded.
+pub fn recording() -> bool {
+    RECORDING.load(Ordering::Relaxed)
+}
+
+/// Starts recording a trace of the current context into `out`,
+/// replacing any trace already being recorded.
+pub fn start<W: Write + Send + 'static>(out: W) -> io::Result<()> {
+    let mut viewport = [0; 4];
+    unsafe {
+        gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+    }
+    let mut out: Box<dyn Write + Send> = Box::new(out);
+    out.write_all(MAGIC)?;
+    out.write_u32::<LittleEndian>(VERSION)?;
+    out.write_u32::<LittleEndian>(viewport[2] as u32)?;
+    out.write_u32::<LittleEndian>(viewport[3] as u32)?;
+
+    stop()?;
+    *lock() = Some(Recorder {
+        out,
+        buf: vec![],
+        thread: thread::current().id(),
+    });
+    RECORDING.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Starts recording a trace into the file at the passed path.
+pub fn start_file<P: AsRef<Path>>(path: P) -> io::Result<()> {
+    start(BufWriter::new(File::create(path)?))
+}
+
+/// Stops recording, flushing the trace.
+pub fn stop() -> io::Result<()> {
+    RECORDING.store(false, Ordering::Relaxed);
+    match lock().take() {
+        Some(mut rec) => rec.out.flush(),
+        None => Ok(()),
+    }
+}
+
+/// Marks the end of a frame, e.g. after the window has been swapped,
+/// and flushes the trace so it is usable even if the program crashes.
+pub fn frame() {
+    if !recording() {
+        return;
+    }
+    record(Op::Frame, &[]);
+    let mut rec = lock();
+    if let Some(err) = rec.as_mut().and_then(|v| v.out.flush().err()) {
+        println!("Failed to write gl trace: {}", err);
+        RECORDING.store(false, Ordering::Relaxed);
+        *rec = None;
+    }
+}
+
+fn lock() -> ::std::sync::MutexGuard<'static, Option<Recorder>> {
+    RECORDER.lock().unwrap_or_else(|v| v.into_inner())
+}
+
+#[doc(hidden)]
+pub fn record(op: Op, args: &[&dyn Arg]) {
+    let mut lock = lock();
+    let rec = match lock.as_mut() {
+        Some(val) if val.thread == thread::current().id() => val,
+        _ => return,
+    };
+    rec.buf.clear();
+    rec.buf.write_u16::<LittleEndian>(op as u16).unwrap();
+    for arg in args {
+        arg.encode(&mut rec.buf);
+    }
+    if let Err(err) = rec.out.write_all(&rec.buf) {
+        println!("Failed to write gl trace: {}", err);
+        RECORDING.store(false, Ordering::Relaxed);
+        *lock = None;
+    }
+}
+
+/// Reads the arguments of a call.
+struct Decoder<R> {
+    r: R,
+}
+
+impl<R: Read> Decoder<R> {
+    fn u32(&mut self) -> io::Result<u32> {
+        self.r.read_u32::<LittleEndian>()
+    }
+
+    fn i32(&mut self) -> io::Result<i32> {
+        self.r.read_i32::<LittleEndian>()
+    }
+
+    fn usize(&mut self) -> io::Result<usize> {
+        Ok(self.r.read_u64::<LittleEndian>()? as usize)
+    }
+
+    fn f32(&mut self) -> io::Result<f32> {
+        self.r.read_f32::<LittleEndian>()
+    }
+
+    fn bool(&mut self) -> io::Result<bool> {
+        Ok(self.r.read_u8()? != 0)
+    }
+
+    fn len(&mut self) -> io::Result<usize> {
+        let len = self.u32()? as usize;
+        // Guards against allocating huge buffers for corrupt traces
+        if len > 1 << 30 {
+            return Err(invalid(format!("slice of {} elements", len)));
+        }
+        Ok(len)
+    }
+
+    fn bytes(&mut self) -> io::Result<Vec<u8>> {
+        let mut data = vec![0; self.len()?];
+        self.r.read_exact(&mut data)?;
+        Ok(data)
+    }
+
+    fn opt_bytes(&mut self) -> io::Result<Option<Vec<u8>>> {
+        if self.bool()? {
+            Ok(Some(self.bytes()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn string(&mut self) -> io::Result<ffi::CString> {
+        ffi::CString::new(self.bytes()?).map_err(|_| invalid("string containing a nul".to_owned()))
+    }
+
+    fn vec<T, F>(&mut self, f: F) -> io::Result<Vec<T>>
+        where F: Fn(&mut Self) -> io::Result<T> {
+        (0..self.len()?).map(|_| f(self)).collect()
+    }
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Maps the ids of one kind of object in a trace to the ones created
+/// whilst replaying it.
+struct Objects {
+    kind: &'static str,
+    ids: HashMap<u32, u32>,
+}
+
+impl Objects {
+    fn new(kind: &'static str) -> Objects {
+        Objects {
+            kind,
+            ids: HashMap::new(),
+        }
+    }
+
+    fn insert(&mut self, traced: u32, id: u32) {
+        self.ids.insert(traced, id);
+    }
+
+    /// 0 (e.g. the default framebuffer) is the same in both.
+    fn get(&self, traced: u32) -> io::Result<u32> {
+        if traced == 0 {
+            return Ok(0);
+        }
+        self.ids.get(&traced)
+            .cloned()
+            .ok_or_else(|| invalid(format!("unknown {} {}", self.kind, traced)))
+    }
+
+    fn remove(&mut self, traced: u32) -> io::Result<u32> {
+        let id = self.get(traced)?;
+        self.ids.remove(&traced);
+        Ok(id)
+    }
+}
+
+/// Replays a recorded trace on the current context.
+///
+/// Uniform locations and uniform block indices are mapped per program
+/// by looking them up by name. Attribute locations aren't tied to a
+/// program when used so are mapped using the most recent lookup.
+pub struct Replayer<R> {
+    d: Decoder<R>,
+    width: u32,
+    height: u32,
+    calls: u64,
+
+    textures: Objects,
+    programs: Objects,
+    shaders: Objects,
+    vertex_arrays: Objects,
+    buffers: Objects,
+    framebuffers: Objects,
+    renderbuffers: Objects,
+    queries: Objects,
+    /// (traced program, traced location) to location
+    uniforms: HashMap<(u32, i32), i32>,
+    /// (traced program, traced index) to uniform block index
+    blocks: HashMap<(u32, u32), u32>,
+    attributes: HashMap<i32, i32>,
+    /// The traced id of the program in use.
+    program: u32,
+}
+
+impl<R: Read> Replayer<R> {
+    /// Reads the header of the trace.
+    pub fn new(r: R) -> io::Result<Replayer<R>> {
+        let mut d = Decoder { r };
+        let mut magic = [0; 8];
+        d.r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a gl trace".to_owned()));
+        }
+        let version = d.u32()?;
+        if version != VERSION {
+            return Err(invalid(format!("unsupported trace version {}", version)));
+        }
+        let width = d.u32()?;
+        let height = d.u32()?;
+        Ok(Replayer {
+            d,
+            width,
+            height,
+            calls: 0,
+            textures: Objects::new("texture"),
+            programs: Objects::new("program"),
+            shaders: Objects::new("shader"),
+            vertex_arrays: Objects::new("vertex array"),
+            buffers: Objects::new("buffer"),
+            framebuffers: Objects::new("framebuffer"),
+            renderbuffers: Objects::new("renderbuffer"),
+            queries: Objects::new("query"),
+            uniforms: HashMap::new(),
+            blocks: HashMap::new(),
+            attributes: HashMap::new(),
+            program: 0,
+        })
+    }
+
+    /// Returns the size of the default framebuffer when the trace was
+    /// started.
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Returns the number of calls replayed so far.
+    pub fn calls(&self) -> u64 {
+        self.calls
+    }
+
+    /// Replays the calls of the next frame. Returns false if the trace
+    /// has ended, a trailing partial frame is still replayed.
+    pub fn next_frame(&mut self) -> io::Result<bool> {
+        let mut any = false;
+        loop {
+            let op = match self.d.r.read_u16::<LittleEndian>() {
+                Ok(val) => val,
+                Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                    super::invalidate_state_cache();
+                    return Ok(any);
+                },
+                Err(err) => return Err(err),
+            };
+            let op = Op::from_raw(op).ok_or_else(|| invalid(format!("unknown call {}", op)))?;
+            if op == Op::Frame {
+                // The replayed calls bypass the state cache
+                super::invalidate_state_cache();
+                return Ok(true);
+            }
+            any = true;
+            self.calls += 1;
+            self.replay(op)?;
+        }
+    }
+
+    fn uniform(&mut self) -> io::Result<i32> {
+        let loc = self.d.i32()?;
+        Ok(self.uniforms.get(&(self.program, loc)).cloned().unwrap_or(-1))
+    }
+
+    /// Reads an attribute's location and column, only the location is
+    /// remapped as columns follow it.
+    fn attribute(&mut self) -> io::Result<u32> {
+        let (loc, column) = (self.d.i32()?, self.d.i32()?);
+        let loc = self.attributes.get(&loc).cloned().unwrap_or(loc);
+        Ok((loc + column) as u32)
+    }
+
+    fn replay(&mut self, op: Op) -> io::Result<()> {
+        let d = &mut self.d;
+        unsafe {
+            match op {
+                Op::Frame => {},
+
+                Op::DrawArrays => gl::DrawArrays(d.u32()?, d.usize()? as i32, d.usize()? as i32),
+                Op::DrawElements => gl::DrawElements(d.u32()?, d.i32()?, d.u32()?, d.usize()? as *const _),
+                Op::DrawArraysInstanced => {
+                    let (ty, offset, count) = (d.u32()?, d.usize()?, d.usize()?);
+                    gl::DrawArraysInstanced(ty, offset as i32, count as i32, d.usize()? as i32);
+                },
+                Op::DrawElementsInstanced => {
+                    let (ty, count, dty, offset) = (d.u32()?, d.i32()?, d.u32()?, d.usize()?);
+                    gl::DrawElementsInstanced(ty, count, dty, offset as *const _, d.usize()? as i32);
+                },
+                Op::DrawElementsBaseVertex => {
+                    let (ty, count, dty, offset) = (d.u32()?, d.i32()?, d.u32()?, d.usize()?);
+                    gl::DrawElementsBaseVertex(ty, count, dty, offset as *const _, d.i32()?);
+                },
+                Op::MultiDrawElements => {
+                    let ty = d.u32()?;
+                    let count = d.vec(Decoder::i32)?;
+                    let dty = d.u32()?;
+                    let offsets = d.vec(Decoder::usize)?;
+                    if count.len() != offsets.len() {
+                        return Err(invalid("mismatched glMultiDrawElements counts".to_owned()));
+                    }
+                    gl::MultiDrawElements(ty, count.as_ptr(), dty, offsets.as_ptr() as *const _, count.len() as i32);
+                },
+                Op::Viewport => gl::Viewport(d.i32()?, d.i32()?, d.i32()?, d.i32()?),
+                Op::ClearColor => gl::ClearColor(d.f32()?, d.f32()?, d.f32()?, d.f32()?),
+                Op::Clear => gl::Clear(d.u32()?),
+                Op::ClearDepth => gl::ClearDepth(d.f32()? as f64),
+                Op::DepthMask => gl::DepthMask(d.bool()? as u8),
+                Op::DepthFunc => gl::DepthFunc(d.u32()?),
+                Op::Enable => gl::Enable(d.u32()?),
+                Op::Disable => gl::Disable(d.u32()?),
+                Op::ActiveTexture => gl::ActiveTexture(gl::TEXTURE0 + d.u32()?),
+                Op::BlendFunc => gl::BlendFunc(d.u32()?, d.u32()?),
+                Op::BlendFuncSeparate => gl::BlendFuncSeparate(d.u32()?, d.u32()?, d.u32()?, d.u32()?),
+                Op::CullFace => gl::CullFace(d.u32()?),
+                Op::FrontFace => gl::FrontFace(d.u32()?),
+
+                Op::GenTexture => {
+                    let mut id = 0;
+                    gl::GenTextures(1, &mut id);
+                    self.textures.insert(d.u32()?, id);
+                },
+                Op::DeleteTexture => {
+                    let id = self.textures.remove(d.u32()?)?;
+                    gl::DeleteTextures(1, &id);
+                },
+                Op::BindTexture => {
+                    let target = d.u32()?;
+                    gl::BindTexture(target, self.textures.get(d.u32()?)?);
+                },
+                Op::GetTexImage => {
+                    let (target, level, format, ty) = (d.u32()?, d.i32()?, d.u32()?, d.u32()?);
+                    let mut data = vec![0u8; d.usize()?];
+                    gl::GetTexImage(target, level, format, ty, data.as_mut_ptr() as *mut _);
+                },
+                Op::TexImage2D => {
+                    let (target, level, internal_format) = (d.u32()?, d.i32()?, d.u32()?);
+                    let (width, height, format, ty) = (d.u32()?, d.u32()?, d.u32()?, d.u32()?);
+                    let data = d.opt_bytes()?;
+                    gl::TexImage2D(target, level, internal_format as i32, width as i32, height as i32, 0,
+                                   format, ty, data.as_ref().map_or(ptr::null(), |v| v.as_ptr() as *const _));
+                },
+                Op::TexSubImage2D => {
+                    let (target, level, x, y) = (d.u32()?, d.i32()?, d.u32()?, d.u32()?);
+                    let (width, height, format, ty) = (d.u32()?, d.u32()?, d.u32()?, d.u32()?);
+                    let data = d.bytes()?;
+                    gl::TexSubImage2D(target, level, x as i32, y as i32, width as i32, height as i32,
+                                      format, ty, data.as_ptr() as *const _);
+                },
+                Op::TexImage2DMultisample => {
+                    let (target, samples, width, height) = (d.u32()?, d.i32()?, d.u32()?, d.u32()?);
+                    let (format, fixed) = (d.u32()?, d.bool()?);
+                    // The replaying driver may support fewer samples
+                    let mut max = 0;
+                    gl::GetIntegerv(gl::MAX_SAMPLES, &mut max);
+                    gl::TexImage2DMultisample(target, samples.min(max), format,
+                                              width as i32, height as i32, fixed as u8);
+                },
+                Op::TexImage3D => {
+                    let (target, level, width, height, depth) = (d.u32()?, d.i32()?, d.u32()?, d.u32()?, d.u32()?);
+                    let (format, ty) = (d.u32()?, d.u32()?);
+                    let data = d.bytes()?;
+                    gl::TexImage3D(target, level, format as i32, width as i32, height as i32, depth as i32, 0,
+                                   format, ty, data.as_ptr() as *const _);
+                },
+                Op::TexSubImage3D => {
+                    let (target, level, x, y, z) = (d.u32()?, d.i32()?, d.u32()?, d.u32()?, d.u32()?);
+                    let (width, height, depth) = (d.u32()?, d.u32()?, d.u32()?);
+                    let (format, ty) = (d.u32()?, d.u32()?);
+                    let data = d.bytes()?;
+                    gl::TexSubImage3D(target, level, x as i32, y as i32, z as i32,
+                                      width as i32, height as i32, depth as i32,
+                                      format, ty, data.as_ptr() as *const _);
+                },
+                Op::TexParameteri => gl::TexParameteri(d.u32()?, d.u32()?, d.i32()?),
+
+                Op::CreateProgram => {
+                    let id = gl::CreateProgram();
+                    self.programs.insert(d.u32()?, id);
+                },
+                Op::DeleteProgram => {
+                    let id = self.programs.remove(d.u32()?)?;
+                    gl::DeleteProgram(id);
+                },
+                Op::AttachShader => {
+                    let program = self.programs.get(d.u32()?)?;
+                    gl::AttachShader(program, self.shaders.get(d.u32()?)?);
+                },
+                Op::LinkProgram => gl::LinkProgram(self.programs.get(d.u32()?)?),
+                Op::UseProgram => {
+                    self.program = d.u32()?;
+                    gl::UseProgram(self.programs.get(self.program)?);
+                },
+                Op::UniformLocation => {
+                    let program = d.u32()?;
+                    let name = d.string()?;
+                    let traced = d.i32()?;
+                    let loc = gl::GetUniformLocation(self.programs.get(program)?, name.as_ptr());
+                    self.uniforms.insert((program, traced), loc);
+                },
+                Op::AttribLocation => {
+                    let program = d.u32()?;
+                    let name = d.string()?;
+                    let traced = d.i32()?;
+                    let loc = gl::GetAttribLocation(self.programs.get(program)?, name.as_ptr());
+                    self.attributes.insert(traced, loc);
+                },
+                Op::BindFragDataLocation => {
+                    let (program, color) = (d.u32()?, d.u32()?);
+                    let name = d.string()?;
+                    gl::BindFragDataLocation(self.programs.get(program)?, color, name.as_ptr());
+                },
+                Op::CreateShader => {
+                    let id = gl::CreateShader(d.u32()?);
+                    self.shaders.insert(d.u32()?, id);
+                },
+                Op::ShaderSource => {
+                    let shader = self.shaders.get(d.u32()?)?;
+                    let src = d.string()?;
+                    gl::ShaderSource(shader, 1, &src.as_ptr(), ptr::null());
+                },
+                Op::CompileShader => gl::CompileShader(self.shaders.get(d.u32()?)?),
+                Op::UniformBlockIndex => {
+                    let program = d.u32()?;
+                    let name = d.string()?;
+                    let traced = d.u32()?;
+                    let index = gl::GetUniformBlockIndex(self.programs.get(program)?, name.as_ptr());
+                    self.blocks.insert((program, traced), index);
+                },
+                Op::UniformBlockBinding => {
+                    let (program, traced, binding) = (d.u32()?, d.u32()?, d.u32()?);
+                    let index = self.blocks.get(&(program, traced)).cloned().unwrap_or(gl::INVALID_INDEX);
+                    gl::UniformBlockBinding(self.programs.get(program)?, index, binding);
+                },
+
+                Op::Uniform1i => gl::Uniform1i(self.uniform()?, self.d.i32()?),
+                Op::Uniform3i => gl::Uniform3i(self.uniform()?, self.d.i32()?, self.d.i32()?, self.d.i32()?),
+                Op::Uniform1f => gl::Uniform1f(self.uniform()?, self.d.f32()?),
+                Op::Uniform2f => gl::Uniform2f(self.uniform()?, self.d.f32()?, self.d.f32()?),
+                Op::Uniform3f => gl::Uniform3f(self.uniform()?, self.d.f32()?, self.d.f32()?, self.d.f32()?),
+                Op::Uniform4f => gl::Uniform4f(self.uniform()?, self.d.f32()?, self.d.f32()?, self.d.f32()?, self.d.f32()?),
+                Op::UniformMatrix4fv => {
+                    let loc = self.uniform()?;
+                    let count = self.d.len()?;
+                    let m = (0..count * 16).map(|_| self.d.f32()).collect::<io::Result<Vec<_>>>()?;
+                    gl::UniformMatrix4fv(loc, count as i32, false as u8, m.as_ptr());
+                },
+
+                Op::EnableVertexAttribArray => gl::EnableVertexAttribArray(self.attribute()?),
+                Op::DisableVertexAttribArray => gl::DisableVertexAttribArray(self.attribute()?),
+                Op::VertexAttribPointer => {
+                    let index = self.attribute()?;
+                    let d = &mut self.d;
+                    let (size, ty, normalized, stride, offset) = (d.i32()?, d.u32()?, d.bool()?, d.i32()?, d.i32()?);
+                    gl::VertexAttribPointer(index, size, ty, normalized as u8, stride, offset as *const _);
+                },
+                Op::VertexAttribIPointer => {
+                    let index = self.attribute()?;
+                    let d = &mut self.d;
+                    let (size, ty, stride, offset) = (d.i32()?, d.u32()?, d.i32()?, d.i32()?);
+                    gl::VertexAttribIPointer(index, size, ty, stride, offset as *const _);
+                },
+                Op::VertexAttribDivisor => {
+                    let index = self.attribute()?;
+                    gl::VertexAttribDivisor(index, self.d.u32()?);
+                },
+                Op::VertexAttrib4f => {
+                    let index = self.attribute()?;
+                    let d = &mut self.d;
+                    gl::VertexAttrib4f(index, d.f32()?, d.f32()?, d.f32()?, d.f32()?);
+                },
+
+                Op::GenVertexArray => {
+                    let mut id = 0;
+                    gl::GenVertexArrays(1, &mut id);
+                    self.vertex_arrays.insert(d.u32()?, id);
+                },
+                Op::DeleteVertexArray => {
+                    let id = self.vertex_arrays.remove(d.u32()?)?;
+                    gl::DeleteVertexArrays(1, &id);
+                },
+                Op::BindVertexArray => gl::BindVertexArray(self.vertex_arrays.get(d.u32()?)?),
+
+                Op::GenBuffer => {
+                    let mut id = 0;
+                    gl::GenBuffers(1, &mut id);
+                    self.buffers.insert(d.u32()?, id);
+                },
+                Op::DeleteBuffer => {
+                    let id = self.buffers.remove(d.u32()?)?;
+                    gl::DeleteBuffers(1, &id);
+                },
+                Op::BindBuffer => {
+                    let target = d.u32()?;
+                    gl::BindBuffer(target, self.buffers.get(d.u32()?)?);
+                },
+                Op::BufferData => {
+                    let target = d.u32()?;
+                    let data = d.bytes()?;
+                    gl::BufferData(target, data.len() as isize, data.as_ptr() as *const _, d.u32()?);
+                },
+                Op::BufferSubData => {
+                    let (target, offset) = (d.u32()?, d.usize()?);
+                    let data = d.bytes()?;
+                    gl::BufferSubData(target, offset as isize, data.len() as isize, data.as_ptr() as *const _);
+                },
+                Op::BindBufferBase => {
+                    let (target, index) = (d.u32()?, d.u32()?);
+                    gl::BindBufferBase(target, index, self.buffers.get(d.u32()?)?);
+                },
+
+                Op::GenFramebuffer => {
+                    let mut id = 0;
+                    gl::GenFramebuffers(1, &mut id);
+                    self.framebuffers.insert(d.u32()?, id);
+                },
+                Op::DeleteFramebuffer => {
+                    let id = self.framebuffers.remove(d.u32()?)?;
+                    gl::DeleteFramebuffers(1, &id);
+                },
+                Op::BindFramebuffer => {
+                    let target = d.u32()?;
+                    gl::BindFramebuffer(target, self.framebuffers.get(d.u32()?)?);
+                },
+                Op::FramebufferTexture2D => {
+                    let (attachment, target) = (d.u32()?, d.u32()?);
+                    let tex = self.textures.get(d.u32()?)?;
+                    gl::FramebufferTexture2D(gl::FRAMEBUFFER, attachment, target, tex, d.i32()?);
+                },
+                Op::DrawBuffers => {
+                    let bufs = d.vec(Decoder::u32)?;
+                    gl::DrawBuffers(bufs.len() as i32, bufs.as_ptr());
+                },
+                Op::BlitFramebuffer => {
+                    let (sx0, sy0, sx1, sy1) = (d.i32()?, d.i32()?, d.i32()?, d.i32()?);
+                    let (dx0, dy0, dx1, dy1) = (d.i32()?, d.i32()?, d.i32()?, d.i32()?);
+                    gl::BlitFramebuffer(sx0, sy0, sx1, sy1, dx0, dy0, dx1, dy1, d.u32()?, d.i32()? as u32);
+                },
+                Op::ReadBuffer => gl::ReadBuffer(d.u32()?),
+                Op::ReadPixels => {
+                    let (x, y, width, height) = (d.i32()?, d.i32()?, d.u32()?, d.u32()?);
+                    let (format, ty) = (d.u32()?, d.u32()?);
+                    let mut data = vec![0u8; d.usize()?];
+                    gl::ReadPixels(x, y, width as i32, height as i32, format, ty, data.as_mut_ptr() as *mut _);
+                },
+                Op::ClearBufferfv => {
+                    let (buffer, draw_buffer) = (d.u32()?, d.i32()?);
+                    let values = d.vec(Decoder::f32)?;
+                    gl::ClearBufferfv(buffer, draw_buffer, values.as_ptr());
+                },
+                Op::FramebufferRenderbuffer => {
+                    let attachment = d.u32()?;
+                    let rb = self.renderbuffers.get(d.u32()?)?;
+                    gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, attachment, gl::RENDERBUFFER, rb);
+                },
+
+                Op::GenRenderbuffer => {
+                    let mut id = 0;
+                    gl::GenRenderbuffers(1, &mut id);
+                    self.renderbuffers.insert(d.u32()?, id);
+                },
+                Op::DeleteRenderbuffer => {
+                    let id = self.renderbuffers.remove(d.u32()?)?;
+                    gl::DeleteRenderbuffers(1, &id);
+                },
+                Op::BindRenderbuffer => gl::BindRenderbuffer(gl::RENDERBUFFER, self.renderbuffers.get(d.u32()?)?),
+                Op::RenderbufferStorage => {
+                    let (format, width, height) = (d.u32()?, d.u32()?, d.u32()?);
+                    gl::RenderbufferStorage(gl::RENDERBUFFER, format, width as i32, height as i32);
+                },
+                Op::RenderbufferStorageMultisample => {
+                    let (samples, format, width, height) = (d.i32()?, d.u32()?, d.u32()?, d.u32()?);
+                    gl::RenderbufferStorageMultisample(gl::RENDERBUFFER, samples, format, width as i32, height as i32);
+                },
+
+                Op::GenQuery => {
+                    let mut id = 0;
+                    gl::GenQueries(1, &mut id);
+                    self.queries.insert(d.u32()?, id);
+                },
+                Op::DeleteQuery => {
+                    let id = self.queries.remove(d.u32()?)?;
+                    gl::DeleteQueries(1, &id);
+                },
+                Op::BeginQuery => {
+                    let target = d.u32()?;
+                    gl::BeginQuery(target, self.queries.get(d.u32()?)?);
+                },
+                Op::EndQuery => gl::EndQuery(d.u32()?),
+                Op::QueryCounter => gl::QueryCounter(self.queries.get(d.u32()?)?, gl::TIMESTAMP),
+                Op::ClipControl => gl::ClipControl(gl::LOWER_LEFT, d.u32()?),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R> Drop for Replayer<R> {
+    fn drop(&mut self) {
+        unsafe {
+            for id in self.textures.ids.values() {
+                gl::DeleteTextures(1, id);
+            }
+            for id in self.programs.ids.values() {
+                gl::DeleteProgram(*id);
+            }
+            for id in self.shaders.ids.values() {
+                gl::DeleteShader(*id);
+            }
+            for id in self.vertex_arrays.ids.values() {
+                gl::DeleteVertexArrays(1, id);
+            }
+            for id in self.buffers.ids.values() {
+                gl::DeleteBuffers(1, id);
+            }
+            for id in self.framebuffers.ids.values() {
+                gl::DeleteFramebuffers(1, id);
+            }
+            for id in self.renderbuffers.ids.values() {
+                gl::DeleteRenderbuffers(1, id);
+            }
+            for id in self.queries.ids.values() {
+                gl::DeleteQueries(1, id);
+            }
+        }
+    }
+}
+
+/// Starts recording if `STEVEN_GL_TRACE` is set. Called by `gl::init`.
+pub(super) fn init() {
+    if let Some(path) = ::std::env::var_os("STEVEN_GL_TRACE") {
+        match start_file(&path) {
+            Ok(()) => println!("Recording gl trace to {}", path.to_string_lossy()),
+            Err(err) => println!("Failed to create gl trace {}: {}", path.to_string_lossy(), err),
+        }
+    }
+}
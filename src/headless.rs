@@ -0,0 +1,116 @@
+//! Offscreen OpenGL contexts that don't need a window or display.
+//!
+//! The context renders into an EGL pbuffer which acts as the default
+//! framebuffer, so `Renderer::tick` works unchanged. On machines without
+//! a GPU Mesa falls back to llvmpipe.
+
+use crate::gl;
+use khronos_egl as egl;
+use std::ptr;
+
+/// `EGL_PLATFORM_SURFACELESS_MESA`, lets Mesa create a display without
+/// X11/Wayland/a drm device.
+const PLATFORM_SURFACELESS_MESA: egl::Enum = 0x31DD;
+
+/// A headless OpenGL 3.2 core context which is current on the thread
+/// that created it.
+pub struct Context {
+    egl: egl::Instance<egl::Static>,
+    display: egl::Display,
+    surface: egl::Surface,
+    context: egl::Context,
+
+    width: u32,
+    height: u32,
+}
+
+impl Context {
+    /// Creates a context with an offscreen surface of the passed size,
+    /// makes it current and inits the gl library with it.
+    pub fn new(width: u32, height: u32) -> Result<Context, egl::Error> {
+        Self::create(width, height, false)
+    }
+
+    /// Like `new` but creates a debug context, which most drivers
+    /// require before reporting messages to `gl::set_debug_callback`.
+    pub fn new_debug(width: u32, height: u32) -> Result<Context, egl::Error> {
+        Self::create(width, height, true)
+    }
+
+    fn create(width: u32, height: u32, debug: bool) -> Result<Context, egl::Error> {
+        let egl = egl::Instance::new(egl::Static);
+        let display = Self::open_display(&egl)?;
+        egl.initialize(display)?;
+
+        let config = egl.choose_first_config(display, &[
+            egl::SURFACE_TYPE, egl::PBUFFER_BIT,
+            egl::RENDERABLE_TYPE, egl::OPENGL_BIT,
+            egl::RED_SIZE, 8,
+            egl::GREEN_SIZE, 8,
+            egl::BLUE_SIZE, 8,
+            egl::ALPHA_SIZE, 8,
+            egl::DEPTH_SIZE, 24,
+            egl::NONE,
+        ])?.ok_or(egl::Error::BadConfig)?;
+
+        let surface = egl.create_pbuffer_surface(display, config, &[
+            egl::WIDTH, width as egl::Int,
+            egl::HEIGHT, height as egl::Int,
+            egl::NONE,
+        ])?;
+
+        egl.bind_api(egl::OPENGL_API)?;
+        let context = egl.create_context(display, config, None, &[
+            egl::CONTEXT_MAJOR_VERSION, 3,
+            egl::CONTEXT_MINOR_VERSION, 2,
+            egl::CONTEXT_OPENGL_PROFILE_MASK, egl::CONTEXT_OPENGL_CORE_PROFILE_BIT,
+            egl::CONTEXT_OPENGL_DEBUG, debug as egl::Int,
+            egl::NONE,
+        ])?;
+        egl.make_current(display, Some(surface), Some(surface), Some(context))?;
+
+        gl::init_with(|s| {
+            egl.get_proc_address(s)
+                .map_or(ptr::null(), |f| f as *const _)
+        });
+
+        Ok(Context {
+            egl,
+            display,
+            surface,
+            context,
+
+            width,
+            height,
+        })
+    }
+
+    fn open_display(egl: &egl::Instance<egl::Static>) -> Result<egl::Display, egl::Error> {
+        // Prefer Mesa's surfaceless platform, the default display
+        // will try (and fail) to connect to X11 when there isn't one.
+        let surfaceless = unsafe {
+            egl.get_platform_display(PLATFORM_SURFACELESS_MESA, egl::DEFAULT_DISPLAY, &[egl::ATTRIB_NONE])
+        };
+        match surfaceless {
+            Ok(display) => Ok(display),
+            Err(_) => unsafe { egl.get_display(egl::DEFAULT_DISPLAY) }.ok_or(egl::Error::BadDisplay),
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+}
+
+impl Drop for Context {
+    fn drop(&mut self) {
+        let _ = self.egl.make_current(self.display, None, None, None);
+        let _ = self.egl.destroy_context(self.display, self.context);
+        let _ = self.egl.destroy_surface(self.display, self.surface);
+        let _ = self.egl.terminate(self.display);
+    }
+}
@@ -0,0 +1,27 @@
+#![allow(clippy::new_without_default)]
+#![allow(clippy::too_many_arguments)]
+#![allow(clippy::trivially_copy_pass_by_ref)]
+#![allow(clippy::get_unwrap)]
+#![allow(clippy::option_map_unit_fn)]
+#![allow(clippy::needless_pass_by_value)]
+// Copyright 2016 Matthew Collins
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+pub mod gl;
+pub mod render;
+pub mod sun;
+#[cfg(feature = "headless")]
+pub mod headless;
+#[cfg(feature = "sdl")]
+pub mod window;
@@ -1,10 +1,3 @@
-#![allow(clippy::new_without_default_derive)]
-#![allow(clippy::new_without_default)]
-#![allow(clippy::too_many_arguments)]
-#![allow(clippy::trivially_copy_pass_by_ref)]
-#![allow(clippy::get_unwrap)]
-#![allow(clippy::option_map_unit_fn)]
-#![allow(clippy::needless_pass_by_value)]
 // Copyright 2016 Matthew Collins
 //
 // Licensed under the Apache License, Version 2.0 (the "License");
@@ -19,46 +12,125 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-pub mod gl;
-pub mod render;
-pub mod sun;
+use invisible::{gl, render, sun, window};
 
 fn main() {
     println!("Starting steven");
 
     let sdl = sdl2::init().unwrap();
     let sdl_video = sdl.video().unwrap();
-    let window = sdl2::video::WindowBuilder::new(&sdl_video, "Steven", 854, 480)
-                            .opengl()
-                            .resizable()
-                            .build()
-                            .expect("Could not create sdl window.");
-    let gl_attr = sdl_video.gl_attr();
-    gl_attr.set_depth_size(24);
-    gl_attr.set_context_major_version(3);
-    gl_attr.set_context_minor_version(2);
-    gl_attr.set_context_profile(sdl2::video::GLProfile::Core);
+    let (window, _gl_context) = window::create(&sdl_video, "Steven", 854, 480);
 
-    let gl_context = window.gl_create_context().unwrap();
-    window.gl_make_current(&gl_context).expect("Could not set current context.");
-
-    gl::init(&sdl_video);
-
-    let mut renderer = render::Renderer::new();
+    let (width, height) = window.drawable_size();
+    let mut renderer = match render::Renderer::new(width, height) {
+        Ok(val) => val,
+        Err(err) => {
+            println!("{}", err);
+            std::process::exit(1);
+        },
+    };
     let mut events = sdl.event_pump().unwrap();
     let mut sun_model = sun::SunModel::new(&mut renderer);
+    let mut screenshots = 0;
+    sdl.mouse().set_relative_mouse_mode(true);
+    let mut last_frame = std::time::Instant::now();
     'outer: loop {
+        let now = std::time::Instant::now();
+        let delta = now - last_frame;
+        last_frame = now;
+
+        walk_camera(&mut renderer.camera, &events, delta);
         sun_model.tick(&mut renderer);
 
         renderer.update_camera();
         renderer.tick();
+        let state_stats = gl::take_state_stats();
 
         window.gl_swap_window();
+        gl::trace::frame();
 
         for event in events.poll_iter() {
-            if let sdl2::event::Event::Quit{..} = event {
-                break 'outer
+            use sdl2::event::{Event, WindowEvent};
+            use sdl2::keyboard::Keycode;
+            use render::projection::Mode;
+            match event {
+                Event::Quit{..} => break 'outer,
+                Event::MouseMotion{xrel, yrel, ..} if sdl.mouse().relative_mouse_mode() => {
+                    renderer.camera.look(xrel as f64, yrel as f64);
+                },
+                // Frees the cursor, clicking captures it again
+                Event::KeyDown{keycode: Some(Keycode::Escape), ..} => {
+                    sdl.mouse().set_relative_mouse_mode(false);
+                },
+                Event::MouseButtonDown{..} => {
+                    sdl.mouse().set_relative_mouse_mode(true);
+                },
+                // The event's size is in screen coordinates, not pixels
+                Event::Window{win_event: WindowEvent::SizeChanged(..), ..} => {
+                    let (width, height) = window.drawable_size();
+                    renderer.resize(width, height);
+                },
+                Event::KeyDown{keycode: Some(Keycode::F2), ..} => {
+                    let ext = if cfg!(feature = "png") { "png" } else { "ppm" };
+                    let path = format!("screenshot-{}.{}", screenshots, ext);
+                    match renderer.screenshot().save(&path) {
+                        Ok(()) => println!("Saved {}", path),
+                        Err(err) => println!("Failed to save {}: {}", path, err),
+                    }
+                    screenshots += 1;
+                },
+                Event::KeyDown{keycode: Some(Keycode::F3), ..} => {
+                    println!("State changes: {} issued, {} skipped", state_stats.issued, state_stats.skipped);
+                    let cull_stats = renderer.model.cull_stats();
+                    println!("Models: {} drawn, {} culled", cull_stats.drawn, cull_stats.culled);
+                    for pass in renderer.profiler.stats() {
+                        match pass.time {
+                            Some(time) => println!("GPU {}: {:.3}ms, {} samples", pass.pass.name(), time.as_secs_f64() * 1000.0, pass.samples),
+                            None => println!("GPU {}: {} samples", pass.pass.name(), pass.samples),
+                        }
+                    }
+                },
+                Event::KeyDown{keycode: Some(Keycode::F4), ..} => {
+                    renderer.model.wireframe = !renderer.model.wireframe;
+                },
+                Event::KeyDown{keycode: Some(Keycode::F5), ..} => {
+                    let current = renderer.samples();
+                    let mut samples = renderer.set_samples(current * 2);
+                    if samples == current {
+                        samples = renderer.set_samples(1);
+                    }
+                    println!("MSAA: {} samples", samples);
+                },
+                Event::KeyDown{keycode: Some(Keycode::F6), ..} => {
+                    let mut projection = *renderer.projection();
+                    projection.mode = match projection.mode {
+                        Mode::Perspective(..) => Mode::Orthographic { height: 200.0 },
+                        Mode::Orthographic { .. } => render::projection::Projection::new().mode,
+                    };
+                    renderer.set_projection(projection);
+                },
+                Event::KeyDown{keycode: Some(Keycode::F7), ..} => {
+                    let mut projection = *renderer.projection();
+                    projection.reverse_z = !projection.reverse_z;
+                    renderer.set_projection(projection);
+                    println!("Reverse-Z: {}", renderer.projection().uses_reverse_z());
+                },
+                _ => (),
             }
         }
     }
 }
+
+fn walk_camera(camera: &mut render::camera::Camera, events: &sdl2::EventPump, delta: std::time::Duration) {
+    use sdl2::keyboard::Scancode;
+    let keys = events.keyboard_state();
+    let axis = |positive, negative| {
+        keys.is_scancode_pressed(positive) as i32 as f64 - keys.is_scancode_pressed(negative) as i32 as f64
+    };
+    camera.walk(
+        axis(Scancode::W, Scancode::S),
+        axis(Scancode::D, Scancode::A),
+        axis(Scancode::Space, Scancode::LShift),
+        delta
+    );
+}
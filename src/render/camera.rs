@@ -0,0 +1,86 @@
+//! The position and orientation the scene is viewed from.
+//!
+//! Follows steven's conventions: the camera is placed at
+//! `(-x, -y, z)` looking along the view vector with `-y` up, and the
+//! world is mirrored on x. A pitch of `PI` is level, looking up or down
+//! moves it towards `PI / 2` or `3 * PI / 2`.
+
+use cgmath::prelude::*;
+use std::f64::consts::PI;
+use std::time::Duration;
+
+/// Blocks per second moved by `walk`.
+pub const DEFAULT_SPEED: f64 = 20.0;
+/// Radians turned per unit of mouse motion by `look`.
+pub const DEFAULT_SENSITIVITY: f64 = 0.0025;
+
+/// How close to straight up or down the pitch may get, looking exactly
+/// along the up vector leaves the view matrix undefined.
+const PITCH_LIMIT: f64 = 0.01;
+
+#[derive(Clone, Debug)]
+pub struct Camera {
+    pub position: cgmath::Point3<f64>,
+    pub yaw: f64,
+    pub pitch: f64,
+    /// Blocks per second moved by `walk`.
+    pub speed: f64,
+    /// Radians turned per unit of mouse motion by `look`.
+    pub sensitivity: f64,
+}
+
+impl Camera {
+    pub fn new() -> Camera {
+        Camera {
+            position: cgmath::Point3::new(-200.0, 65.0, 90.0),
+            yaw: -8.0,
+            pitch: 3.0,
+            speed: DEFAULT_SPEED,
+            sensitivity: DEFAULT_SENSITIVITY,
+        }
+    }
+
+    /// The unit vector the camera looks along.
+    pub fn view_vector(&self) -> cgmath::Vector3<f32> {
+        let (yaw, pitch) = (self.yaw, self.pitch);
+        cgmath::Vector3::new(
+            ((yaw - PI/2.0).cos() * -pitch.cos()) as f32,
+            (-pitch.sin()) as f32,
+            (-(yaw - PI/2.0).sin() * -pitch.cos()) as f32
+        )
+    }
+
+    /// The view matrix uploaded in the `CameraMatrices` block.
+    pub fn matrix(&self) -> cgmath::Matrix4<f32> {
+        let view_vector = self.view_vector();
+        let camera = cgmath::Point3::new(-self.position.x as f32, -self.position.y as f32, self.position.z as f32);
+        let camera_matrix = cgmath::Matrix4::look_at(
+            camera,
+            camera + cgmath::Point3::new(-view_vector.x, -view_vector.y, view_vector.z).to_vec(),
+            cgmath::Vector3::new(0.0, -1.0, 0.0)
+        );
+        camera_matrix * cgmath::Matrix4::from_nonuniform_scale(-1.0, 1.0, 1.0)
+    }
+
+    /// Turns the camera by a mouse motion, positive `dx` turns right and
+    /// positive `dy` looks down. The pitch stops just short of straight
+    /// up and down.
+    pub fn look(&mut self, dx: f64, dy: f64) {
+        self.yaw = (self.yaw - dx * self.sensitivity) % (PI * 2.0);
+        self.pitch = (self.pitch - dy * self.sensitivity)
+            .clamp(PI / 2.0 + PITCH_LIMIT, PI * 3.0 / 2.0 - PITCH_LIMIT);
+    }
+
+    /// Moves the camera for `delta` at `speed`. `forward` follows the
+    /// view vector, `right` is level with the ground and `up` is
+    /// straight up, each is normally -1, 0 or 1.
+    pub fn walk(&mut self, forward: f64, right: f64, up: f64, delta: Duration) {
+        let distance = self.speed * delta.as_secs_f64();
+        let view = self.view_vector().cast::<f64>().unwrap();
+        let side = cgmath::Vector3::new(-self.yaw.cos(), 0.0, self.yaw.sin());
+        let offset = view * forward + side * right + cgmath::Vector3::unit_y() * up;
+        if offset.magnitude2() > 0.0 {
+            self.position += offset.normalize() * distance;
+        }
+    }
+}
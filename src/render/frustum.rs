@@ -0,0 +1,79 @@
+//! Bounding boxes and the view frustum they are culled against.
+
+use cgmath::{Matrix, Matrix4, Point3, Vector3, Vector4};
+
+/// An axis-aligned bounding box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    /// The smallest box containing the points, `None` when there are
+    /// none.
+    pub fn from_points<I: IntoIterator<Item = Point3<f32>>>(points: I) -> Option<Aabb> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        Some(points.fold(Aabb { min: first, max: first }, |b, p| Aabb {
+            min: Point3::new(b.min.x.min(p.x), b.min.y.min(p.y), b.min.z.min(p.z)),
+            max: Point3::new(b.max.x.max(p.x), b.max.y.max(p.y), b.max.z.max(p.z)),
+        }))
+    }
+
+    pub fn corners(&self) -> [Point3<f32>; 8] {
+        let (a, b) = (self.min, self.max);
+        [
+            Point3::new(a.x, a.y, a.z), Point3::new(b.x, a.y, a.z),
+            Point3::new(a.x, b.y, a.z), Point3::new(b.x, b.y, a.z),
+            Point3::new(a.x, a.y, b.z), Point3::new(b.x, a.y, b.z),
+            Point3::new(a.x, b.y, b.z), Point3::new(b.x, b.y, b.z),
+        ]
+    }
+
+    /// The box containing this one after being transformed.
+    pub fn transform(&self, matrix: &Matrix4<f32>) -> Aabb {
+        use cgmath::Transform;
+        Aabb::from_points(self.corners().iter().map(|v| matrix.transform_point(*v))).unwrap()
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb::from_points(vec![self.min, self.max, other.min, other.max]).unwrap()
+    }
+}
+
+/// The planes bounding what is visible, normals point inwards.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frustum {
+    planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes of a projection × camera matrix.
+    /// `zero_to_one` is whether clip space depth is 0 to 1 (with
+    /// `glClipControl`) rather than -1 to 1.
+    pub fn from_matrix(matrix: &Matrix4<f32>, zero_to_one: bool) -> Frustum {
+        let (x, y, z, w) = (matrix.row(0), matrix.row(1), matrix.row(2), matrix.row(3));
+        Frustum {
+            planes: [
+                w + x, w - x,
+                w + y, w - y,
+                if zero_to_one { z } else { w + z }, w - z,
+            ],
+        }
+    }
+
+    /// Whether any of the box may be visible. Boxes near the corners
+    /// of the frustum can be kept despite being outside.
+    pub fn contains(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the plane's normal
+            let corner = Vector3::new(
+                if plane.x >= 0.0 { aabb.max.x } else { aabb.min.x },
+                if plane.y >= 0.0 { aabb.max.y } else { aabb.min.y },
+                if plane.z >= 0.0 { aabb.max.z } else { aabb.min.z },
+            );
+            plane.x * corner.x + plane.y * corner.y + plane.z * corner.z + plane.w >= 0.0
+        })
+    }
+}
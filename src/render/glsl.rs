@@ -29,6 +29,13 @@ impl Registry {
         self.shaders.insert(name.to_owned(), source.trim().to_owned());
     }
 
+    /// Returns the names of the registered shaders, sorted.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.shaders.keys().map(|v| v.as_str()).collect();
+        names.sort_unstable();
+        names
+    }
+
     pub fn get(&self, name: &str) -> String {
         let mut out = String::new();
         out.push_str("#version 150\n");
@@ -41,7 +48,7 @@ impl Registry {
         out.push_str("#version 150\n");
         out.push_str("#define ");
         out.push_str(define);
-        out.push_str("\n");
+        out.push('\n');
         self.get_internal(&mut out, name);
         out
     }
@@ -49,13 +56,13 @@ impl Registry {
     fn get_internal(&self, out: &mut String, name: &str) {
         let src = self.shaders.get(name).unwrap();
         for line in src.lines() {
-            if line.starts_with("#include ") {
-                let inc = line["#include ".len()..].trim();
+            if let Some(inc) = line.strip_prefix("#include ") {
+                let inc = inc.trim();
                 self.get_internal(out, inc);
                 continue;
             }
             out.push_str(line);
-            out.push_str("\n");
+            out.push('\n');
         }
     }
 }
@@ -12,91 +12,172 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod camera;
+pub mod frustum;
 pub mod glsl;
 #[macro_use]
 pub mod shaders;
 pub mod model;
+pub mod profiler;
+pub mod projection;
+pub mod screenshot;
+pub mod stream;
 
 use crate::gl;
 use byteorder::{WriteBytesExt, NativeEndian};
 use cgmath::prelude::*;
 
-// TEMP
-const NUM_SAMPLES: i32 = 2;
+/// The MSAA samples used until changed with `set_samples`.
+const DEFAULT_SAMPLES: i32 = 2;
+
+/// The `UNIFORM_BUFFER` binding the `CameraMatrices` block is read
+/// from.
+pub const CAMERA_BLOCK_BINDING: u32 = 0;
 
 pub struct Renderer {
     pub model: model::Manager,
-
-    trans_shader: TransShader,
-
-
-    perspective_matrix: cgmath::Matrix4<f32>,
+    /// Times the passes of `tick`.
+    pub profiler: profiler::Profiler,
+
+    /// Averages the samples of the multisampled color, `None` with
+    /// the `MultisampleTexelFetch` quirk.
+    trans_shader: Option<TransShader>,
+    /// Reads a single sampled color, used with 1 sample or after
+    /// resolving with a blit.
+    resolved_shader: TransShader,
+    samples: i32,
+    camera_buffer: gl::Buffer,
+
+    projection: projection::Projection,
+    projection_matrix: cgmath::Matrix4<f32>,
     camera_matrix: cgmath::Matrix4<f32>,
-    pub view_vector: cgmath::Vector3<f32>,
+    frustum: frustum::Frustum,
+    pub camera: camera::Camera,
 
     trans: Option<TransInfo>,
 }
 
 impl Renderer {
-    pub fn new() -> Renderer {
+    /// Creates a renderer drawing `width` by `height` pixels, see
+    /// `resize`. An empty size (a window created minimized) draws 1 by
+    /// 1 pixels until the next `resize`.
+    pub fn new(width: u32, height: u32) -> Result<Renderer, shaders::ProgramError> {
 
         let mut greg = glsl::Registry::new();
         shaders::add_shaders(&mut greg);
 
         // Shaders
-        let trans_shader = TransShader::new(&greg);
-
-        Renderer {
-            model: model::Manager::new(&greg),
+        let trans_shader = if gl::quirks::has(gl::quirks::Quirk::MultisampleTexelFetch) {
+            None
+        } else {
+            Some(TransShader::new(&greg)?)
+        };
+        let resolved_shader = TransShader::new_manual(&greg.get("trans_vertex"), None, &greg.get_define("trans_frag", "RESOLVED"))?;
+
+        let camera_buffer = gl::Buffer::new();
+        camera_buffer.bind(gl::UNIFORM_BUFFER);
+        camera_buffer.label("camera");
+
+        let mut renderer = Renderer {
+            model: model::Manager::new(&greg)?,
+            profiler: profiler::Profiler::new(),
             trans_shader,
-            perspective_matrix: cgmath::Matrix4::identity(),
+            resolved_shader,
+            samples: clamp_samples(DEFAULT_SAMPLES),
+            camera_buffer,
+            projection: projection::Projection::new(),
+            projection_matrix: cgmath::Matrix4::identity(),
             camera_matrix: cgmath::Matrix4::identity(),
-            view_vector: cgmath::Vector3::zero(),
+            frustum: frustum::Frustum::from_matrix(&cgmath::Matrix4::identity(), false),
+            camera: camera::Camera::new(),
 
             trans: None,
+        };
+        renderer.resize(width.max(1), height.max(1));
+        Ok(renderer)
+    }
+
+    /// Sets the size of the drawable area in pixels, e.g. the window's
+    /// `drawable_size`. The framebuffers are only recreated when the
+    /// size changes. Empty sizes (a minimized window) are ignored.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        if let Some(trans) = &self.trans {
+            if trans.width == width && trans.height == height {
+                return;
+            }
         }
+        gl::viewport(0, 0, width as i32, height as i32);
+        self.rebuild_trans(width, height);
     }
 
-    pub fn update_camera(&mut self) {
-        use std::f64::consts::PI as PI64;
+    /// Sets the number of MSAA samples, 1 disables multisampling.
+    /// The count is rounded down to a power of two and limited to
+    /// what the driver supports, the count used is returned.
+    pub fn set_samples(&mut self, samples: i32) -> i32 {
+        let samples = clamp_samples(samples);
+        if samples != self.samples {
+            self.samples = samples;
+            let trans = self.trans.as_ref().unwrap();
+            let (width, height) = (trans.width, trans.height);
+            self.rebuild_trans(width, height);
+        }
+        samples
+    }
 
-        let width = 854;
-        let height = 480;
-        gl::viewport(0, 0, width as i32, height as i32);
+    pub fn samples(&self) -> i32 {
+        self.samples
+    }
 
-        self.perspective_matrix = cgmath::Matrix4::from(
-            cgmath::PerspectiveFov {
-                fovy: cgmath::Rad::from(cgmath::Deg(90f32)),
-                aspect: (width as f32 / height as f32),
-                near: 0.1f32,
-                far: 500.0f32,
-            }
-        );
+    /// Sets the projection used from the next `update_camera`. The
+    /// framebuffers are recreated when the depth format changes.
+    pub fn set_projection(&mut self, projection: projection::Projection) {
+        let rebuild = projection.depth_format() != self.projection.depth_format();
+        self.projection = projection;
+        if rebuild {
+            let trans = self.trans.as_ref().unwrap();
+            let (width, height) = (trans.width, trans.height);
+            self.rebuild_trans(width, height);
+        }
+    }
 
-        self.init_trans(width, height);
+    pub fn projection(&self) -> &projection::Projection {
+        &self.projection
+    }
 
-        let yaw: f64 = -8.0;
-        let pitch: f64 = 3.0;
-        let x: f64 = -200.0;
-        let y: f64 = 65.0;
-        let z: f64 = 90.0;
+    fn rebuild_trans(&mut self, width: u32, height: u32) {
+        // Free the old framebuffers first
+        self.trans = None;
+        let depth_format = self.projection.depth_format();
+        let trans = TransInfo::new(width, height, self.samples, depth_format, self.trans_shader());
+        self.trans = Some(trans);
+    }
 
-        self.view_vector = cgmath::Vector3::new(
-            ((yaw - PI64/2.0).cos() * -pitch.cos()) as f32,
-            (-pitch.sin()) as f32,
-            (-(yaw - PI64/2.0).sin() * -pitch.cos()) as f32
-        );
-        let camera = cgmath::Point3::new(-x as f32, -y as f32, z as f32);
-        let camera_matrix = cgmath::Matrix4::look_at(
-            camera,
-            camera + cgmath::Point3::new(-self.view_vector.x, -self.view_vector.y, self.view_vector.z).to_vec(),
-            cgmath::Vector3::new(0.0, -1.0, 0.0)
+    fn trans_shader(&self) -> &TransShader {
+        match self.trans_shader {
+            Some(ref shader) if self.samples > 1 => shader,
+            _ => &self.resolved_shader,
+        }
+    }
+
+    pub fn update_camera(&mut self) {
+        let trans = self.trans.as_ref().unwrap();
+        let (width, height) = (trans.width, trans.height);
+
+        self.projection_matrix = self.projection.matrix(width as f32 / height as f32);
+
+        self.camera_matrix = self.camera.matrix();
+        self.frustum = frustum::Frustum::from_matrix(
+            &(self.projection_matrix * self.camera_matrix),
+            self.projection.uses_reverse_z(),
         );
-        self.camera_matrix = camera_matrix * cgmath::Matrix4::from_nonuniform_scale(-1.0, 1.0, 1.0);
     }
 
     pub fn tick(&mut self) {
-        let trans = self.trans.as_mut().unwrap();
+        let trans = self.trans.as_ref().unwrap();
+        self.profiler.begin_frame();
         trans.main.bind();
 
         gl::clear_color(
@@ -105,35 +186,90 @@ impl Renderer {
              247.0 / 255.0,
              1.0
         );
+        self.projection.apply_depth();
         gl::clear(gl::ClearFlags::Color | gl::ClearFlags::Depth);
 
+        // Shared by every shader using the camera block
+        let mut camera = gl::std140::Writer::new();
+        camera.mat4(&self.projection_matrix);
+        camera.mat4(&self.camera_matrix);
+        self.camera_buffer.bind_base(gl::UNIFORM_BUFFER, CAMERA_BLOCK_BINDING);
+        self.camera_buffer.set_data(gl::UNIFORM_BUFFER, &camera.finish(), gl::STREAM_DRAW);
+
         // Model rendering
-        self.model.draw(&self.perspective_matrix, &self.camera_matrix);
+        self.profiler.begin(profiler::Pass::Model);
+        gl::enable(gl::DEPTH_TEST);
+        self.model.draw(&self.frustum);
+        gl::disable(gl::DEPTH_TEST);
 
+        self.profiler.begin(profiler::Pass::Translucent);
         trans.trans.bind();
         gl::clear_buffer(gl::COLOR, 0, &[0.0, 0.0, 0.0, 1.0]);
 
         gl::check_framebuffer_status();
+        self.profiler.begin(profiler::Pass::Resolve);
         gl::unbind_framebuffer();
-        trans.draw(&self.trans_shader);
+        trans.draw(self.trans_shader());
+        self.profiler.end();
 
         gl::check_gl_error();
     }
 
-    fn init_trans(&mut self, width: u32, height: u32) {
-        self.trans = Some(TransInfo::new(width, height, &self.trans_shader));
+    /// Reads back the last frame drawn by `tick`. The multisampled
+    /// scene is resolved into a normal texture before being read.
+    pub fn screenshot(&self) -> screenshot::Image {
+        let trans = self.trans.as_ref().unwrap();
+        let (width, height) = (trans.width, trans.height);
+
+        let resolved = gl::Texture::new();
+        resolved.bind(gl::TEXTURE_2D);
+        resolved.image_2d_ex(gl::TEXTURE_2D, 0, width, height, gl::RGBA8, gl::RGBA, gl::UNSIGNED_BYTE, None);
+        let resolve = gl::Framebuffer::new();
+        resolve.bind();
+        resolve.texture_2d(gl::COLOR_ATTACHMENT_0, gl::TEXTURE_2D, &resolved, 0);
+        gl::check_framebuffer_status();
+
+        trans.main.bind_read();
+        resolve.bind_draw();
+        gl::blit_framebuffer(
+            0, 0, width as i32, height as i32,
+            0, 0, width as i32, height as i32,
+            gl::ClearFlags::Color, gl::NEAREST
+        );
+        gl::unbind_framebuffer();
+
+        let mut data = vec![0; width as usize * height as usize * 4];
+        resolved.bind(gl::TEXTURE_2D);
+        resolved.get_pixels(gl::TEXTURE_2D, 0, gl::RGBA, gl::UNSIGNED_BYTE, &mut data);
+        screenshot::Image::from_gl(width, height, data)
     }
 }
 
+/// Rounds the sample count down to a power of two supported by the
+/// driver, at least 1.
+fn clamp_samples(samples: i32) -> i32 {
+    let max = gl::get_integer(gl::MAX_SAMPLES).min(gl::get_integer(gl::MAX_COLOR_TEXTURE_SAMPLES));
+    let samples = samples.min(max).max(1);
+    1 << (31 - samples.leading_zeros())
+}
+
 struct TransInfo {
+    width: u32,
+    height: u32,
+    samples: i32,
+
     main: gl::Framebuffer,
     fb_color: gl::Texture,
-    _fb_depth: gl::Texture,
+    _fb_depth: gl::Renderbuffer,
     trans: gl::Framebuffer,
-    _depth: gl::Texture,
+    _depth: gl::Renderbuffer,
 
     array: gl::VertexArray,
     _buffer: gl::Buffer,
+
+    // Single sampled copy of fb_color for drivers that can't
+    // texelFetch from multisampled textures.
+    resolve: Option<(gl::Framebuffer, gl::Texture)>,
 }
 
 init_shader! {
@@ -141,25 +277,29 @@ init_shader! {
         vert = "trans_vertex",
         frag = "trans_frag",
         attribute = {
-            required position => "aPosition",
+            required position: FLOAT_VEC2 => "aPosition",
         },
         uniform = {
+            // sampler2DMS, or sampler2D with RESOLVED
             required color => "tcolor",
+            optional samples: INT_TYPE => "samples",
         },
     }
 }
 
 impl TransInfo {
-    pub fn new(width: u32, height: u32, shader: &TransShader) -> TransInfo {
+    /// Creates the framebuffers, `main` is only multisampled with more
+    /// than 1 sample.
+    pub fn new(width: u32, height: u32, samples: i32, depth_format: gl::TextureFormat, shader: &TransShader) -> TransInfo {
         let trans = gl::Framebuffer::new();
         trans.bind();
 
-        let trans_depth = gl::Texture::new();
-        trans_depth.bind(gl::TEXTURE_2D);
-        trans_depth.image_2d_ex(gl::TEXTURE_2D, 0, width, height, gl::DEPTH_COMPONENT24, gl::DEPTH_COMPONENT, gl::UNSIGNED_BYTE, None);
-        trans_depth.set_parameter(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR);
-        trans_depth.set_parameter(gl::TEXTURE_2D, gl::TEXTURE_MAX_LEVEL, gl::LINEAR);
-        trans.texture_2d(gl::DEPTH_ATTACHMENT, gl::TEXTURE_2D, &trans_depth, 0);
+        let trans_depth = gl::Renderbuffer::new();
+        trans_depth.bind();
+        trans_depth.storage(depth_format, width, height);
+        trans.renderbuffer(gl::DEPTH_ATTACHMENT, &trans_depth);
+        trans.label("trans");
+        trans_depth.label("trans depth");
 
         gl::check_framebuffer_status();
 
@@ -167,16 +307,49 @@ impl TransInfo {
         main.bind();
 
         let fb_color = gl::Texture::new();
-        fb_color.bind(gl::TEXTURE_2D_MULTISAMPLE);
-        fb_color.image_2d_sample(gl::TEXTURE_2D_MULTISAMPLE, NUM_SAMPLES, width, height, gl::RGBA8, false);
-        main.texture_2d(gl::COLOR_ATTACHMENT_0, gl::TEXTURE_2D_MULTISAMPLE, &fb_color, 0);
-
-        let fb_depth = gl::Texture::new();
-        fb_depth.bind(gl::TEXTURE_2D_MULTISAMPLE);
-        fb_depth.image_2d_sample(gl::TEXTURE_2D_MULTISAMPLE, NUM_SAMPLES, width, height, gl::DEPTH_COMPONENT24, false);
-        main.texture_2d(gl::DEPTH_ATTACHMENT, gl::TEXTURE_2D_MULTISAMPLE, &fb_depth, 0);
+        let fb_depth = gl::Renderbuffer::new();
+        if samples > 1 {
+            fb_color.bind(gl::TEXTURE_2D_MULTISAMPLE);
+            // Fixed sample locations are required when mixed with
+            // renderbuffer attachments
+            fb_color.image_2d_sample(gl::TEXTURE_2D_MULTISAMPLE, samples, width, height, gl::RGBA8, true);
+            main.texture_2d(gl::COLOR_ATTACHMENT_0, gl::TEXTURE_2D_MULTISAMPLE, &fb_color, 0);
+
+            fb_depth.bind();
+            fb_depth.storage_multisample(samples, depth_format, width, height);
+        } else {
+            fb_color.bind(gl::TEXTURE_2D);
+            fb_color.image_2d_ex(gl::TEXTURE_2D, 0, width, height, gl::RGBA8, gl::RGBA, gl::UNSIGNED_BYTE, None);
+            fb_color.set_parameter(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST);
+            fb_color.set_parameter(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST);
+            main.texture_2d(gl::COLOR_ATTACHMENT_0, gl::TEXTURE_2D, &fb_color, 0);
+
+            fb_depth.bind();
+            fb_depth.storage(depth_format, width, height);
+        }
+        main.renderbuffer(gl::DEPTH_ATTACHMENT, &fb_depth);
+        main.label("main");
+        fb_color.label("main color");
+        fb_depth.label("main depth");
         gl::check_framebuffer_status();
 
+        let resolve = if samples > 1 && gl::quirks::has(gl::quirks::Quirk::MultisampleTexelFetch) {
+            let resolve = gl::Framebuffer::new();
+            resolve.bind();
+            let resolved = gl::Texture::new();
+            resolved.bind(gl::TEXTURE_2D);
+            resolved.image_2d_ex(gl::TEXTURE_2D, 0, width, height, gl::RGBA8, gl::RGBA, gl::UNSIGNED_BYTE, None);
+            resolved.set_parameter(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST);
+            resolved.set_parameter(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST);
+            resolve.texture_2d(gl::COLOR_ATTACHMENT_0, gl::TEXTURE_2D, &resolved, 0);
+            resolve.label("main resolve");
+            resolved.label("main resolved color");
+            gl::check_framebuffer_status();
+            Some((resolve, resolved))
+        } else {
+            None
+        };
+
         gl::unbind_framebuffer();
 
         shader.program.use_program();
@@ -184,9 +357,11 @@ impl TransInfo {
         array.bind();
         let buffer = gl::Buffer::new();
         buffer.bind(gl::ARRAY_BUFFER);
+        array.label("trans quad");
+        buffer.label("trans quad");
 
         let mut data = vec![];
-        for f in [-1.0, 1.0, 1.0, -1.0, -1.0, -1.0, 1.0, 1.0, 1.0, -1.0, -1.0, 1.0].into_iter() {
+        for f in [-1.0, 1.0, 1.0, -1.0, -1.0, -1.0, 1.0, 1.0, 1.0, -1.0, -1.0, 1.0].iter() {
             data.write_f32::<NativeEndian>(*f).unwrap();
         }
         buffer.set_data(gl::ARRAY_BUFFER, &data, gl::STATIC_DRAW);
@@ -195,6 +370,10 @@ impl TransInfo {
         shader.position.vertex_pointer(2, gl::FLOAT, false, 8, 0);
 
         TransInfo {
+            width,
+            height,
+            samples,
+
             main,
             fb_color,
             _fb_depth: fb_depth,
@@ -203,15 +382,32 @@ impl TransInfo {
 
             array,
             _buffer: buffer,
+
+            resolve,
         }
     }
 
-    fn draw(&mut self, shader: &TransShader) {
+    fn draw(&self, shader: &TransShader) {
         gl::active_texture(0);
-        self.fb_color.bind(gl::TEXTURE_2D_MULTISAMPLE);
+        if let Some((ref resolve, ref resolved)) = self.resolve {
+            self.main.bind_read();
+            resolve.bind_draw();
+            gl::blit_framebuffer(
+                0, 0, self.width as i32, self.height as i32,
+                0, 0, self.width as i32, self.height as i32,
+                gl::ClearFlags::Color, gl::NEAREST
+            );
+            gl::unbind_framebuffer();
+            resolved.bind(gl::TEXTURE_2D);
+        } else if self.samples > 1 {
+            self.fb_color.bind(gl::TEXTURE_2D_MULTISAMPLE);
+        } else {
+            self.fb_color.bind(gl::TEXTURE_2D);
+        }
 
         shader.program.use_program();
         shader.color.set_int(0);
+        shader.samples.map(|v| v.set_int(self.samples));
         self.array.bind();
         gl::draw_arrays(gl::TRIANGLES, 0, 6);
     }
@@ -220,7 +416,8 @@ impl TransInfo {
 #[allow(unused_must_use)]
 pub fn generate_element_buffer(size: usize) -> (Vec<u8>, gl::Type) {
     let mut ty = gl::UNSIGNED_SHORT;
-    let mut data = if (size / 6) * 4 * 3 >= u16::max_value() as usize {
+    let mut data = if (size / 6) * 4 * 3 >= u16::MAX as usize
+        || gl::quirks::has(gl::quirks::Quirk::NoShortIndices) {
         ty = gl::UNSIGNED_INT;
         Vec::with_capacity(size * 4)
     } else {
@@ -1,21 +1,41 @@
 
+use super::frustum::{Aabb, Frustum};
 use super::glsl;
 use super::shaders;
+use super::stream::StreamBuffer;
 use crate::gl;
 use cgmath::{Matrix4, SquareMatrix};
 use std::collections::HashMap;
+use std::ops::Range;
 use byteorder::{WriteBytesExt, NativeEndian};
 
 pub struct Manager {
     collections: Vec<Collection>,
 
+    /// Draws the edges of the models' triangles over them.
+    pub wireframe: bool,
+    /// Skips models outside of the view frustum.
+    pub culling: bool,
+    cull_stats: CullStats,
+
     index_buffer: gl::Buffer,
     index_type: gl::Type,
     max_index: usize,
+
+    /// Instance data, rewritten every frame.
+    stream: StreamBuffer,
 }
 
 pub const DEFAULT: CollectionKey = CollectionKey(0);
 pub const SUN: CollectionKey = CollectionKey(1);
+pub const INSTANCED: CollectionKey = CollectionKey(2);
+
+/// Bytes per instance, a matrix followed by a colour.
+const INSTANCE_STRIDE: i32 = 80;
+/// Bytes per vertex in a model's vertex buffer.
+const VERTEX_SIZE: usize = 36;
+/// The smallest size, in vertices, a collection's arena grows to.
+const MIN_ARENA_VERTICES: usize = 1024;
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub struct CollectionKey(usize);
@@ -23,34 +43,71 @@ pub struct CollectionKey(usize);
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub struct ModelKey(CollectionKey, usize);
 
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct InstancedKey(CollectionKey, usize);
+
 impl Manager {
-    pub fn new(greg: &glsl::Registry) -> Manager {
+    pub fn new(greg: &glsl::Registry) -> Result<Manager, shaders::ProgramError> {
         let mut m = Manager {
             collections: vec![],
 
+            wireframe: false,
+            culling: true,
+            cull_stats: CullStats::default(),
+
             index_buffer: gl::Buffer::new(),
             index_type: gl::UNSIGNED_SHORT,
             max_index: 0,
+
+            stream: StreamBuffer::new(gl::ARRAY_BUFFER, "model stream", 64 * 1024),
         };
-        m.add_collection(
-            &greg.get("model_vertex"),
-            &greg.get("model_frag"),
-        );
-        m.add_collection(
-            &greg.get("sun_vertex"),
-            &greg.get("sun_frag"),
-        );
-        m
+        m.add_collection(greg, "model_vertex", "model_frag")?;
+        m.add_collection(greg, "sun_vertex", "sun_frag")?;
+        m.add_collection(greg, "instanced_vertex", "instanced_frag")?;
+        Ok(m)
     }
 
-    fn add_collection(&mut self, vert: &str, frag: &str) -> CollectionKey {
+    fn add_collection(&mut self, greg: &glsl::Registry, vert: &str, frag: &str) -> Result<CollectionKey, shaders::ProgramError> {
+        let vert = greg.get(vert);
+        let shader = ModelShader::new_manual(&vert, None, &greg.get(frag))?;
+        let wireframe = WireframeShader::new_manual(&vert, Some(&greg.get("wireframe_geom")), &greg.get("wireframe_frag"))?;
+
+        let array = gl::VertexArray::new();
+        array.bind();
+        self.index_buffer.bind(gl::ELEMENT_ARRAY_BUFFER);
+        let buffer = gl::Buffer::new();
+        buffer.bind(gl::ARRAY_BUFFER);
+        array.label("model");
+        buffer.label("model vertices");
+        shader.program.use_program();
+        shader.position.map(|v| v.enable());
+        shader.position.map(|v| v.vertex_pointer(3, gl::FLOAT, false, VERTEX_SIZE as i32, 0));
+
+        // The attribute locations may differ between the programs
+        let wire_array = gl::VertexArray::new();
+        wire_array.bind();
+        self.index_buffer.bind(gl::ELEMENT_ARRAY_BUFFER);
+        wire_array.label("model wireframe");
+        wireframe.program.use_program();
+        wireframe.position.map(|v| v.enable());
+        wireframe.position.map(|v| v.vertex_pointer(3, gl::FLOAT, false, VERTEX_SIZE as i32, 0));
+
         let collection = Collection {
-            shader: ModelShader::new_manual(vert, frag),
+            shader,
+            wireframe,
+            arena: Arena {
+                array,
+                wire_array,
+                buffer,
+                capacity: 0,
+                free: vec![],
+            },
             models: HashMap::new(),
+            instanced: HashMap::new(),
             next_id: 0,
         };
         self.collections.push(collection);
-        CollectionKey(self.collections.len())
+        Ok(CollectionKey(self.collections.len()))
     }
 
     pub fn get_model(&mut self, key: ModelKey) -> Option<&mut Model> {
@@ -58,65 +115,127 @@ impl Manager {
         collection.models.get_mut(&key)
     }
 
+    pub fn get_instanced_model(&mut self, key: InstancedKey) -> Option<&mut InstancedModel> {
+        let collection = &mut self.collections[(key.0).0];
+        collection.instanced.get_mut(&key)
+    }
+
+    /// Creates a model from its parts' vertices. The vertices are
+    /// placed in the collection's shared vertex buffer.
     pub fn create_model(&mut self, ckey: CollectionKey, parts: Vec<Vec<Vertex>>) -> ModelKey {
-        let array = gl::VertexArray::new();
-        array.bind();
-        self.index_buffer.bind(gl::ELEMENT_ARRAY_BUFFER);
-        let buffer = gl::Buffer::new();
-        buffer.bind(gl::ARRAY_BUFFER);
+        let mut model = Model {
+            // Per a part
+            matrix: Vec::with_capacity(parts.len()),
+            colors: Vec::with_capacity(parts.len()),
 
-        let mut model = {
-            let collection = &mut self.collections[ckey.0];
-            collection.shader.program.use_program();
-            collection.shader.position.map(|v| v.enable());
-            collection.shader.position.map(|v| v.vertex_pointer(3, gl::FLOAT, false, 36, 0));
+            range: 0..0,
+            count: 0,
+            part_bounds: Vec::with_capacity(parts.len()),
 
-            let mut model = Model {
-                // Per a part
-                matrix: Vec::with_capacity(parts.len()),
-                colors: Vec::with_capacity(parts.len()),
+            verts: vec![],
+        };
+        model.set_parts(parts);
 
-                array,
-                buffer,
-                buffer_size: 0,
-                count: 0,
+        let collection = &mut self.collections[ckey.0];
+        let key = ModelKey(ckey, collection.next_id);
+        collection.next_id += 1;
+        let count = model.count as usize;
+        collection.place(key, model);
 
-                verts: vec![],
-            };
+        self.ensure_indices(count);
+        key
+    }
 
-            for (_i, part) in parts.into_iter().enumerate() {
-                model.matrix.push(Matrix4::identity());
-                model.colors.push([1.0, 1.0, 1.0, 1.0]);
-                for pp in part {
-                    model.verts.push(pp);
-                }
-            }
-            model
+    /// Replaces the model's vertices with new parts. The matrices and
+    /// colors of the parts that remain are kept, added parts start
+    /// untransformed and white.
+    pub fn update_model(&mut self, key: ModelKey, parts: Vec<Vec<Vertex>>) {
+        let collection = &mut self.collections[(key.0).0];
+        // Taken out so growing the arena doesn't upload the old vertices
+        let mut model = match collection.models.remove(&key) {
+            Some(model) => model,
+            None => return,
         };
+        collection.arena.release(model.range.clone());
+        model.set_parts(parts);
+        let count = model.count as usize;
+        collection.place(key, model);
 
-        Self::rebuild_model(&mut model);
-        if self.max_index < model.count as usize {
-            let (data, ty) = super::generate_element_buffer(model.count as usize);
-            self.index_buffer.bind(gl::ELEMENT_ARRAY_BUFFER);
-            self.index_buffer.set_data(gl::ELEMENT_ARRAY_BUFFER, &data, gl::DYNAMIC_DRAW);
-            self.max_index = model.count as usize;
-            self.index_type = ty;
+        self.ensure_indices(count);
+    }
+
+    /// Removes the model, its space in the collection's vertex buffer
+    /// is reused by later models.
+    pub fn remove_model(&mut self, key: ModelKey) {
+        let collection = &mut self.collections[(key.0).0];
+        if let Some(model) = collection.models.remove(&key) {
+            collection.arena.release(model.range);
         }
+    }
+
+    /// Creates a mesh that is drawn once per entry of its `instances`
+    /// with a single draw call. The collection's shader reads the
+    /// instance from the `aInstanceMatrix` and `aInstanceColor`
+    /// attributes.
+    pub fn create_instanced_model(&mut self, ckey: CollectionKey, verts: Vec<Vertex>) -> InstancedKey {
+        let array = gl::VertexArray::new();
+        array.bind();
+        self.index_buffer.bind(gl::ELEMENT_ARRAY_BUFFER);
+        let buffer = gl::Buffer::new();
+        buffer.bind(gl::ARRAY_BUFFER);
+        buffer.set_data(gl::ARRAY_BUFFER, &Self::vertex_data(&verts), gl::STATIC_DRAW);
+        array.label("instanced model");
+        buffer.label("instanced model vertices");
 
         let collection = &mut self.collections[ckey.0];
-        let key = ModelKey(ckey, collection.next_id);
+        let shader = &collection.shader;
+        shader.program.use_program();
+        shader.position.map(|v| v.enable());
+        shader.position.map(|v| v.vertex_pointer(3, gl::FLOAT, false, VERTEX_SIZE as i32, 0));
+
+        // Otherwise the attributes are set per draw. The arrays are
+        // pointed into the stream buffer when drawn.
+        if gl::caps::has(gl::caps::Cap::InstancedArrays) {
+            if let Some(matrix) = shader.instance_matrix {
+                for i in 0..4 {
+                    let column = matrix.column(i);
+                    column.enable();
+                    column.divisor(1);
+                }
+            }
+            if let Some(color) = shader.instance_color {
+                color.enable();
+                color.divisor(1);
+            }
+        }
+
+        let model = InstancedModel {
+            instances: vec![],
+            array,
+            _buffer: buffer,
+            count: ((verts.len() / 4) * 6) as i32,
+        };
+        let key = InstancedKey(ckey, collection.next_id);
         collection.next_id += 1;
-        collection.models.insert(key, model);
+        self.ensure_indices(model.count as usize);
+        self.collections[ckey.0].instanced.insert(key, model);
 
         key
     }
 
-    fn rebuild_model(model: &mut Model) {
-        model.array.bind();
-        model.count = ((model.verts.len() / 4) * 6) as i32;
+    fn ensure_indices(&mut self, count: usize) {
+        if self.max_index < count {
+            let (data, ty) = super::generate_element_buffer(count);
+            self.index_buffer.bind(gl::ELEMENT_ARRAY_BUFFER);
+            self.index_buffer.set_data(gl::ELEMENT_ARRAY_BUFFER, &data, gl::DYNAMIC_DRAW);
+            self.max_index = count;
+            self.index_type = ty;
+        }
+    }
 
-        let mut buffer = Vec::with_capacity(36 * model.verts.len());
-        for vert in &model.verts {
+    fn vertex_data(verts: &[Vertex]) -> Vec<u8> {
+        let mut buffer = Vec::with_capacity(VERTEX_SIZE * verts.len());
+        for vert in verts {
             let _ = buffer.write_f32::<NativeEndian>(vert.x);
             let _ = buffer.write_f32::<NativeEndian>(vert.y);
             let _ = buffer.write_f32::<NativeEndian>(vert.z);
@@ -137,52 +256,255 @@ impl Manager {
             let _ = buffer.write_u8(0);
             let _ = buffer.write_u8(0);
         }
+        buffer
+    }
 
-        model.buffer.bind(gl::ARRAY_BUFFER);
-        if buffer.len() < model.buffer_size {
-            model.buffer.re_set_data(gl::ARRAY_BUFFER, &buffer);
-        } else {
-            model.buffer.set_data(gl::ARRAY_BUFFER, &buffer, gl::DYNAMIC_DRAW);
-            model.buffer_size = buffer.len();
-        }
+    /// Returns how many models the last `draw` drew and culled.
+    pub fn cull_stats(&self) -> CullStats {
+        self.cull_stats
     }
 
-    pub fn draw(&mut self, perspective_matrix: &Matrix4<f32>, camera_matrix: &Matrix4<f32>) {
+    /// Draws the models, skipping those outside of `frustum` when
+    /// `culling` is enabled. Instanced models are always drawn. The
+    /// camera is read from the buffer bound to `CAMERA_BLOCK_BINDING`.
+    pub fn draw(&mut self, frustum: &Frustum) {
+        self.stream.begin_frame();
+        let mut stats = CullStats::default();
         for collection in &self.collections {
             collection.shader.program.use_program();
-            collection.shader.perspective_matrix.map(|v| v.set_matrix4(perspective_matrix));
-            collection.shader.camera_matrix.map(|v| v.set_matrix4(camera_matrix));
 
-            for model in collection.models.values() {
-                model.array.bind();
+            let models: Vec<&Model> = collection.models.values()
+                .filter(|model| !self.culling || model.bounds().is_none_or(|v| frustum.contains(&v)))
+                .collect();
+            stats.culled += collection.models.len() - models.len();
+            stats.drawn += models.len();
+
+            if !models.is_empty() {
+                collection.arena.array.bind();
+            }
+            for model in &models {
                 println!("model.matrix(len={}) = {:?}", model.matrix.len(), &model.matrix);
                 collection.shader.model_matrix.map(|v| v.set_matrix4_multi(&model.matrix));
 println!("about to draw model {:?} {:?}", model.count, self.index_type);
-                gl::draw_elements(gl::TRIANGLES, model.count, self.index_type, 0);
+                gl::draw_elements_base_vertex(gl::TRIANGLES, model.count, self.index_type, 0, model.range.start as i32);
+            }
+            if self.wireframe && !models.is_empty() {
+                collection.wireframe.program.use_program();
+                collection.arena.wire_array.bind();
+                for model in &models {
+                    collection.wireframe.model_matrix.map(|v| v.set_matrix4_multi(&model.matrix));
+                    gl::draw_elements_base_vertex(gl::TRIANGLES, model.count, self.index_type, 0, model.range.start as i32);
+                }
+                collection.shader.program.use_program();
+            }
+
+            for model in collection.instanced.values() {
+                if model.instances.is_empty() {
+                    continue;
+                }
+                model.array.bind();
+                if !gl::caps::has(gl::caps::Cap::InstancedArrays) {
+                    // The instance attributes' arrays are disabled so
+                    // their current values are used
+                    for instance in &model.instances {
+                        collection.shader.instance_matrix.map(|v| for i in 0..4 {
+                            let c = instance.matrix[i as usize];
+                            v.column(i).set_float4(c.x, c.y, c.z, c.w);
+                        });
+                        let [r, g, b, a] = instance.color;
+                        collection.shader.instance_color.map(|v| v.set_float4(r, g, b, a));
+                        gl::draw_elements(gl::TRIANGLES, model.count, self.index_type, 0);
+                    }
+                    continue;
+                }
+
+                let mut data = Vec::with_capacity(INSTANCE_STRIDE as usize * model.instances.len());
+                for instance in &model.instances {
+                    let m: &[f32; 16] = instance.matrix.as_ref();
+                    for f in m.iter().chain(&instance.color) {
+                        let _ = data.write_f32::<NativeEndian>(*f);
+                    }
+                }
+                let offset = self.stream.write(&data) as i32;
+                if let Some(matrix) = collection.shader.instance_matrix {
+                    for i in 0..4 {
+                        matrix.column(i).vertex_pointer(4, gl::FLOAT, false, INSTANCE_STRIDE, offset + i * 16);
+                    }
+                }
+                collection.shader.instance_color.map(|v| v.vertex_pointer(4, gl::FLOAT, false, INSTANCE_STRIDE, offset + 64));
+                gl::draw_elements_instanced(gl::TRIANGLES, model.count, self.index_type, 0, model.instances.len());
             }
         }
+        self.stream.end_frame();
+        self.cull_stats = stats;
     }
 }
 
+/// The number of models drawn and skipped by frustum culling.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CullStats {
+    pub drawn: usize,
+    pub culled: usize,
+}
+
 struct Collection {
     shader: ModelShader,
+    wireframe: WireframeShader,
+    arena: Arena,
 
     models: HashMap<ModelKey, Model>,
+    instanced: HashMap<InstancedKey, InstancedModel>,
 
     next_id: usize,
 }
 
+impl Collection {
+    /// Uploads the model's vertices to a new range of the arena and
+    /// adds it to the collection.
+    fn place(&mut self, key: ModelKey, mut model: Model) {
+        model.range = self.allocate(model.verts.len());
+        self.arena.buffer.bind(gl::ARRAY_BUFFER);
+        self.arena.buffer.set_sub_data(
+            gl::ARRAY_BUFFER,
+            model.range.start * VERTEX_SIZE,
+            &Manager::vertex_data(&model.verts),
+        );
+        self.models.insert(key, model);
+    }
+
+    /// Finds space for `len` vertices in the arena, growing it if
+    /// needed.
+    fn allocate(&mut self, len: usize) -> Range<usize> {
+        if len == 0 {
+            return 0..0;
+        }
+        if let Some(start) = self.arena.allocate(len) {
+            return start..start + len;
+        }
+        self.arena.grow(len);
+        // Growing drops the buffer's contents
+        for model in self.models.values() {
+            self.arena.buffer.set_sub_data(
+                gl::ARRAY_BUFFER,
+                model.range.start * VERTEX_SIZE,
+                &Manager::vertex_data(&model.verts),
+            );
+        }
+        let start = self.arena.allocate(len).unwrap();
+        start..start + len
+    }
+}
+
+/// A vertex buffer shared by every model in a collection. Each model
+/// occupies a range of it and is drawn with its start as the base
+/// vertex, so the collection needs a single vertex array.
+struct Arena {
+    array: gl::VertexArray,
+    /// `array` set up for the collection's wireframe program.
+    wire_array: gl::VertexArray,
+    buffer: gl::Buffer,
+    /// The size of the buffer in vertices.
+    capacity: usize,
+    /// Unused ranges, sorted and never touching each other.
+    free: Vec<Range<usize>>,
+}
+
+impl Arena {
+    /// Takes `len` vertices from the first free range large enough.
+    fn allocate(&mut self, len: usize) -> Option<usize> {
+        let i = self.free.iter().position(|v| v.len() >= len)?;
+        let start = self.free[i].start;
+        self.free[i].start += len;
+        if self.free[i].is_empty() {
+            self.free.remove(i);
+        }
+        Some(start)
+    }
+
+    fn release(&mut self, range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+        let i = self.free.iter().position(|v| v.start > range.start).unwrap_or(self.free.len());
+        self.free.insert(i, range);
+        if i + 1 < self.free.len() && self.free[i].end == self.free[i + 1].start {
+            self.free[i].end = self.free.remove(i + 1).end;
+        }
+        if i > 0 && self.free[i - 1].end == self.free[i].start {
+            self.free[i - 1].end = self.free.remove(i).end;
+        }
+    }
+
+    /// Resizes the buffer to fit at least `len` more vertices at the
+    /// end. The buffer's contents are lost.
+    fn grow(&mut self, len: usize) {
+        let old = self.capacity;
+        self.capacity = (old + len).next_power_of_two().max(MIN_ARENA_VERTICES);
+        self.release(old..self.capacity);
+        self.buffer.bind(gl::ARRAY_BUFFER);
+        self.buffer.set_data(gl::ARRAY_BUFFER, &vec![0; self.capacity * VERTEX_SIZE], gl::DYNAMIC_DRAW);
+    }
+}
+
 pub struct Model {
     // Per a part
     pub matrix: Vec<Matrix4<f32>>,
     pub colors: Vec<[f32; 4]>,
 
-    array: gl::VertexArray,
-    buffer: gl::Buffer,
-    buffer_size: usize,
+    /// The model's vertices within the collection's arena.
+    range: Range<usize>,
     count: i32,
+    /// The bounds of each part's vertices, before its matrix.
+    part_bounds: Vec<Option<Aabb>>,
 
-    pub verts: Vec<Vertex>,
+    /// A copy of the vertices in the arena, uploaded again when it
+    /// grows. Changed through `Manager::update_model`.
+    verts: Vec<Vertex>,
+}
+
+impl Model {
+    /// The vertices of every part, in order.
+    pub fn verts(&self) -> &[Vertex] {
+        &self.verts
+    }
+
+    fn set_parts(&mut self, parts: Vec<Vec<Vertex>>) {
+        self.matrix.resize(parts.len(), Matrix4::identity());
+        self.colors.resize(parts.len(), [1.0, 1.0, 1.0, 1.0]);
+        self.part_bounds.clear();
+        self.verts.clear();
+        for part in parts {
+            self.part_bounds.push(Aabb::from_points(part.iter().map(|v| cgmath::Point3::new(v.x, v.y, v.z))));
+            self.verts.extend(part);
+        }
+        self.count = ((self.verts.len() / 4) * 6) as i32;
+    }
+
+    /// The bounds of the model with its parts' current matrices, `None`
+    /// without any vertices. Like the vertex shaders, y is flipped
+    /// before the part's matrix.
+    pub fn bounds(&self) -> Option<Aabb> {
+        let flip = Matrix4::from_nonuniform_scale(1.0, -1.0, 1.0);
+        self.part_bounds.iter()
+            .zip(&self.matrix)
+            .filter_map(|(bounds, matrix)| bounds.map(|v| v.transform(&(matrix * flip))))
+            .fold(None, |total: Option<Aabb>, v| Some(total.map_or(v, |total| total.union(&v))))
+    }
+}
+
+/// The transform and colour of one copy of an instanced model.
+#[derive(Clone)]
+pub struct Instance {
+    pub matrix: Matrix4<f32>,
+    pub color: [f32; 4],
+}
+
+pub struct InstancedModel {
+    pub instances: Vec<Instance>,
+
+    array: gl::VertexArray,
+    _buffer: gl::Buffer,
+    count: i32,
 }
 
 #[derive(Clone)]
@@ -197,14 +519,34 @@ init_shader! {
         vert = "model_vertex",
         frag = "model_frag",
         attribute = {
-            optional position => "aPosition",
+            optional position: FLOAT_VEC3 => "aPosition",
             optional id => "id",
+            optional instance_matrix: FLOAT_MAT4 => "aInstanceMatrix",
+            optional instance_color: FLOAT_VEC4 => "aInstanceColor",
         },
         uniform = {
-            optional perspective_matrix => "perspectiveMatrix",
-            optional camera_matrix => "cameraMatrix",
-            optional model_matrix => "modelMatrix",
+            optional model_matrix: FLOAT_MAT4 => "modelMatrix",
+        },
+        uniform_block = {
+            optional camera: super::CAMERA_BLOCK_BINDING => "CameraMatrices",
         },
     }
 }
 
+
+init_shader! {
+    Program WireframeShader {
+        vert = "model_vertex",
+        geom = "wireframe_geom",
+        frag = "wireframe_frag",
+        attribute = {
+            optional position: FLOAT_VEC3 => "aPosition",
+        },
+        uniform = {
+            optional model_matrix: FLOAT_MAT4 => "modelMatrix",
+        },
+        uniform_block = {
+            optional camera: super::CAMERA_BLOCK_BINDING => "CameraMatrices",
+        },
+    }
+}
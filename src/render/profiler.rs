@@ -0,0 +1,192 @@
+//! Measures how long each pass of `Renderer::tick` takes on the gpu.
+//!
+//! Each pass is bracketed by a `TIME_ELAPSED` query, when timer queries
+//! are supported, and a `SAMPLES_PASSED` query. The results are read
+//! once available, normally a frame or two later, so the cpu never
+//! waits on the gpu. Frames whose results still aren't available after
+//! `FRAMES_IN_FLIGHT` frames are dropped.
+
+use crate::gl;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// The number of frames of queries kept, the oldest is reused for the
+/// next frame.
+const FRAMES_IN_FLIGHT: usize = 4;
+/// The number of frames averaged.
+const HISTORY: usize = 60;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pass {
+    /// Drawing the models into the multisampled framebuffer.
+    Model,
+    /// Clearing and drawing into the translucency framebuffer.
+    Translucent,
+    /// Resolving the framebuffers onto the screen.
+    Resolve,
+}
+
+pub const PASSES: [Pass; 3] = [Pass::Model, Pass::Translucent, Pass::Resolve];
+
+impl Pass {
+    pub fn name(self) -> &'static str {
+        match self {
+            Pass::Model => "model",
+            Pass::Translucent => "translucent",
+            Pass::Resolve => "resolve",
+        }
+    }
+}
+
+/// The averages of a pass over the last frames.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PassStats {
+    pub pass: Pass,
+    /// `None` without timer queries.
+    pub time: Option<Duration>,
+    pub samples: u64,
+    /// The number of frames averaged, 0 until the first results arrive.
+    pub frames: usize,
+}
+
+struct PassQueries {
+    time: Option<gl::Query>,
+    samples: gl::Query,
+    /// Whether the queries were used this frame.
+    issued: bool,
+    /// Queries can only be labeled once begun.
+    labeled: bool,
+}
+
+struct Frame {
+    passes: Vec<PassQueries>,
+    pending: bool,
+}
+
+pub struct Profiler {
+    frames: Vec<Frame>,
+    current: usize,
+    active: Option<Pass>,
+    /// (time, samples) per frame for each pass, oldest first.
+    history: Vec<VecDeque<(Option<u64>, u64)>>,
+    dropped: usize,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        let timer = gl::caps::has(gl::caps::Cap::TimerQuery);
+        let frames = (0..FRAMES_IN_FLIGHT)
+            .map(|_| Frame {
+                passes: PASSES.iter()
+                    .map(|_| PassQueries {
+                        time: if timer { Some(gl::Query::new()) } else { None },
+                        samples: gl::Query::new(),
+                        issued: false,
+                        labeled: false,
+                    })
+                    .collect(),
+                pending: false,
+            })
+            .collect();
+        Profiler {
+            frames,
+            current: 0,
+            active: None,
+            history: PASSES.iter().map(|_| VecDeque::with_capacity(HISTORY)).collect(),
+            dropped: 0,
+        }
+    }
+
+    /// Collects the results of earlier frames that have become
+    /// available and starts a new frame. Called at the start of each
+    /// frame.
+    pub fn begin_frame(&mut self) {
+        for i in 1..=FRAMES_IN_FLIGHT {
+            let idx = (self.current + i) % FRAMES_IN_FLIGHT;
+            self.collect(idx);
+        }
+        self.current = (self.current + 1) % FRAMES_IN_FLIGHT;
+        let frame = &mut self.frames[self.current];
+        if frame.pending {
+            self.dropped += 1;
+        }
+        frame.pending = true;
+        frame.passes.iter_mut().for_each(|v| v.issued = false);
+    }
+
+    fn collect(&mut self, idx: usize) {
+        let frame = &mut self.frames[idx];
+        if !frame.pending {
+            return;
+        }
+        let ready = frame.passes.iter()
+            .filter(|v| v.issued)
+            .all(|v| v.samples.is_available() && v.time.as_ref().is_none_or(|t| t.is_available()));
+        if !ready {
+            return;
+        }
+        for (pass, history) in frame.passes.iter().zip(&mut self.history) {
+            if !pass.issued {
+                continue;
+            }
+            if history.len() == HISTORY {
+                history.pop_front();
+            }
+            history.push_back((pass.time.as_ref().map(|v| v.result()), pass.samples.result()));
+        }
+        frame.pending = false;
+    }
+
+    /// Starts measuring the pass, ending the previous one.
+    pub fn begin(&mut self, pass: Pass) {
+        self.end();
+        let queries = &mut self.frames[self.current].passes[pass as usize];
+        queries.issued = true;
+        queries.samples.begin(gl::SAMPLES_PASSED);
+        if let Some(time) = &queries.time {
+            time.begin(gl::TIME_ELAPSED);
+        }
+        if !queries.labeled {
+            queries.labeled = true;
+            queries.samples.label(&format!("{} samples", pass.name()));
+            if let Some(time) = &queries.time {
+                time.label(&format!("{} time", pass.name()));
+            }
+        }
+        self.active = Some(pass);
+    }
+
+    /// Stops measuring the current pass.
+    pub fn end(&mut self) {
+        if let Some(pass) = self.active.take() {
+            gl::end_query(gl::SAMPLES_PASSED);
+            if self.frames[self.current].passes[pass as usize].time.is_some() {
+                gl::end_query(gl::TIME_ELAPSED);
+            }
+        }
+    }
+
+    /// Returns the averages of each pass.
+    pub fn stats(&self) -> Vec<PassStats> {
+        PASSES.iter()
+            .zip(&self.history)
+            .map(|(pass, history)| {
+                let frames = history.len();
+                let times: Option<u64> = history.iter().map(|v| v.0).sum();
+                let samples: u64 = history.iter().map(|v| v.1).sum();
+                PassStats {
+                    pass: *pass,
+                    time: times.filter(|_| frames > 0).map(|v| Duration::from_nanos(v / frames as u64)),
+                    samples: samples.checked_div(frames as u64).unwrap_or(0),
+                    frames,
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the number of frames whose results were never read
+    /// because the gpu fell too far behind.
+    pub fn dropped_frames(&self) -> usize {
+        self.dropped
+    }
+}
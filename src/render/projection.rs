@@ -0,0 +1,125 @@
+//! The projection from the camera's view onto the screen.
+//!
+//! With `reverse_z` the near plane is mapped to a depth of 1 and
+//! infinitely far away to 0, and the depth buffer stores floats. Float
+//! precision is densest near 0 so this roughly evens out the precision
+//! the perspective divide concentrates near the camera, and nothing is
+//! clipped by a far plane. It needs `glClipControl`, without it
+//! (`Cap::ClipControl`) the normal depth range is used instead.
+
+use crate::gl;
+
+/// The field of view of a perspective projection, in degrees.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fov {
+    Vertical(f32),
+    /// Kept as the window is resized, the vertical field of view
+    /// follows the aspect ratio.
+    Horizontal(f32),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    Perspective(Fov),
+    /// A parallel projection showing `height` blocks vertically, for
+    /// debugging and top-down views.
+    Orthographic { height: f32 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Projection {
+    pub mode: Mode,
+    pub near: f32,
+    /// Unused by reverse-Z perspective projections, which have no far
+    /// plane.
+    pub far: f32,
+    pub reverse_z: bool,
+}
+
+impl Projection {
+    pub fn new() -> Projection {
+        Projection {
+            mode: Mode::Perspective(Fov::Vertical(90.0)),
+            near: 0.1,
+            far: 500.0,
+            reverse_z: false,
+        }
+    }
+
+    /// Whether reverse-Z is both requested and supported.
+    pub fn uses_reverse_z(&self) -> bool {
+        self.reverse_z && gl::caps::has(gl::caps::Cap::ClipControl)
+    }
+
+    /// The format of the depth buffers drawn with this projection.
+    pub fn depth_format(&self) -> gl::TextureFormat {
+        if self.uses_reverse_z() {
+            gl::DEPTH_COMPONENT32F
+        } else {
+            gl::DEPTH_COMPONENT24
+        }
+    }
+
+    /// The vertical field of view in radians for the passed aspect
+    /// ratio, `None` for orthographic projections.
+    pub fn fovy(&self, aspect: f32) -> Option<f32> {
+        match self.mode {
+            Mode::Perspective(Fov::Vertical(fov)) => Some(fov.to_radians()),
+            Mode::Perspective(Fov::Horizontal(fov)) => Some(2.0 * ((fov.to_radians() / 2.0).tan() / aspect).atan()),
+            Mode::Orthographic { .. } => None,
+        }
+    }
+
+    /// The projection matrix for the passed aspect ratio (width over
+    /// height).
+    pub fn matrix(&self, aspect: f32) -> cgmath::Matrix4<f32> {
+        let (near, far) = (self.near, self.far);
+        match (self.mode, self.uses_reverse_z()) {
+            (Mode::Perspective(_), false) => cgmath::Matrix4::from(
+                cgmath::PerspectiveFov {
+                    fovy: cgmath::Rad(self.fovy(aspect).unwrap()),
+                    aspect,
+                    near,
+                    far,
+                }
+            ),
+            (Mode::Perspective(_), true) => {
+                let f = 1.0 / (self.fovy(aspect).unwrap() / 2.0).tan();
+                cgmath::Matrix4::new(
+                    f / aspect, 0.0, 0.0, 0.0,
+                    0.0, f, 0.0, 0.0,
+                    0.0, 0.0, 0.0, -1.0,
+                    0.0, 0.0, near, 0.0,
+                )
+            },
+            (Mode::Orthographic { height }, reverse_z) => {
+                let (w, h) = (height * aspect / 2.0, height / 2.0);
+                let ortho = cgmath::ortho(-w, w, -h, h, near, far);
+                if reverse_z {
+                    let mut ortho = ortho;
+                    ortho.z.z = 1.0 / (far - near);
+                    ortho.w.z = far / (far - near);
+                    ortho
+                } else {
+                    ortho
+                }
+            },
+        }
+    }
+
+    /// Sets the clip control, depth test and depth clear value used
+    /// with this projection.
+    pub fn apply_depth(&self) {
+        if self.uses_reverse_z() {
+            gl::clip_control(gl::ZERO_TO_ONE);
+            gl::clear_depth(0.0);
+            gl::depth_func(gl::GREATER_OR_EQUAL);
+        } else {
+            if gl::caps::has(gl::caps::Cap::ClipControl) {
+                gl::clip_control(gl::NEGATIVE_ONE_TO_ONE);
+            }
+            gl::clear_depth(1.0);
+            gl::depth_func(gl::LESS_OR_EQUAL);
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use crate::gl;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// An RGBA8 image read back from the gpu. Rows are stored top-down.
+#[derive(Clone)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+impl Image {
+    /// Creates an image from pixels in gl's bottom-up row order.
+    pub fn from_gl(width: u32, height: u32, mut data: Vec<u8>) -> Image {
+        let stride = width as usize * 4;
+        let rows = height as usize;
+        for y in 0..rows / 2 {
+            let (top, bottom) = data.split_at_mut((rows - 1 - y) * stride);
+            top[y * stride..(y + 1) * stride].swap_with_slice(&mut bottom[..stride]);
+        }
+        Image {
+            width,
+            height,
+            data,
+        }
+    }
+
+    /// Returns the RGBA value of the pixel at x, y (from the top left).
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = (y * self.width + x) as usize * 4;
+        [self.data[i], self.data[i + 1], self.data[i + 2], self.data[i + 3]]
+    }
+
+    /// Writes the image as a binary PPM. Alpha is dropped.
+    pub fn write_ppm<W: Write>(&self, mut w: W) -> io::Result<()> {
+        write!(w, "P6\n{} {}\n255\n", self.width, self.height)?;
+        for px in self.data.chunks(4) {
+            w.write_all(&px[..3])?;
+        }
+        w.flush()
+    }
+
+    /// Writes the image as a PNG.
+    #[cfg(feature = "png")]
+    pub fn write_png<W: Write>(&self, w: W) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(w, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.data)?;
+        Ok(())
+    }
+
+    /// Saves the image to the passed path, picking the format from
+    /// the extension. Anything other than `.png` is saved as a PPM.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let is_png = path.extension().is_some_and(|v| v == "png");
+        if is_png && !cfg!(feature = "png") {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "png support not enabled"));
+        }
+        let file = BufWriter::new(File::create(path)?);
+        #[cfg(feature = "png")]
+        {
+            if is_png {
+                return self.write_png(file);
+            }
+        }
+        self.write_ppm(file)
+    }
+}
+
+/// Reads the passed region of the default framebuffer, e.g. the window
+/// after a frame has been drawn but before it is swapped.
+pub fn read_default_framebuffer(width: u32, height: u32) -> Image {
+    gl::unbind_framebuffer_read();
+    let mut data = vec![0; width as usize * height as usize * 4];
+    gl::read_pixels(0, 0, width, height, gl::RGBA, gl::UNSIGNED_BYTE, &mut data);
+    Image::from_gl(width, height, data)
+}
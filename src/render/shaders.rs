@@ -14,16 +14,26 @@
 
 use crate::render::glsl;
 use crate::gl;
+use std::error;
+use std::fmt;
 
 pub fn add_shaders(reg: &mut glsl::Registry) {
     reg.register("trans_vertex", include_str!("shaders/trans_vertex.glsl"));
     reg.register("trans_frag", include_str!("shaders/trans_frag.glsl"));
 
+    reg.register("camera_block", include_str!("shaders/camera_block.glsl"));
+
     reg.register("model_vertex", include_str!("shaders/model_vertex.glsl"));
     reg.register("model_frag", include_str!("shaders/model_frag.glsl"));
 
     reg.register("sun_vertex", include_str!("shaders/sun_vertex.glsl"));
     reg.register("sun_frag", include_str!("shaders/sun_frag.glsl"));
+
+    reg.register("instanced_vertex", include_str!("shaders/instanced_vertex.glsl"));
+    reg.register("instanced_frag", include_str!("shaders/instanced_frag.glsl"));
+
+    reg.register("wireframe_geom", include_str!("shaders/wireframe_geom.glsl"));
+    reg.register("wireframe_frag", include_str!("shaders/wireframe_frag.glsl"));
 }
 
 macro_rules! get_shader {
@@ -35,28 +45,46 @@ macro_rules! get_shader {
     )
 }
 
+macro_rules! get_geometry_shader {
+    ($reg:ident) => (
+        None::<String>
+    );
+    ($reg:ident, $name:expr $(, $def:expr)?) => (
+        Some(get_shader!($reg, $name $(, $def)?))
+    )
+}
+
 #[macro_export]
 macro_rules! init_shader {
     (
         Program $name:ident {
             vert = $vert:expr, $(#$vdef:ident)*
+            $(geom = $geom:expr, $(#$gdef:ident)*)?
             frag = $frag:expr, $(#$fdef:ident)*
             attribute = {
                 $(
-                    required $field:ident => $glname:expr,
+                    required $field:ident $(: $fty:ident)? => $glname:expr,
                 )*
                 $(
-                    optional $ofield:ident => $oglname:expr,
+                    optional $ofield:ident $(: $ofty:ident)? => $oglname:expr,
                 )*
             },
             uniform = {
                 $(
-                    required $ufield:ident => $uglname:expr,
+                    required $ufield:ident $(: $uty:ident)? => $uglname:expr,
                 )*
                 $(
-                    optional $oufield:ident => $ouglname:expr,
+                    optional $oufield:ident $(: $outy:ident)? => $ouglname:expr,
                 )*
             },
+            $(uniform_block = {
+                $(
+                    required $bfield:ident: $binding:expr => $bglname:expr,
+                )*
+                $(
+                    optional $obfield:ident: $obinding:expr => $obglname:expr,
+                )*
+            },)?
         }
     ) => (
         #[allow(dead_code)]
@@ -74,75 +102,239 @@ macro_rules! init_shader {
             $(
                 $oufield: Option<gl::Uniform>,
             )*
+            $($(
+                $bfield: gl::UniformBlock,
+            )*)?
+            $($(
+                $obfield: Option<gl::UniformBlock>,
+            )*)?
         }
 
         impl $name {
             #[allow(dead_code)]
-            pub fn new(reg: &glsl::Registry) -> $name {
+            pub fn new(reg: &glsl::Registry) -> Result<$name, shaders::ProgramError> {
                 let v = get_shader!(reg, $vert $(,stringify!($vdef))*);
+                let g = get_geometry_shader!(reg $(, $geom $(,stringify!($gdef))*)?);
                 let f = get_shader!(reg, $frag $(,stringify!($fdef))*);
-                $name::new_manual(&v, &f)
+                $name::new_manual(&v, g.as_deref(), &f)
             }
 
+            /// Creates the program from the passed sources instead of
+            /// the ones named in the declaration.
             #[allow(dead_code)]
-            pub fn new_manual(v: &str, f: &str) -> $name {
-                let shader = shaders::create_program(&v, &f);
-                $name {
+            pub fn new_manual(v: &str, g: Option<&str>, f: &str) -> Result<$name, shaders::ProgramError> {
+                let shader = shaders::create_program(v, g, f)?;
+                shader.label(stringify!($name));
+
+                let attributes = shader.active_attributes();
+                $(
+                    shaders::check_variable(&attributes, $glname, &[$(gl::$fty)?], true, false)?;
+                )*
+                $(
+                    shaders::check_variable(&attributes, $oglname, &[$(gl::$ofty)?], false, false)?;
+                )*
+                let uniforms = shader.active_uniforms();
+                $(
+                    shaders::check_variable(&uniforms, $uglname, &[$(gl::$uty)?], true, true)?;
+                )*
+                $(
+                    shaders::check_variable(&uniforms, $ouglname, &[$(gl::$outy)?], false, true)?;
+                )*
+
+                Ok($name {
                     $(
-                        $field: shader.attribute_location($glname).unwrap(),
+                        $field: shader.attribute_location($glname)
+                            .ok_or(shaders::ProgramError::MissingAttribute($glname))?,
                     )*
                     $(
                         $ofield: shader.attribute_location($oglname),
                     )*
                     $(
-                        $ufield: shader.uniform_location($uglname).unwrap(),
+                        $ufield: shader.uniform_location(&uniforms, $uglname)
+                            .ok_or(shaders::ProgramError::MissingUniform($uglname))?,
                     )*
                     $(
-                        $oufield: shader.uniform_location($ouglname),
+                        $oufield: shader.uniform_location(&uniforms, $ouglname),
                     )*
+                    $($(
+                        $bfield: {
+                            let block = shader.uniform_block($bglname)
+                                .ok_or(shaders::ProgramError::MissingUniformBlock($bglname))?;
+                            shader.bind_uniform_block(&block, $binding);
+                            block
+                        },
+                    )*)?
+                    $($(
+                        $obfield: shader.uniform_block($obglname).map(|block| {
+                            shader.bind_uniform_block(&block, $obinding);
+                            block
+                        }),
+                    )*)?
                     program: shader,
-                }
+                })
             }
         }
     )
 }
 
-pub fn create_program(vertex: &str, fragment: &str) -> gl::Program {
-    let program = gl::Program::new();
+/// `ProgramError` is a reason a shader program couldn't be created.
+#[derive(Debug)]
+pub enum ProgramError {
+    /// A shader stage failed to compile.
+    Compile {
+        stage: &'static str,
+        log: String,
+        source: String,
+    },
+    /// The stages compiled but couldn't be linked together.
+    Link {
+        log: String,
+    },
+    /// A required attribute isn't active in the linked program.
+    MissingAttribute(&'static str),
+    /// A required uniform isn't active in the linked program.
+    MissingUniform(&'static str),
+    /// A required uniform block isn't active in the linked program.
+    MissingUniformBlock(&'static str),
+    /// An attribute or uniform has a different type to the one
+    /// declared in `init_shader!`.
+    TypeMismatch {
+        name: &'static str,
+        expected: gl::VariableType,
+        actual: gl::VariableType,
+    },
+}
 
-    let v = gl::Shader::new(gl::VERTEX_SHADER);
-    v.set_source(vertex);
-    v.compile();
-
-    if v.get_parameter(gl::COMPILE_STATUS) == 0 {
-        println!("Src: {}", vertex);
-        panic!("Shader error: {}", v.get_info_log());
-    } else {
-        let log = v.get_info_log();
-        let log = log.trim().trim_matches('\u{0}');
-        if !log.is_empty() {
-            println!("{}", log);
+impl fmt::Display for ProgramError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ProgramError::Compile{stage, ref log, ref source} => {
+                writeln!(f, "failed to compile {} shader:", stage)?;
+                writeln!(f, "{}", log.trim_end())?;
+                write_annotated_source(f, source, &error_lines(log))
+            },
+            ProgramError::Link{ref log} => write!(f, "failed to link program:\n{}", log.trim_end()),
+            ProgramError::MissingAttribute(name) => write!(f, "required attribute {} is missing", name),
+            ProgramError::MissingUniform(name) => write!(f, "required uniform {} is missing", name),
+            ProgramError::MissingUniformBlock(name) => write!(f, "required uniform block {} is missing", name),
+            ProgramError::TypeMismatch{name, expected, actual} => write!(f,
+                "{} is declared as {} but the shader uses {}",
+                name, gl::variable_type_name(expected), gl::variable_type_name(actual),
+            ),
         }
     }
+}
+
+impl error::Error for ProgramError {}
 
-    let f = gl::Shader::new(gl::FRAGMENT_SHADER);
-    f.set_source(fragment);
-    f.compile();
-
-    if f.get_parameter(gl::COMPILE_STATUS) == 0 {
-        println!("Src: {}", fragment);
-        panic!("Shader error: {}", f.get_info_log());
-    } else {
-        let log = f.get_info_log();
-        let log = log.trim().trim_matches('\u{0}');
-        if !log.is_empty() {
-            println!("{}", log);
+/// Returns the source line numbers mentioned by the info log.
+/// Handles the common `0:12(3)` (Mesa, Intel, AMD) and `0(12)`
+/// (Nvidia) formats.
+fn error_lines(log: &str) -> Vec<usize> {
+    let mut lines = vec![];
+    for line in log.lines() {
+        let line = line.trim_start_matches("ERROR: ").trim_start_matches("WARNING: ");
+        let rest = match line.strip_prefix("0:").or_else(|| line.strip_prefix("0(")) {
+            Some(rest) => rest,
+            None => continue,
+        };
+        let num: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+        if let Ok(num) = num.parse() {
+            lines.push(num);
         }
     }
+    lines
+}
+
+/// Writes the source with line numbers, marking the lines that
+/// have errors.
+fn write_annotated_source(f: &mut fmt::Formatter, source: &str, errors: &[usize]) -> fmt::Result {
+    for (i, line) in source.lines().enumerate() {
+        let num = i + 1;
+        let marker = if errors.contains(&num) { ">>" } else { "  " };
+        writeln!(f, "{} {:4} | {}", marker, num, line)?;
+    }
+    Ok(())
+}
+
+/// Checks a variable declared by `init_shader!` against the active
+/// variables of the program. `expected` is empty when no type was
+/// declared.
+pub fn check_variable(
+    active: &[gl::ActiveVariable], name: &'static str, expected: &[gl::VariableType],
+    required: bool, uniform: bool,
+) -> Result<(), ProgramError> {
+    let var = match active.iter().find(|v| v.is_named(name)) {
+        Some(var) => var,
+        None if !required => return Ok(()),
+        None if uniform => return Err(ProgramError::MissingUniform(name)),
+        None => return Err(ProgramError::MissingAttribute(name)),
+    };
+    match expected.first() {
+        Some(&ty) if ty != var.ty => Err(ProgramError::TypeMismatch {
+            name,
+            expected: ty,
+            actual: var.ty,
+        }),
+        _ => Ok(()),
+    }
+}
+
+fn stage_name(ty: gl::ShaderType) -> &'static str {
+    match ty {
+        gl::VERTEX_SHADER => "vertex",
+        gl::FRAGMENT_SHADER => "fragment",
+        gl::GEOMETRY_SHADER => "geometry",
+        _ => "unknown",
+    }
+}
+
+/// Compiles a single shader stage, printing any warnings.
+pub fn compile_shader(ty: gl::ShaderType, src: &str) -> Result<gl::Shader, ProgramError> {
+    let shader = gl::Shader::new(ty);
+    shader.set_source(src);
+    shader.compile();
+
+    let log = shader.get_info_log();
+    let log = log.trim().trim_matches('\u{0}');
+    if shader.get_parameter(gl::COMPILE_STATUS) == 0 {
+        return Err(ProgramError::Compile {
+            stage: stage_name(ty),
+            log: log.to_owned(),
+            source: src.to_owned(),
+        });
+    }
+    if !log.is_empty() {
+        println!("{}", log);
+    }
+    Ok(shader)
+}
+
+/// Compiles and links a program, the geometry stage is optional.
+pub fn create_program(vertex: &str, geometry: Option<&str>, fragment: &str) -> Result<gl::Program, ProgramError> {
+    let program = gl::Program::new();
+
+    let v = compile_shader(gl::VERTEX_SHADER, vertex)?;
+    let g = geometry.map(|g| compile_shader(gl::GEOMETRY_SHADER, g)).transpose()?;
+    let f = compile_shader(gl::FRAGMENT_SHADER, fragment)?;
 
     program.attach_shader(v);
+    if let Some(g) = g {
+        program.attach_shader(g);
+    }
     program.attach_shader(f);
     program.link();
+
+    let log = program.get_info_log();
+    let log = log.trim();
+    if !program.is_linked() {
+        return Err(ProgramError::Link {
+            log: log.to_owned(),
+        });
+    }
+    if !log.is_empty() {
+        println!("{}", log);
+    }
     program.use_program();
-    program
+    Ok(program)
 }
@@ -0,0 +1,4 @@
+layout(std140) uniform CameraMatrices {
+	mat4 perspectiveMatrix;
+	mat4 cameraMatrix;
+};
@@ -0,0 +1,7 @@
+in vec4 vColor;
+
+out vec4 fragColor;
+
+void main() {
+	fragColor = vColor;
+}
@@ -0,0 +1,13 @@
+in vec3 aPosition;
+in mat4 aInstanceMatrix;
+in vec4 aInstanceColor;
+
+#include camera_block
+
+out vec4 vColor;
+
+void main() {
+	vec3 pos = vec3(aPosition.x, -aPosition.y, aPosition.z);
+	gl_Position = perspectiveMatrix * cameraMatrix * aInstanceMatrix * vec4(pos, 1.0);
+	vColor = aInstanceColor;
+}
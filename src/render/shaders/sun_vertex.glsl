@@ -1,7 +1,6 @@
 in vec3 aPosition;
 
-uniform mat4 perspectiveMatrix;
-uniform mat4 cameraMatrix;
+#include camera_block
 uniform mat4 modelMatrix[10];
 
 void main() {
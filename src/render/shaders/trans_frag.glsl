@@ -1,7 +1,22 @@
+#ifdef RESOLVED
+uniform sampler2D tcolor;
+#else
 uniform sampler2DMS tcolor;
+uniform int samples;
+#endif
 
 out vec4 fragColor;
 
 void main() {
-    fragColor = texelFetch(tcolor, ivec2(gl_FragCoord.xy), 0);
+    ivec2 pos = ivec2(gl_FragCoord.xy);
+#ifdef RESOLVED
+    fragColor = texelFetch(tcolor, pos, 0);
+#else
+    // Averaged like a resolve blit would
+    vec4 color = vec4(0.0);
+    for (int i = 0; i < samples; i++) {
+        color += texelFetch(tcolor, pos, i);
+    }
+    fragColor = color / float(samples);
+#endif
 }
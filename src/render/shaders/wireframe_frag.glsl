@@ -0,0 +1,12 @@
+noperspective in vec3 vEdge;
+
+out vec4 fragColor;
+
+void main() {
+	// Distance to the closest edge in pixels
+	vec3 d = vEdge / fwidth(vEdge);
+	if (min(d.x, min(d.y, d.z)) > 1.0) {
+		discard;
+	}
+	fragColor = vec4(1.0, 0.0, 1.0, 1.0);
+}
@@ -0,0 +1,15 @@
+layout(triangles) in;
+layout(triangle_strip, max_vertices = 3) out;
+
+// Barycentric coordinates, 0 along the opposite edge
+noperspective out vec3 vEdge;
+
+void main() {
+	for (int i = 0; i < 3; i++) {
+		gl_Position = gl_in[i].gl_Position;
+		vEdge = vec3(0.0);
+		vEdge[i] = 1.0;
+		EmitVertex();
+	}
+	EndPrimitive();
+}
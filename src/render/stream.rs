@@ -0,0 +1,106 @@
+//! A buffer for data that is rewritten every frame, currently the
+//! instance data of instanced models.
+//!
+//! The buffer is split into `SECTIONS` sections used in turn, one per
+//! frame. Each frame writes into its section with an unsynchronized
+//! mapping so gl never waits for earlier draws still reading the
+//! buffer. Instead a fence is placed after the frame's commands and
+//! waited on before the section is reused, by then the gpu has
+//! normally long finished with it.
+
+use crate::gl;
+use std::time::Duration;
+
+/// The number of frames that can be written before the oldest
+/// section is reused.
+const SECTIONS: usize = 3;
+/// Writes start at a multiple of this, enough for vertex attributes
+/// and uniform buffer offsets on common drivers.
+const ALIGN: usize = 256;
+
+pub struct StreamBuffer {
+    buffer: gl::Buffer,
+    target: gl::BufferTarget,
+    /// The size of each section in bytes.
+    section_size: usize,
+    current: usize,
+    /// Where the next write goes in the current section.
+    offset: usize,
+    fences: [Option<gl::Fence>; SECTIONS],
+}
+
+impl StreamBuffer {
+    /// Creates a buffer with `size` bytes available each frame, this
+    /// grows if a frame writes more.
+    pub fn new(target: gl::BufferTarget, label: &str, size: usize) -> StreamBuffer {
+        let buffer = gl::Buffer::new();
+        buffer.bind(target);
+        buffer.label(label);
+        let mut stream = StreamBuffer {
+            buffer,
+            target,
+            section_size: 0,
+            current: 0,
+            offset: 0,
+            fences: Default::default(),
+        };
+        stream.allocate(size);
+        stream
+    }
+
+    fn allocate(&mut self, size: usize) {
+        self.section_size = size.next_multiple_of(ALIGN);
+        self.buffer.bind(self.target);
+        // Orphans the old storage, gl keeps it until the gpu is done
+        self.buffer.set_data(self.target, &vec![0; self.section_size * SECTIONS], gl::STREAM_DRAW);
+        self.fences = Default::default();
+        self.offset = 0;
+    }
+
+    /// Moves on to the next section, waiting for the gpu to finish
+    /// with it if needed. Called at the start of each frame.
+    pub fn begin_frame(&mut self) {
+        self.current = (self.current + 1) % SECTIONS;
+        self.offset = 0;
+        if let Some(fence) = self.fences[self.current].take() {
+            loop {
+                match fence.wait(Duration::from_millis(100)) {
+                    Ok(true) => break,
+                    Ok(false) => {},
+                    // Nothing more can be done, the section is reused
+                    Err(err) => {
+                        println!("Waiting for the stream buffer failed: {}", err);
+                        break;
+                    },
+                }
+            }
+        }
+    }
+
+    /// Fences the current section. Called once the frame's draws using
+    /// the buffer have been issued.
+    pub fn end_frame(&mut self) {
+        self.fences[self.current] = Some(gl::Fence::new());
+    }
+
+    /// Copies the data into the current section, returning its offset
+    /// in the buffer. The data must be used by draws issued this frame
+    /// and the buffer is left bound.
+    pub fn write(&mut self, data: &[u8]) -> usize {
+        if self.offset + data.len() > self.section_size {
+            // Earlier writes this frame stay in the orphaned storage
+            let size = (self.section_size * 2).max(data.len());
+            self.allocate(size);
+        }
+        let offset = self.current * self.section_size + self.offset;
+        self.offset = (self.offset + data.len()).next_multiple_of(ALIGN);
+
+        self.buffer.bind(self.target);
+        let mut mapped = self.buffer.map_range(
+            self.target, offset, data.len(),
+            gl::MapFlags::Write | gl::MapFlags::InvalidateRange | gl::MapFlags::Unsynchronized,
+        );
+        mapped.extend_from_slice(data);
+        offset
+    }
+}
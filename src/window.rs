@@ -0,0 +1,42 @@
+//! Creation of the sdl window and its OpenGL context.
+
+use crate::gl;
+use sdl2::video::{GLContext, Window, WindowBuilder};
+
+/// Creates a resizable window with an OpenGL 3.2 core context,
+/// makes the context current and inits gl with it. On high-DPI
+/// displays the drawable size (see `Window::drawable_size`) can be
+/// larger than the passed size.
+///
+/// `STEVEN_GL_DEBUG` creates a debug context and prints its messages,
+/// `STEVEN_GL_CHECK` enables `gl::set_debug_checks`.
+pub fn create(video: &sdl2::VideoSubsystem, title: &str, width: u32, height: u32) -> (Window, GLContext) {
+    let window = WindowBuilder::new(video, title, width, height)
+                            .opengl()
+                            .resizable()
+                            .allow_highdpi()
+                            .build()
+                            .expect("Could not create sdl window.");
+    let gl_attr = video.gl_attr();
+    gl_attr.set_depth_size(24);
+    gl_attr.set_context_major_version(3);
+    gl_attr.set_context_minor_version(2);
+    gl_attr.set_context_profile(sdl2::video::GLProfile::Core);
+    let debug = std::env::var_os("STEVEN_GL_DEBUG").is_some();
+    if debug {
+        gl_attr.set_context_flags().debug().set();
+    }
+
+    let gl_context = window.gl_create_context().unwrap();
+    window.gl_make_current(&gl_context).expect("Could not set current context.");
+
+    gl::init(video);
+    if std::env::var_os("STEVEN_GL_CHECK").is_some() {
+        gl::set_debug_checks(true);
+    }
+    if debug && !gl::set_debug_callback(gl::print_debug_message) {
+        println!("KHR_debug/ARB_debug_output not supported, no debug output");
+    }
+
+    (window, gl_context)
+}
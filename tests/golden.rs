@@ -0,0 +1,370 @@
+//! Golden image regression tests.
+//!
+//! Each scene is rendered headlessly and compared against the reference
+//! image in `tests/golden/<name>.png`. On a mismatch the rendered image
+//! and a diff (mismatching pixels in red) are written next to the test
+//! binary's temporary directory.
+//!
+//! To add a scene, write a function that creates its models and add a
+//! `golden_test!` line for it. Run with `UPDATE_GOLDEN=1` to (re)create
+//! the reference images.
+//!
+//! A scene can also be rendered with driver quirks forced on or
+//! optional gl capabilities disabled, checking the workarounds and
+//! fallbacks still produce the same image.
+
+use invisible::gl::caps::{self, Cap};
+use invisible::gl::quirks::{self, Quirk};
+use invisible::headless;
+use invisible::render::{self, model, projection, screenshot::{self, Image}};
+use invisible::sun;
+use cgmath::{Matrix4, Vector3};
+use std::env;
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+const WIDTH: u32 = 854;
+const HEIGHT: u32 = 480;
+
+/// Largest difference allowed in any channel of a pixel.
+const TOLERANCE: u8 = 8;
+/// Fraction of the pixels allowed to be outside of the tolerance,
+/// covers differences in how drivers rasterize/resolve edges.
+const MAX_MISMATCHED: f64 = 0.002;
+
+const SKY: [u8; 3] = [122, 165, 247];
+const SUN: [u8; 3] = [255, 255, 255];
+const WIREFRAME: [u8; 3] = [255, 0, 255];
+
+// Only one headless context should exist at a time.
+static CONTEXT_LOCK: Mutex<()> = Mutex::new(());
+
+struct Scene {
+    name: &'static str,
+    /// The name of the reference image.
+    reference: &'static str,
+    /// Quirks to force on whilst rendering.
+    quirks: &'static [Quirk],
+    /// Capabilities to disable whilst rendering.
+    disabled_caps: &'static [Cap],
+    /// Creates the models for the scene.
+    build: fn(&mut render::Renderer),
+    /// Reads the rendered image after a frame.
+    capture: fn(&render::Renderer) -> Image,
+    /// Scene specific sanity checks on the rendered image, these run
+    /// even when updating the reference images.
+    check: fn(&Image) -> Result<(), String>,
+}
+
+macro_rules! golden_test {
+    ($name:ident, $build:expr, $check:expr) => (
+        golden_test!($name => $name, $build, $check, quirks: &[]);
+    );
+    ($name:ident => $reference:ident, $build:expr, $check:expr, quirks: $quirks:expr) => (
+        golden_test!($name => $reference, $build, $check, quirks: $quirks, disabled_caps: &[]);
+    );
+    ($name:ident => $reference:ident, $build:expr, $check:expr, disabled_caps: $caps:expr) => (
+        golden_test!($name => $reference, $build, $check, quirks: &[], disabled_caps: $caps);
+    );
+    ($name:ident => $reference:ident, $build:expr, $check:expr, quirks: $quirks:expr, disabled_caps: $caps:expr) => (
+        golden_test!($name => $reference, $build, $check, quirks: $quirks, disabled_caps: $caps,
+            capture: render::Renderer::screenshot);
+    );
+    ($name:ident => $reference:ident, $build:expr, $check:expr, capture: $capture:expr) => (
+        golden_test!($name => $reference, $build, $check, quirks: &[], disabled_caps: &[], capture: $capture);
+    );
+    ($name:ident => $reference:ident, $build:expr, $check:expr, quirks: $quirks:expr, disabled_caps: $caps:expr,
+            capture: $capture:expr) => (
+        #[test]
+        fn $name() {
+            run(&Scene {
+                name: stringify!($name),
+                reference: stringify!($reference),
+                quirks: $quirks,
+                disabled_caps: $caps,
+                build: $build,
+                capture: $capture,
+                check: $check,
+            });
+        }
+    );
+}
+
+golden_test!(sun, build_sun, check_sun);
+golden_test!(sun_with_quirks => sun, build_sun, check_sun, quirks: &quirks::ALL);
+golden_test!(sun_without_caps => sun, build_sun, check_sun, disabled_caps: &caps::ALL);
+golden_test!(sun_single_sample => sun, build_sun_single_sample, check_sun, quirks: &[]);
+golden_test!(sun_presented => sun, build_sun, check_sun, capture: read_presented);
+golden_test!(sun_single_sample_presented => sun, build_sun_single_sample, check_sun, capture: read_presented);
+golden_test!(sun_wireframe, build_sun_wireframe, check_sun_wireframe);
+golden_test!(instanced, build_instanced, check_instanced);
+golden_test!(instanced_with_quirks => instanced, build_instanced, check_instanced, quirks: &quirks::ALL);
+golden_test!(instanced_without_caps => instanced, build_instanced, check_instanced, disabled_caps: &caps::ALL);
+golden_test!(depth, build_depth, check_depth);
+golden_test!(depth_reverse_z => depth, build_depth_reverse_z, check_depth, quirks: &[]);
+golden_test!(depth_reverse_z_with_quirks => depth, build_depth_reverse_z, check_depth, quirks: &quirks::ALL);
+golden_test!(depth_reverse_z_without_caps => depth, build_depth_reverse_z, check_depth, disabled_caps: &caps::ALL);
+golden_test!(sun_orthographic, build_sun_orthographic, check_sun);
+
+fn build_sun(renderer: &mut render::Renderer) {
+    let mut sun = sun::SunModel::new(renderer);
+    sun.tick(renderer);
+}
+
+fn build_sun_single_sample(renderer: &mut render::Renderer) {
+    assert_eq!(renderer.set_samples(1), 1);
+    build_sun(renderer);
+}
+
+/// Reads the frame the trans pass resolved onto the default
+/// framebuffer, rather than the main framebuffer it was drawn into.
+fn read_presented(_renderer: &render::Renderer) -> Image {
+    screenshot::read_default_framebuffer(WIDTH, HEIGHT)
+}
+
+fn build_sun_orthographic(renderer: &mut render::Renderer) {
+    build_sun(renderer);
+    renderer.set_projection(projection::Projection {
+        mode: projection::Mode::Orthographic { height: 300.0 },
+        ..projection::Projection::new()
+    });
+}
+
+fn build_sun_wireframe(renderer: &mut render::Renderer) {
+    build_sun(renderer);
+    renderer.model.wireframe = true;
+}
+
+fn check_sun_wireframe(img: &Image) -> Result<(), String> {
+    check_sun(img)?;
+    let edges = count_color(img, WIREFRAME);
+    if edges < 100 {
+        return Err(format!("expected the sun's edges to be drawn, only {} edge pixels", edges));
+    }
+    Ok(())
+}
+
+// steven#25: the sun wasn't drawn, only the sky was visible.
+fn check_sun(img: &Image) -> Result<(), String> {
+    let sun = count_color(img, SUN);
+    if sun < 1000 {
+        return Err(format!("expected the sun to be visible, only {} sun pixels", sun));
+    }
+    let sky = count_color(img, SKY);
+    if sky < 1000 {
+        return Err(format!("expected the sky to be visible, only {} sky pixels", sky));
+    }
+    Ok(())
+}
+
+/// Colours of the instances, one per quad around the ring.
+const RING: [[u8; 3]; 4] = [[255, 0, 0], [0, 255, 0], [0, 0, 255], [255, 255, 0]];
+
+fn build_instanced(renderer: &mut render::Renderer) {
+    let key = renderer.model.create_instanced_model(
+        model::INSTANCED,
+        vec![
+            model::Vertex{x: 0.0, y: -10.0, z: -10.0},
+            model::Vertex{x: 0.0, y: 10.0, z: -10.0},
+            model::Vertex{x: 0.0, y: -10.0, z: 10.0},
+            model::Vertex{x: 0.0, y: 10.0, z: 10.0},
+        ],
+    );
+    let model = renderer.model.get_instanced_model(key).unwrap();
+    model.instances = (0..RING.len() * 2)
+        .map(|i| {
+            let angle = i as f32 * std::f32::consts::TAU / (RING.len() * 2) as f32;
+            let [r, g, b] = RING[i % RING.len()];
+            model::Instance {
+                matrix: Matrix4::from_translation(Vector3::new(-300.0, -13.0 + 40.0 * angle.sin(), 0.5 + 40.0 * angle.cos())),
+                color: [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, 1.0],
+            }
+        })
+        .collect();
+}
+
+// Every instance must be drawn with its own colour, not just the first.
+fn check_instanced(img: &Image) -> Result<(), String> {
+    for color in &RING {
+        let count = count_color(img, *color);
+        if count < 100 {
+            return Err(format!("expected the {:?} instances to be visible, only {} pixels", color, count));
+        }
+    }
+    Ok(())
+}
+
+const BEHIND: [u8; 3] = [255, 0, 0];
+
+/// A large red quad behind the sun, drawn after it.
+fn build_depth(renderer: &mut render::Renderer) {
+    build_sun(renderer);
+    let key = renderer.model.create_instanced_model(
+        model::INSTANCED,
+        vec![
+            model::Vertex{x: 0.0, y: -10.0, z: -10.0},
+            model::Vertex{x: 0.0, y: 10.0, z: -10.0},
+            model::Vertex{x: 0.0, y: -10.0, z: 10.0},
+            model::Vertex{x: 0.0, y: 10.0, z: 10.0},
+        ],
+    );
+    let model = renderer.model.get_instanced_model(key).unwrap();
+    model.instances = vec![model::Instance {
+        matrix: Matrix4::from_translation(Vector3::new(-400.0, -13.0, 0.5)) * Matrix4::from_scale(12.0),
+        color: [1.0, 0.0, 0.0, 1.0],
+    }];
+}
+
+fn build_depth_reverse_z(renderer: &mut render::Renderer) {
+    build_depth(renderer);
+    renderer.set_projection(projection::Projection {
+        reverse_z: true,
+        ..projection::Projection::new()
+    });
+}
+
+// The sun must hide the quad behind it despite being drawn first.
+fn check_depth(img: &Image) -> Result<(), String> {
+    check_sun(img)?;
+    let behind = count_color(img, BEHIND);
+    if behind < 1000 {
+        return Err(format!("expected the quad behind the sun to be visible around it, only {} pixels", behind));
+    }
+    Ok(())
+}
+
+fn count_color(img: &Image, color: [u8; 3]) -> usize {
+    img.data.chunks(4)
+        .filter(|px| px[..3] == color)
+        .count()
+}
+
+fn run(scene: &Scene) {
+    let img = render_scene(scene);
+    let out_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    fs::create_dir_all(&out_dir).unwrap();
+
+    if let Err(err) = (scene.check)(&img) {
+        let actual = out_dir.join(format!("{}-actual.png", scene.name));
+        img.save(&actual).unwrap();
+        panic!("{}: {} (rendered image: {})", scene.name, err, actual.display());
+    }
+
+    let reference = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(format!("{}.png", scene.reference));
+    if env::var_os("UPDATE_GOLDEN").is_some() && scene.reference == scene.name {
+        img.save(&reference).unwrap();
+        return;
+    }
+    let expected = load_png(&reference)
+        .unwrap_or_else(|| panic!("{}: missing reference image {}, run with UPDATE_GOLDEN=1", scene.name, reference.display()));
+
+    if let Err((err, diff)) = compare(&expected, &img) {
+        let actual = out_dir.join(format!("{}-actual.png", scene.name));
+        let diff_path = out_dir.join(format!("{}-diff.png", scene.name));
+        img.save(&actual).unwrap();
+        if let Some(diff) = diff {
+            diff.save(&diff_path).unwrap();
+        }
+        panic!("{}: {} (rendered image: {}, diff: {})",
+            scene.name, err, actual.display(), diff_path.display());
+    }
+}
+
+/// Stops forcing the quirks when dropped, even if the test panics.
+struct ForcedQuirks(&'static [Quirk]);
+
+impl ForcedQuirks {
+    fn new(quirks: &'static [Quirk]) -> ForcedQuirks {
+        quirks.iter().for_each(|q| quirks::force(*q));
+        ForcedQuirks(quirks)
+    }
+}
+
+impl Drop for ForcedQuirks {
+    fn drop(&mut self) {
+        self.0.iter().for_each(|q| quirks::unforce(*q));
+    }
+}
+
+/// Reenables the capabilities when dropped, even if the test panics.
+struct DisabledCaps(&'static [Cap]);
+
+impl DisabledCaps {
+    fn new(caps: &'static [Cap]) -> DisabledCaps {
+        caps.iter().for_each(|c| caps::disable(*c));
+        DisabledCaps(caps)
+    }
+}
+
+impl Drop for DisabledCaps {
+    fn drop(&mut self) {
+        self.0.iter().for_each(|c| caps::reenable(*c));
+    }
+}
+
+fn render_scene(scene: &Scene) -> Image {
+    let _lock = CONTEXT_LOCK.lock().unwrap_or_else(|v| v.into_inner());
+    let _quirks = ForcedQuirks::new(scene.quirks);
+    let _caps = DisabledCaps::new(scene.disabled_caps);
+    let _context = headless::Context::new(WIDTH, HEIGHT).expect("failed to create headless context");
+
+    let mut renderer = render::Renderer::new(WIDTH, HEIGHT).unwrap_or_else(|err| panic!("{}", err));
+    (scene.build)(&mut renderer);
+    renderer.update_camera();
+    renderer.tick();
+    (scene.capture)(&renderer)
+}
+
+/// Compares the two images, returning a diff image on failure if they
+/// are the same size.
+fn compare(expected: &Image, actual: &Image) -> Result<(), (String, Option<Image>)> {
+    if expected.width != actual.width || expected.height != actual.height {
+        return Err((format!("size mismatch, expected {}x{} got {}x{}",
+            expected.width, expected.height, actual.width, actual.height), None));
+    }
+
+    let mut diff = Image {
+        width: actual.width,
+        height: actual.height,
+        data: Vec::with_capacity(actual.data.len()),
+    };
+    let mut mismatched = 0;
+    for (e, a) in expected.data.chunks(4).zip(actual.data.chunks(4)) {
+        let worst = e.iter().zip(a)
+            .map(|(e, a)| (i16::from(*e) - i16::from(*a)).unsigned_abs())
+            .max()
+            .unwrap();
+        if worst > u16::from(TOLERANCE) {
+            mismatched += 1;
+            diff.data.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            // Faded copy of the image so the mismatches stand out
+            diff.data.extend(a[..3].iter().map(|v| v / 4));
+            diff.data.push(255);
+        }
+    }
+
+    let total = (actual.width * actual.height) as usize;
+    if mismatched as f64 > total as f64 * MAX_MISMATCHED {
+        return Err((format!("{} of {} pixels differ from the reference", mismatched, total), Some(diff)));
+    }
+    Ok(())
+}
+
+fn load_png(path: &PathBuf) -> Option<Image> {
+    let decoder = png::Decoder::new(File::open(path).ok()?);
+    let mut reader = decoder.read_info().unwrap();
+    let mut data = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut data).unwrap();
+    assert_eq!(info.color_type, png::ColorType::Rgba, "reference images must be RGBA");
+    data.truncate(info.buffer_size());
+    Some(Image {
+        width: info.width,
+        height: info.height,
+        data,
+    })
+}
@@ -0,0 +1,771 @@
+//! Tests of the renderer's gl usage, run against the mock gl
+//! implementation so no context is needed.
+
+use invisible::gl::{self, mock};
+use invisible::render::{self, model, shaders};
+use invisible::sun;
+use cgmath::{Matrix4, Vector3};
+use std::time::Duration;
+
+fn renderer() -> render::Renderer {
+    mock::init();
+    render::Renderer::new(854, 480).unwrap_or_else(|err| panic!("{}", err))
+}
+
+fn labeled(id: i64, label: &str) -> bool {
+    mock::label(id as u32).as_deref() == Some(label)
+}
+
+#[test]
+fn manager_draws_models_from_shared_array() {
+    let mut renderer = renderer();
+    let mut suns = vec![sun::SunModel::new(&mut renderer), sun::SunModel::new(&mut renderer)];
+    for sun in &mut suns {
+        sun.tick(&mut renderer);
+    }
+    renderer.update_camera();
+
+    mock::clear_calls();
+    renderer.tick();
+
+    let mut drawn = vec![];
+    let mut arrays = vec![];
+    let mut model_matrix = false;
+    for call in mock::calls() {
+        match call.func {
+            "glBindVertexArray" if labeled(call.args[0], "model") => arrays.push(call.args[0]),
+            "glUniformMatrix4fv" if call.uniform.as_deref() == Some("modelMatrix[0]") => model_matrix = true,
+            "glDrawElements" => panic!("model drawn without a base vertex"),
+            "glDrawElementsBaseVertex" => {
+                assert!(!arrays.is_empty(), "draw without a model vertex array bound");
+                assert!(model_matrix, "modelMatrix not set before drawing");
+                model_matrix = false;
+                drawn.push(call.args[4]);
+            },
+            _ => {},
+        }
+    }
+    assert_eq!(arrays.len(), 1, "expected the collection's vertex array to be bound once");
+    drawn.sort_unstable();
+    drawn.dedup();
+    assert_eq!(drawn.len(), 2, "expected both models to be drawn from their own vertices");
+}
+
+fn base_vertices(renderer: &mut render::Renderer) -> Vec<i64> {
+    mock::clear_calls();
+    renderer.tick();
+    let mut bases: Vec<_> = mock::calls().into_iter()
+        .filter(|v| v.func == "glDrawElementsBaseVertex")
+        .map(|v| v.args[4])
+        .collect();
+    bases.sort_unstable();
+    bases
+}
+
+#[test]
+fn removed_model_space_is_reused() {
+    let mut renderer = renderer();
+    // The quads are off screen
+    renderer.model.culling = false;
+    renderer.update_camera();
+    let quad = |x| vec![vec![model::Vertex{x, y: 0.0, z: 0.0}; 4]];
+    let first = renderer.model.create_model(model::SUN, quad(1.0));
+    renderer.model.create_model(model::SUN, quad(2.0));
+    assert_eq!(base_vertices(&mut renderer), [0, 4]);
+
+    renderer.model.remove_model(first);
+    assert_eq!(base_vertices(&mut renderer), [4]);
+
+    mock::clear_calls();
+    renderer.model.create_model(model::SUN, quad(3.0));
+    assert!(!mock::calls().iter().any(|v| v.func == "glBufferData" && v.args[0] as u32 == gl::ARRAY_BUFFER.raw()),
+        "vertex buffer reallocated despite free space");
+    assert_eq!(base_vertices(&mut renderer), [0, 4]);
+
+    // Too large for the free space, grows the buffer keeping the others
+    renderer.model.create_model(model::SUN, vec![vec![model::Vertex{x: 0.0, y: 0.0, z: 0.0}; 2048]]);
+    assert_eq!(base_vertices(&mut renderer), [0, 4, 8]);
+    let data = mock::live_objects().into_iter()
+        .filter(|v| labeled(*v as i64, "model vertices"))
+        .find_map(|v| mock::buffer_data(v).filter(|v| !v.is_empty()))
+        .expect("no model vertices uploaded");
+    let x = |vertex: usize| {
+        let v = &data[vertex * 36..];
+        f32::from_ne_bytes([v[0], v[1], v[2], v[3]])
+    };
+    assert_eq!((x(0), x(4)), (3.0, 2.0), "vertices lost when growing");
+}
+
+#[test]
+fn updated_model_reuploaded() {
+    let mut renderer = renderer();
+    renderer.model.culling = false;
+    renderer.update_camera();
+    let quad = |x| vec![model::Vertex{x, y: 0.0, z: 0.0}; 4];
+    let first = renderer.model.create_model(model::SUN, vec![quad(1.0)]);
+    renderer.model.create_model(model::SUN, vec![quad(2.0)]);
+
+    // Too large for the free space, the arena grows while it moves
+    renderer.model.update_model(first, vec![quad(5.0); 512]);
+    assert_eq!(renderer.model.get_model(first).unwrap().verts().len(), 2048);
+    let drawn: Vec<_> = {
+        mock::clear_calls();
+        renderer.tick();
+        mock::calls().into_iter()
+            .filter(|v| v.func == "glDrawElementsBaseVertex")
+            .map(|v| (v.args[1], v.args[4]))
+            .collect()
+    };
+    assert!(drawn.contains(&(6, 4)), "unchanged model moved");
+    let &(count, base) = drawn.iter().find(|v| v.1 != 4).expect("updated model not drawn");
+    assert_eq!(count, 512 * 6, "count not updated");
+
+    let data = mock::live_objects().into_iter()
+        .filter(|v| labeled(*v as i64, "model vertices"))
+        .find_map(|v| mock::buffer_data(v).filter(|v| !v.is_empty()))
+        .expect("no model vertices uploaded");
+    let x = |vertex: usize| {
+        let v = &data[vertex * 36..];
+        f32::from_ne_bytes([v[0], v[1], v[2], v[3]])
+    };
+    assert_eq!(x(4), 2.0, "vertices lost when growing");
+    assert!((0..2048).all(|v| x(base as usize + v) == 5.0), "updated vertices not uploaded");
+}
+
+#[test]
+fn trans_draws_scene_to_default_framebuffer() {
+    let mut renderer = renderer();
+    renderer.update_camera();
+    renderer.tick();
+
+    let calls = mock::calls();
+    let last_draw = calls.iter().rposition(|v| v.func == "glDrawArrays").expect("no trans draw");
+    assert_eq!(mock::bound_framebuffers(), (0, 0));
+    assert!(labeled(mock::bound_vertex_array() as i64, "trans quad"));
+    assert!(labeled(mock::bound_program() as i64, "TransShader"));
+
+    let color = mock::bound_texture(0, gl::TEXTURE_2D_MULTISAMPLE);
+    assert!(labeled(color as i64, "main color"), "trans pass isn't sampling the scene");
+    let tcolor = calls[..last_draw].iter()
+        .rev()
+        .find(|v| v.uniform.as_deref() == Some("tcolor"))
+        .expect("tcolor not set");
+    assert_eq!(tcolor.args[1], 0, "tcolor should use texture unit 0");
+}
+
+#[test]
+fn renderer_frees_objects() {
+    let mut renderer = renderer();
+    sun::SunModel::new(&mut renderer);
+    renderer.update_camera();
+    renderer.tick();
+    drop(renderer);
+
+    // Shaders are owned by the driver once attached to a program
+    let shaders: Vec<u32> = mock::calls().iter()
+        .filter(|v| v.func == "glCreateShader")
+        .map(|v| v.args[1] as u32)
+        .collect();
+    let leaked: Vec<_> = mock::live_objects().into_iter()
+        .filter(|v| !shaders.contains(v))
+        .map(|v| (v, mock::label(v)))
+        .collect();
+    assert!(leaked.is_empty(), "leaked gl objects: {:?}", leaked);
+}
+
+#[test]
+fn active_uniforms_queried_once_per_program() {
+    renderer();
+    let mut queried: Vec<_> = mock::calls().into_iter()
+        .filter(|v| v.func == "glGetActiveUniform")
+        .map(|v| (v.args[0], v.args[1]))
+        .collect();
+    assert!(!queried.is_empty(), "no uniforms queried");
+    let total = queried.len();
+    queried.sort_unstable();
+    queried.dedup();
+    assert_eq!(queried.len(), total, "uniforms queried again for each lookup");
+}
+
+#[test]
+fn shader_compile_errors_are_annotated() {
+    mock::init();
+    let src = "#version 150\nvoid main() {\n#error broken\n}\n";
+    match shaders::compile_shader(gl::FRAGMENT_SHADER, src) {
+        Err(err @ shaders::ProgramError::Compile { .. }) => {
+            let msg = err.to_string();
+            assert!(msg.contains("broken"), "log missing from:\n{}", msg);
+            assert!(msg.contains(">>    3"), "error line not marked in:\n{}", msg);
+        },
+        Err(err) => panic!("unexpected error: {}", err),
+        Ok(_) => panic!("shader with #error compiled"),
+    }
+}
+
+const READ_FRAMEBUFFER: u32 = 0x8CA8;
+const DRAW_FRAMEBUFFER: u32 = 0x8CA9;
+
+const STATE_CALLS: &[&str] = &[
+    "glUseProgram", "glBindVertexArray", "glBindBuffer", "glActiveTexture",
+    "glBindTexture", "glBindFramebuffer", "glEnable", "glDisable",
+    "glBindBufferBase",
+];
+
+fn state_calls() -> u64 {
+    mock::calls().iter().filter(|v| STATE_CALLS.contains(&v.func)).count() as u64
+}
+
+#[test]
+fn redundant_binds_are_skipped() {
+    let mut renderer = renderer();
+    let mut sun = sun::SunModel::new(&mut renderer);
+    sun.tick(&mut renderer);
+    renderer.update_camera();
+    renderer.tick();
+
+    mock::clear_calls();
+    gl::take_state_stats();
+    renderer.tick();
+    let stats = gl::take_state_stats();
+    assert!(stats.skipped > 0, "nothing skipped: {:?}", stats);
+    assert_eq!(state_calls(), stats.issued);
+
+    // None of the binds should set what is already bound
+    let mut bound = std::collections::HashMap::new();
+    for call in mock::calls() {
+        let (keys, value): (&[&str], _) = match (call.func, call.args.first().map(|v| *v as u32)) {
+            ("glUseProgram", _) => (&["program"], call.args[0]),
+            ("glBindVertexArray", _) => (&["vertex array"], call.args[0]),
+            ("glActiveTexture", _) => (&["active texture"], call.args[0]),
+            ("glBindFramebuffer", Some(READ_FRAMEBUFFER)) => (&["read"], call.args[1]),
+            ("glBindFramebuffer", Some(DRAW_FRAMEBUFFER)) => (&["draw"], call.args[1]),
+            ("glBindFramebuffer", _) => (&["read", "draw"], call.args[1]),
+            _ => continue,
+        };
+        let mut changed = false;
+        for key in keys {
+            changed |= bound.insert(*key, value) != Some(value);
+        }
+        assert!(changed, "redundant {:?}", call);
+    }
+
+    // Without the cache everything is issued but still counted
+    gl::set_state_cache(false);
+    mock::clear_calls();
+    renderer.tick();
+    gl::set_state_cache(true);
+    assert_eq!(gl::take_state_stats(), stats);
+    assert_eq!(state_calls(), stats.issued + stats.skipped);
+}
+
+#[test]
+fn element_buffer_forgotten_with_bound_vertex_array() {
+    mock::init();
+    let buffer = gl::Buffer::new();
+    let array = gl::VertexArray::new();
+    array.bind();
+    buffer.bind(gl::ELEMENT_ARRAY_BUFFER);
+    drop(array);
+
+    // Vertex array 0 is bound now, without an element buffer
+    mock::clear_calls();
+    buffer.bind(gl::ELEMENT_ARRAY_BUFFER);
+    let bind = mock::calls().into_iter()
+        .find(|v| v.func == "glBindBuffer")
+        .expect("element buffer bind skipped");
+    assert_eq!(mock::bound_buffer(gl::ELEMENT_ARRAY_BUFFER), bind.args[1] as u32);
+}
+
+#[test]
+fn camera_block_uploaded_once_per_frame() {
+    let mut renderer = renderer();
+    let mut suns = vec![sun::SunModel::new(&mut renderer), sun::SunModel::new(&mut renderer)];
+    for sun in &mut suns {
+        sun.tick(&mut renderer);
+    }
+    renderer.update_camera();
+
+    mock::clear_calls();
+    renderer.tick();
+
+    let calls = mock::calls();
+    let uploads: Vec<_> = calls.iter()
+        .filter(|v| v.func == "glBufferData" && v.args[0] as u32 == gl::UNIFORM_BUFFER.raw())
+        .collect();
+    assert_eq!(uploads.len(), 1, "camera should be uploaded once per frame");
+    assert!(!calls.iter().any(|v| matches!(v.uniform.as_deref(), Some("perspectiveMatrix" | "cameraMatrix"))),
+        "camera matrices set as plain uniforms");
+
+    let camera = mock::bound_indexed_buffer(gl::UNIFORM_BUFFER, render::CAMERA_BLOCK_BINDING);
+    assert!(labeled(camera as i64, "camera"), "camera buffer not bound to its binding");
+    let data: Vec<f32> = mock::buffer_data(camera)
+        .expect("camera buffer has no data")
+        .chunks(4)
+        .map(|v| f32::from_ne_bytes([v[0], v[1], v[2], v[3]]))
+        .collect();
+    assert_eq!(data.len(), 32, "expected two std140 mat4s");
+    assert_eq!(data[11], -1.0, "perspectiveMatrix should come first");
+    assert_eq!(data[31], 1.0);
+
+    let bindings: Vec<_> = mock::live_objects().into_iter()
+        .filter_map(|v| mock::block_binding(v, "CameraMatrices"))
+        .collect();
+    assert!(!bindings.is_empty(), "no program uses the camera block");
+    assert!(bindings.iter().all(|v| *v == render::CAMERA_BLOCK_BINDING));
+}
+
+fn instanced_quad(renderer: &mut render::Renderer) -> model::InstancedKey {
+    renderer.model.create_instanced_model(
+        model::INSTANCED,
+        vec![
+            model::Vertex{x: 0.0, y: -1.0, z: -1.0},
+            model::Vertex{x: 0.0, y: 1.0, z: -1.0},
+            model::Vertex{x: 0.0, y: -1.0, z: 1.0},
+            model::Vertex{x: 0.0, y: 1.0, z: 1.0},
+        ],
+    )
+}
+
+#[test]
+fn instanced_model_drawn_in_one_call() {
+    let mut renderer = renderer();
+    let key = instanced_quad(&mut renderer);
+    let divisors = mock::calls().iter()
+        .filter(|v| v.func == "glVertexAttribDivisor" && v.args[1] == 1)
+        .count();
+    assert_eq!(divisors, 5, "expected the 4 matrix columns and the colour to advance per instance");
+
+    renderer.model.get_instanced_model(key).unwrap().instances = (0..3)
+        .map(|i| model::Instance {
+            matrix: Matrix4::from_translation(Vector3::new(i as f32, 0.0, 0.0)),
+            color: [1.0, 0.0, 0.0, 1.0],
+        })
+        .collect();
+    renderer.update_camera();
+
+    mock::clear_calls();
+    renderer.tick();
+
+    let draws: Vec<_> = mock::calls().into_iter()
+        .filter(|v| v.func == "glDrawElementsInstanced")
+        .collect();
+    assert_eq!(draws.len(), 1, "expected a single draw for all instances");
+    assert_eq!(draws[0].args.last(), Some(&3));
+}
+
+#[test]
+fn wireframe_drawn_with_geometry_shader() {
+    let mut renderer = renderer();
+    let calls = mock::calls();
+    let geometry: Vec<_> = calls.iter()
+        .filter(|v| v.func == "glCreateShader" && v.args[0] as u32 == gl::GEOMETRY_SHADER.raw())
+        .map(|v| v.args[1])
+        .collect();
+    assert!(!geometry.is_empty(), "no geometry shader compiled");
+    let wireframe: Vec<_> = calls.iter()
+        .filter(|v| v.func == "glAttachShader" && geometry.contains(&v.args[1]))
+        .map(|v| v.args[0])
+        .collect();
+    assert!(wireframe.iter().all(|v| labeled(*v, "WireframeShader")));
+
+    let mut sun = sun::SunModel::new(&mut renderer);
+    sun.tick(&mut renderer);
+    renderer.update_camera();
+    renderer.model.wireframe = true;
+
+    mock::clear_calls();
+    renderer.tick();
+
+    let mut program = 0;
+    let mut draws = (0, 0);
+    for call in mock::calls() {
+        match call.func {
+            "glUseProgram" => program = call.args[0],
+            "glDrawElementsBaseVertex" if wireframe.contains(&program) => draws.1 += 1,
+            "glDrawElementsBaseVertex" => draws.0 += 1,
+            _ => {},
+        }
+    }
+    assert_eq!(draws, (1, 1), "expected the sun to be drawn then its wireframe");
+}
+
+#[test]
+fn profiler_averages_passes() {
+    let mut renderer = renderer();
+    let mut sun = sun::SunModel::new(&mut renderer);
+    sun.tick(&mut renderer);
+    renderer.update_camera();
+    for _ in 0..3 {
+        renderer.tick();
+    }
+
+    // The last frame's results are collected at the start of the next
+    let stats = renderer.profiler.stats();
+    let names: Vec<_> = stats.iter().map(|v| v.pass.name()).collect();
+    assert_eq!(names, ["model", "translucent", "resolve"]);
+    for pass in &stats {
+        assert_eq!(pass.frames, 2, "{} results not collected", pass.pass.name());
+        assert_eq!(pass.time, Some(Duration::from_millis(1)));
+    }
+    assert_eq!(stats[0].samples, 1, "expected the sun's draw to be counted");
+    assert_eq!(stats[1].samples, 0);
+    assert_eq!(renderer.profiler.dropped_frames(), 0);
+}
+
+#[test]
+fn instances_streamed_without_stalls() {
+    let mut renderer = renderer();
+    let key = instanced_quad(&mut renderer);
+    renderer.model.get_instanced_model(key).unwrap().instances = vec![model::Instance {
+        matrix: Matrix4::from_translation(Vector3::new(0.0, 0.0, 0.0)),
+        color: [1.0, 0.0, 0.0, 1.0],
+    }];
+    renderer.update_camera();
+    // Fill every section of the stream buffer once
+    for _ in 0..3 {
+        renderer.tick();
+    }
+
+    // GL_MAP_UNSYNCHRONIZED_BIT
+    let unsynchronized = 0x20;
+    let mut offsets = vec![];
+    for frame in 0..4 {
+        mock::clear_calls();
+        renderer.tick();
+        let calls = mock::calls();
+        assert!(!calls.iter().any(|v| v.func == "glBufferData" && v.args[0] as u32 == gl::ARRAY_BUFFER.raw()),
+            "vertex data reallocated in frame {}", frame);
+        let maps: Vec<_> = calls.iter().filter(|v| v.func == "glMapBufferRange").collect();
+        assert_eq!(maps.len(), 1);
+        assert_ne!(maps[0].args[3] & unsynchronized, 0, "mapping waits for the gpu");
+        offsets.push(maps[0].args[1]);
+        assert_eq!(calls.iter().filter(|v| v.func == "glFenceSync").count(), 1);
+        assert!(calls.iter().any(|v| v.func == "glClientWaitSync"), "section reused without waiting on its fence");
+    }
+    assert_eq!(offsets[0], offsets[3], "expected the sections to be used in turn");
+    assert!(offsets[0] != offsets[1] && offsets[1] != offsets[2] && offsets[0] != offsets[2]);
+
+    // A failed wait, e.g. a lost context, must not wait forever
+    mock::fail_waits();
+    mock::clear_calls();
+    renderer.tick();
+    assert_eq!(mock::calls().iter().filter(|v| v.func == "glClientWaitSync").count(), 1);
+}
+
+#[test]
+fn framebuffers_recreated_only_on_resize() {
+    let mut renderer = renderer();
+    let framebuffers = || mock::calls().iter().filter(|v| v.func == "glGenFramebuffers").count();
+
+    mock::clear_calls();
+    for _ in 0..3 {
+        renderer.resize(854, 480);
+        renderer.update_camera();
+        renderer.tick();
+    }
+    assert_eq!(framebuffers(), 0, "framebuffers recreated without a resize");
+
+    renderer.resize(0, 0);
+    assert_eq!(framebuffers(), 0, "framebuffers recreated for an empty window");
+
+    renderer.resize(1280, 720);
+    renderer.update_camera();
+    renderer.tick();
+    assert!(framebuffers() > 0, "framebuffers not recreated");
+    let viewport = mock::calls().into_iter()
+        .rfind(|v| v.func == "glViewport")
+        .expect("viewport not set");
+    assert_eq!(viewport.args, [0, 0, 1280, 720]);
+
+    let camera = mock::bound_indexed_buffer(gl::UNIFORM_BUFFER, render::CAMERA_BLOCK_BINDING);
+    let data = mock::buffer_data(camera).expect("camera buffer has no data");
+    let x_scale = f32::from_ne_bytes([data[0], data[1], data[2], data[3]]);
+    let y_scale = f32::from_ne_bytes([data[20], data[21], data[22], data[23]]);
+    assert!((y_scale / x_scale - 1280.0 / 720.0).abs() < 1e-4, "projection doesn't match the new aspect");
+}
+
+#[test]
+fn renderer_created_for_empty_window() {
+    mock::init();
+    let mut renderer = render::Renderer::new(0, 0).unwrap_or_else(|err| panic!("{}", err));
+    renderer.update_camera();
+    renderer.tick();
+    renderer.set_samples(1);
+    let projection = render::projection::Projection { reverse_z: true, ..*renderer.projection() };
+    renderer.set_projection(projection);
+    let image = renderer.screenshot();
+    assert_eq!((image.width, image.height), (1, 1));
+
+    mock::clear_calls();
+    renderer.resize(854, 480);
+    renderer.update_camera();
+    renderer.tick();
+    assert!(mock::calls().iter().any(|v| v.func == "glGenFramebuffers"), "framebuffers not recreated");
+}
+
+#[test]
+fn samples_clamped_and_applied() {
+    let mut renderer = renderer();
+    assert_eq!(renderer.samples(), 2);
+
+    // The mock supports up to 4 samples
+    assert_eq!(renderer.set_samples(16), 4);
+    assert_eq!(renderer.set_samples(3), 2);
+    assert_eq!(renderer.set_samples(0), 1);
+
+    mock::clear_calls();
+    renderer.set_samples(4);
+    renderer.update_camera();
+    renderer.tick();
+    let calls = mock::calls();
+    let multisample = calls.iter()
+        .find(|v| v.func == "glTexImage2DMultisample")
+        .expect("main color not multisampled");
+    assert_eq!(multisample.args[1], 4);
+    assert!(calls.iter().any(|v| v.uniform.as_deref() == Some("samples") && v.args.last() == Some(&4)),
+        "resolve shader not told the sample count");
+
+    mock::clear_calls();
+    renderer.set_samples(1);
+    renderer.tick();
+    let calls = mock::calls();
+    assert!(!calls.iter().any(|v| matches!(v.func, "glTexImage2DMultisample" | "glRenderbufferStorageMultisample")),
+        "multisampled storage with 1 sample");
+    assert!(calls.iter().any(|v| v.func == "glTexImage2D"), "main color not recreated");
+
+    // The default is clamped too
+    drop(renderer);
+    mock::init();
+    mock::set_max_samples(1);
+    let renderer = render::Renderer::new(854, 480).unwrap_or_else(|err| panic!("{}", err));
+    assert_eq!(renderer.samples(), 1);
+    assert!(!mock::calls().iter().any(|v| matches!(v.func, "glTexImage2DMultisample" | "glRenderbufferStorageMultisample")),
+        "multisampled storage without multisampling support");
+}
+
+#[test]
+fn camera_moved_and_uploaded() {
+    use cgmath::prelude::*;
+    let mut renderer = renderer();
+    let start = renderer.camera.position;
+    let view = renderer.camera.view_vector().cast::<f64>().unwrap();
+
+    renderer.camera.walk(1.0, 0.0, 0.0, Duration::from_millis(500));
+    let moved = renderer.camera.position - start;
+    assert!((moved - view * renderer.camera.speed * 0.5).magnitude() < 1e-6, "didn't move along the view vector");
+
+    // Looking far down stops short of straight down
+    renderer.camera.look(0.0, 1e6);
+    assert!(renderer.camera.pitch > std::f64::consts::PI / 2.0);
+    assert!(renderer.camera.view_vector().y.abs() < 1.0);
+
+    renderer.update_camera();
+    renderer.tick();
+    let camera = mock::bound_indexed_buffer(gl::UNIFORM_BUFFER, render::CAMERA_BLOCK_BINDING);
+    let data: Vec<f32> = mock::buffer_data(camera)
+        .expect("camera buffer has no data")
+        .chunks(4)
+        .map(|v| f32::from_ne_bytes([v[0], v[1], v[2], v[3]]))
+        .collect();
+    let matrix = renderer.camera.matrix();
+    let matrix: &[f32; 16] = matrix.as_ref();
+    assert_eq!(&data[16..], &matrix[..], "camera matrix not uploaded");
+}
+
+#[test]
+fn reverse_z_uses_float_depth() {
+    use render::projection::{Fov, Mode, Projection};
+    let mut renderer = renderer();
+    renderer.update_camera();
+    renderer.tick();
+
+    // Only the field of view changing keeps the framebuffers
+    mock::clear_calls();
+    renderer.set_projection(Projection {
+        mode: Mode::Perspective(Fov::Horizontal(100.0)),
+        ..Projection::new()
+    });
+    assert!(!mock::calls().iter().any(|v| v.func.starts_with("glRenderbufferStorage")),
+        "framebuffers recreated without the depth format changing");
+    let fovy = renderer.projection().fovy(2.0).unwrap();
+    assert!((fovy - 2.0 * (50f32.to_radians().tan() / 2.0).atan()).abs() < 1e-6);
+
+    mock::clear_calls();
+    renderer.set_projection(Projection {
+        reverse_z: true,
+        ..Projection::new()
+    });
+    renderer.update_camera();
+    renderer.tick();
+    let calls = mock::calls();
+    let depth_formats: Vec<_> = calls.iter()
+        .filter_map(|v| match v.func {
+            "glRenderbufferStorage" => Some(v.args[1]),
+            "glRenderbufferStorageMultisample" => Some(v.args[2]),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(depth_formats.len(), 2);
+    assert!(depth_formats.iter().all(|v| *v as u32 == gl::DEPTH_COMPONENT32F.raw()), "depth isn't stored as floats");
+    assert!(calls.iter().any(|v| v.func == "glClipControl" && v.args[1] as u32 == gl::ZERO_TO_ONE.raw()));
+    assert!(calls.iter().any(|v| v.func == "glDepthFunc" && v.args[0] as u32 == gl::GREATER_OR_EQUAL.raw()));
+    assert!(calls.iter().any(|v| v.func == "glClearDepth" && v.floats == [0.0]));
+
+    // The near plane is at depth 1, infinitely far away at 0
+    let projection = renderer.projection().matrix(1.0);
+    let depth = |z: f32| {
+        let v = projection * cgmath::Vector4::new(0.0, 0.0, z, 1.0);
+        v.z / v.w
+    };
+    assert!((depth(-0.1) - 1.0).abs() < 1e-6);
+    assert!(depth(-1e6).abs() < 1e-6);
+}
+
+#[test]
+fn models_outside_frustum_culled() {
+    let mut renderer = renderer();
+    let mut suns = vec![sun::SunModel::new(&mut renderer), sun::SunModel::new(&mut renderer)];
+    for sun in &mut suns {
+        sun.tick(&mut renderer);
+    }
+    // Behind the camera
+    let behind = sun::SunModel::generate_moon(&mut renderer);
+    renderer.model.get_model(behind).unwrap().matrix[0] = Matrix4::from_translation(Vector3::new(100.0, -13.2, 0.5));
+    let bounds = renderer.model.get_model(behind).unwrap().bounds().unwrap();
+    assert_eq!((bounds.min.x, bounds.max.x), (100.0, 150.0));
+    // Drawn with y flipped
+    assert_eq!((bounds.min.y, bounds.max.y), (-63.2, 86.8));
+
+    renderer.update_camera();
+    let base_vertices = base_vertices(&mut renderer);
+    assert_eq!(base_vertices.len(), 2, "expected only the visible models to be drawn");
+    assert_eq!(renderer.model.cull_stats(), model::CullStats { drawn: 2, culled: 1 });
+
+    // Turned around only the other model is visible
+    renderer.camera.yaw += std::f64::consts::PI;
+    renderer.update_camera();
+    renderer.tick();
+    assert_eq!(renderer.model.cull_stats(), model::CullStats { drawn: 1, culled: 2 });
+
+    renderer.model.culling = false;
+    renderer.tick();
+    assert_eq!(renderer.model.cull_stats(), model::CullStats { drawn: 3, culled: 0 });
+}
+
+#[test]
+fn model_at_top_edge_not_culled() {
+    use cgmath::prelude::*;
+    let mut renderer = renderer();
+    renderer.update_camera();
+
+    // Just inside the top edge of the screen
+    let aspect = 854.0 / 480.0;
+    let view_projection = renderer.projection().matrix(aspect) * renderer.camera.matrix();
+    let edge = view_projection.invert().unwrap() * cgmath::Vector4::new(0.0, 0.99, 0.99, 1.0);
+    let edge = edge.truncate() / edge.w;
+
+    // Only drawn below its origin, so on screen across the edge. Without
+    // the flip its box would be wholly above the edge
+    let key = renderer.model.create_model(model::SUN, vec![vec![
+        model::Vertex{x: 0.0, y: -4.0, z: -1.0},
+        model::Vertex{x: 0.0, y: 0.0, z: -1.0},
+        model::Vertex{x: 0.0, y: -4.0, z: 1.0},
+        model::Vertex{x: 0.0, y: 0.0, z: 1.0},
+    ]]);
+    renderer.model.get_model(key).unwrap().matrix[0] = Matrix4::from_translation(edge - Vector3::new(0.0, 2.0, 0.0));
+
+    renderer.tick();
+    assert_eq!(renderer.model.cull_stats(), model::CullStats { drawn: 1, culled: 0 });
+}
+
+/// A trace being recorded, readable once recording stops.
+#[derive(Clone, Default)]
+struct SharedTrace(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+impl std::io::Write for SharedTrace {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The calls a trace should reproduce, reads, labels and fences
+/// aren't traced.
+fn traced_calls() -> Vec<mock::Call> {
+    mock::calls().into_iter()
+        .filter(|v| !v.func.starts_with("glGet") && !v.func.ends_with("Sync") && v.func != "glObjectLabel")
+        .collect()
+}
+
+#[test]
+fn trace_replays_frame() {
+    mock::init();
+    gl::viewport(0, 0, 854, 480);
+    let trace = SharedTrace::default();
+    gl::trace::start(trace.clone()).unwrap();
+    mock::clear_calls();
+    let mut renderer = render::Renderer::new(854, 480).unwrap();
+    let mut sun = sun::SunModel::new(&mut renderer);
+    sun.tick(&mut renderer);
+    instanced_quad(&mut renderer);
+    renderer.update_camera();
+    renderer.tick();
+    gl::trace::frame();
+    gl::trace::stop().unwrap();
+    let recorded = traced_calls();
+    drop(renderer);
+
+    // Ids and attribute locations in the new context are offset
+    mock::init();
+    let offset = 100;
+    mock::set_attribute_base(offset as i32);
+    let _taken: Vec<_> = (0..offset).map(|_| gl::Buffer::new()).collect();
+    mock::clear_calls();
+    let data = trace.0.lock().unwrap().clone();
+    let mut replayer = gl::trace::Replayer::new(std::io::Cursor::new(data)).unwrap();
+    assert_eq!(replayer.size(), (854, 480));
+    while replayer.next_frame().unwrap() {}
+    let replayed = traced_calls();
+
+    assert_eq!(recorded.iter().map(|v| v.func).collect::<Vec<_>>(), replayed.iter().map(|v| v.func).collect::<Vec<_>>());
+    let mut remapped = std::collections::HashSet::new();
+    for (rec, rep) in recorded.iter().zip(&replayed) {
+        assert_eq!(rec.floats, rep.floats, "{} replayed with different values", rec.func);
+        assert_eq!(rec.uniform, rep.uniform, "{} replayed on a different uniform", rec.func);
+        for (a, b) in rec.args.iter().zip(&rep.args) {
+            assert!(a == b || *b == a + offset, "{} replayed with {:?}, recorded {:?}", rec.func, rep.args, rec.args);
+        }
+        if rec.func.contains("VertexAttrib") {
+            assert_eq!(rep.args[0], rec.args[0] + offset, "{} attribute not remapped", rec.func);
+        }
+        let id = match rec.func {
+            "glBindBuffer" | "glBeginQuery" | "glBindBufferBase" => rec.args.get(1),
+            "glUseProgram" => rec.args.first(),
+            _ => None,
+        };
+        if let Some(&id) = id.filter(|v| **v != 0) {
+            let index = rec.args.iter().position(|v| *v == id).unwrap();
+            assert_eq!(rep.args[index], id + offset, "{} id not remapped", rec.func);
+            remapped.insert(rec.func);
+        }
+    }
+    for func in &["glBindBuffer", "glBeginQuery", "glUseProgram"] {
+        assert!(remapped.contains(func), "no {} to check the remapping of", func);
+    }
+}
+
+#[test]
+fn profiler_queries_labeled_once_begun() {
+    let mut renderer = renderer();
+    renderer.update_camera();
+    renderer.tick();
+    let labels: Vec<_> = mock::live_objects().into_iter().filter_map(mock::label).collect();
+    for name in &["model samples", "model time", "resolve samples"] {
+        assert!(labels.iter().any(|v| v == name), "query {} not labeled", name);
+    }
+    assert_eq!(gl::get_error(), None);
+}